@@ -7,10 +7,14 @@ use std::{
     sync::Arc,
 };
 
-use io_http::rfc9110::request::HttpRequest;
 use io_oauth::rfc6749::{
-    access_token_request::*, auth_request::Oauth20AuthRequestParams,
-    auth_response::Oauth20AuthParams, state::Oauth20State,
+    access_token_request::*,
+    auth_request::Oauth20AuthRequestParams,
+    auth_response::Oauth20AuthParams,
+    endpoint::{parse_endpoint, post_request},
+    redirect_uri::Oauth20RedirectUri,
+    scope::parse_scope,
+    state::Oauth20State,
 };
 use rustls::{ClientConfig, ClientConnection, StreamOwned};
 use rustls_platform_verifier::ConfigVerifierExt;
@@ -25,9 +29,9 @@ fn main() {
         Err(_) => read_line("Client ID?"),
     };
 
-    let redirect_uri = match env::var("REDIRECT_URI") {
-        Ok(uri) => uri,
-        Err(_) => read_line("Redirect URI?"),
+    let redirect_uri: Oauth20RedirectUri = match env::var("REDIRECT_URI") {
+        Ok(uri) => uri.parse().unwrap(),
+        Err(_) => read_line("Redirect URI?").parse().unwrap(),
     };
 
     let scope = match env::var("SCOPE") {
@@ -35,14 +39,14 @@ fn main() {
         Err(_) => read_line("Scope?"),
     };
 
-    let auth_uri: Url = match env::var("AUTHORIZATION_URI") {
-        Ok(url) => url.parse().unwrap(),
-        Err(_) => read_line("Authorization URL?").parse().unwrap(),
+    let auth_uri = match env::var("AUTHORIZATION_URI") {
+        Ok(url) => parse_endpoint(&url).unwrap(),
+        Err(_) => parse_endpoint(&read_line("Authorization URL?")).unwrap(),
     };
 
-    let token_uri: Url = match env::var("TOKEN_URI") {
-        Ok(url) => url.parse().unwrap(),
-        Err(_) => read_line("Token URL?").parse().unwrap(),
+    let token_uri = match env::var("TOKEN_URI") {
+        Ok(url) => parse_endpoint(&url).unwrap(),
+        Err(_) => parse_endpoint(&read_line("Token URL?")).unwrap(),
     };
 
     let mut stream = connect(&token_uri);
@@ -51,10 +55,12 @@ fn main() {
     // browses to
 
     let state = Oauth20State::default();
+    #[cfg_attr(feature = "oauth21", allow(deprecated))]
     let auth_uri = Oauth20AuthRequestParams {
         client_id: client_id.as_str().into(),
-        redirect_uri: Some(redirect_uri.clone().into()),
-        scope: scope.split_whitespace().map(Into::into).collect(),
+        redirect_uri: Some(redirect_uri.clone()),
+        scope: parse_scope(&scope).unwrap(),
+        scope_delimiter: Default::default(),
         state: Some(Cow::Borrowed(&state)),
         pkce_code_challenge: None,
         extras: BTreeMap::new(),
@@ -83,19 +89,11 @@ fn main() {
 
     // NOTE: step 3, access token request: send the request
 
-    let host = token_uri.host_str().unwrap();
-    let port = token_uri.port_or_known_default().unwrap();
-    let request = HttpRequest {
-        method: "POST".into(),
-        url: token_uri.clone(),
-        headers: Vec::new(),
-        body: Vec::new(),
-    }
-    .header("Host", format!("{host}:{port}"));
+    let request = post_request(&token_uri);
 
     let params = Oauth20AccessTokenRequestParams {
         code: response_params.code,
-        redirect_uri: Some(redirect_uri.into()),
+        redirect_uri: Some(redirect_uri),
         client_id: client_id.into(),
         client_secret: None,
         pkce_code_verifier: None,
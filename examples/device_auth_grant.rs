@@ -3,7 +3,7 @@ use std::{
     io::{Write, stdin, stdout},
 };
 
-use io_oauth::{client::Oauth20ClientStd, rfc8628::auth::*};
+use io_oauth::{client::Oauth20ClientStd, rfc6749::scope::parse_scope, rfc8628::auth::*};
 use pimalaya_stream::tls::Tls;
 use secrecy::ExposeSecret;
 use url::Url;
@@ -39,7 +39,8 @@ fn main() {
 
     let params = Oauth20DeviceAuthRequestParams {
         client_id: client_id.as_str().into(),
-        scope: scope.split_whitespace().map(Into::into).collect(),
+        scope: parse_scope(&scope).unwrap(),
+        scope_delimiter: Default::default(),
     };
 
     let device = match client
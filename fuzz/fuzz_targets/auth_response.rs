@@ -0,0 +1,19 @@
+//! Parses arbitrary authorization redirections.
+
+#![no_main]
+
+use io_oauth::rfc6749::auth_response::Oauth20AuthParams;
+use libfuzzer_sys::fuzz_target;
+use url::Url;
+
+fuzz_target!(|data: &[u8]| {
+    let Ok(url) = core::str::from_utf8(data) else {
+        return;
+    };
+
+    let Ok(url) = Url::parse(url) else {
+        return;
+    };
+
+    let _ = Oauth20AuthParams::from(&url).validate(None);
+});
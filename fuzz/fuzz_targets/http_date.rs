@@ -0,0 +1,18 @@
+//! Parses arbitrary `Date` and `Retry-After` header values, and RFC 3339
+//! timestamps.
+
+#![no_main]
+
+use io_oauth::rfc6749::issue_access_token::{parse_http_date, parse_retry_after, parse_rfc3339};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let Ok(value) = core::str::from_utf8(data) else {
+        return;
+    };
+
+    let _ = parse_http_date(value);
+    let _ = parse_rfc3339(value);
+    let _ = parse_retry_after(value, Some(value));
+    let _ = parse_retry_after(value, Some("Sun, 06 Nov 1994 08:49:37 GMT"));
+});
@@ -0,0 +1,19 @@
+//! Parses arbitrary token endpoint JSON bodies.
+
+#![no_main]
+
+use io_oauth::rfc6749::issue_access_token::{
+    Oauth20AccessTokenErrorParams, Oauth20AccessTokenSuccessParams,
+};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(params) = Oauth20AccessTokenSuccessParams::try_from(data) {
+        let _ = params.scope_tokens();
+        let _ = params.is_expired(0);
+    }
+
+    if let Ok(params) = Oauth20AccessTokenErrorParams::try_from(data) {
+        let _ = params.to_string();
+    }
+});
@@ -0,0 +1,40 @@
+//! Parses arbitrary token endpoint responses, the first byte picking
+//! the status and the content coding.
+
+#![no_main]
+
+use core::time::Duration;
+
+use io_http::rfc9110::{response::HttpResponse, status::HttpStatusCode};
+use io_oauth::rfc6749::issue_access_token::parse_access_token_response;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let Some((&flags, body)) = data.split_first() else {
+        return;
+    };
+
+    let status = if flags & 1 == 0 { 200 } else { 400 };
+    let mut headers = vec![("date".into(), "Sun, 06 Nov 1994 08:49:37 GMT".into())];
+
+    if flags & 2 != 0 {
+        headers.push(("content-encoding".into(), "gzip".into()));
+    }
+
+    if flags & 4 != 0 {
+        headers.push(("content-encoding".into(), "deflate".into()));
+    }
+
+    let response = HttpResponse {
+        status: HttpStatusCode(status),
+        version: "HTTP/1.1".into(),
+        headers,
+        body: body.to_vec(),
+    };
+
+    if let Ok(Ok(params)) = parse_access_token_response(&response) {
+        let _ = params.is_expired(u64::MAX);
+        let _ = params.needs_refresh(Duration::MAX, u64::MAX);
+        let _ = params.refresh_at(Duration::MAX);
+    }
+});
@@ -12,31 +12,30 @@ use alloc::{
     boxed::Box,
     format,
     string::{String, ToString},
-    vec::Vec,
+    sync::Arc,
 };
+use core::time::Duration;
 
-use std::{
-    io::{self, BufRead, BufReader, Read, Write},
-    net::{Shutdown, TcpListener},
-};
 #[cfg(any(
     feature = "rustls-aws",
     feature = "rustls-ring",
     feature = "native-tls"
 ))]
+use std::thread;
 use std::{
-    thread,
-    time::{Duration, Instant},
+    io::{self, BufRead, BufReader, Read, Write},
+    net::{Shutdown, TcpListener},
+    time::Instant,
 };
 
-use io_http::{rfc7617::basic::HttpAuthBasic, rfc9110::request::HttpRequest};
+use io_http::rfc9110::request::HttpRequest;
 #[cfg(any(
     feature = "rustls-aws",
     feature = "rustls-ring",
     feature = "native-tls"
 ))]
 use pimalaya_stream::{std::stream::StreamStd, tls::Tls};
-use secrecy::{ExposeSecret, SecretString};
+use secrecy::SecretString;
 use thiserror::Error;
 use url::Url;
 
@@ -45,13 +44,14 @@ use url::Url;
     feature = "rustls-ring",
     feature = "native-tls"
 ))]
-use crate::{
-    rfc6749::issue_access_token::Oauth20AccessTokenErrorCode,
-    rfc8628::auth::Oauth20DeviceAuthSuccessParams,
-};
+use crate::rfc8628::auth::Oauth20DeviceAuthSuccessParams;
 use crate::{
     rfc6749::{
-        access_token_request::*, client_credentials::*, issue_access_token::*,
+        access_token_request::*,
+        client_auth::Oauth20ClientAuthMethod,
+        client_credentials::*,
+        endpoint::{Oauth20EndpointError, check_endpoint, post_request},
+        issue_access_token::*,
         refresh_access_token::*,
     },
     rfc7591::register::*,
@@ -68,19 +68,22 @@ pub enum Oauth20ClientStdError {
     Io(#[from] io::Error),
     /// The authorization code exchange failed.
     #[error(transparent)]
-    AccessTokenRequest(#[from] Oauth20AccessTokenRequestError),
+    AccessTokenRequest(#[from] Oauth20RequestError<Oauth20AccessTokenRequestError>),
+    /// The token endpoint answered with error params.
+    #[error("OAuth 2.0 token endpoint error: {0}")]
+    AccessTokenErrorResponse(#[from] Oauth20AccessTokenErrorParams),
     /// The token refresh failed.
     #[error(transparent)]
-    AccessTokenRefresh(#[from] Oauth20AccessTokenRefreshError),
+    AccessTokenRefresh(#[from] Oauth20RequestError<Oauth20AccessTokenRefreshError>),
     /// The client credentials exchange failed.
     #[error(transparent)]
-    ClientCredentialsRequest(#[from] Oauth20ClientCredentialsRequestError),
+    ClientCredentialsRequest(#[from] Oauth20RequestError<Oauth20ClientCredentialsRequestError>),
     /// The device authorization request failed.
     #[error(transparent)]
     DeviceAuthRequest(#[from] Oauth20DeviceAuthRequestError),
     /// A device access token poll failed.
     #[error(transparent)]
-    DeviceAccessTokenRequest(#[from] Oauth20DeviceAccessTokenRequestError),
+    DeviceAccessTokenRequest(#[from] Oauth20RequestError<Oauth20DeviceAccessTokenRequestError>),
     /// The device code expired before the user completed the flow.
     #[error("OAuth 2.0 device code expired before the user completed authorization")]
     DeviceCodeExpired,
@@ -98,6 +101,9 @@ pub enum Oauth20ClientStdError {
     ))]
     #[error(transparent)]
     Tls(#[from] anyhow::Error),
+    /// The endpoint URL is invalid or does not use TLS.
+    #[error(transparent)]
+    Endpoint(#[from] Oauth20EndpointError),
     /// The endpoint URL has no host.
     #[error("OAuth 2.0 URL `{0}` has no host")]
     UrlMissingHost(String),
@@ -117,6 +123,59 @@ pub enum Oauth20ClientStdError {
     InvalidRedirectRequest(String),
 }
 
+/// A token endpoint operation of [`Oauth20ClientStd`].
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Oauth20ClientStdOperation {
+    /// [`Oauth20ClientStd::request_access_token`].
+    AccessTokenRequest,
+    /// [`Oauth20ClientStd::refresh_access_token`].
+    AccessTokenRefresh,
+    /// [`Oauth20ClientStd::request_client_credentials`].
+    ClientCredentialsRequest,
+    /// [`Oauth20ClientStd::request_device_access_token`], once per poll.
+    DeviceAccessTokenRequest,
+}
+
+impl Oauth20ClientStdOperation {
+    /// Returns the operation's name, fit for metric labels.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::AccessTokenRequest => "access_token_request",
+            Self::AccessTokenRefresh => "access_token_refresh",
+            Self::ClientCredentialsRequest => "client_credentials_request",
+            Self::DeviceAccessTokenRequest => "device_access_token_request",
+        }
+    }
+}
+
+/// The outcome of a token endpoint operation.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Oauth20ClientStdOutcome {
+    /// The token endpoint issued a token.
+    Success,
+    /// The token endpoint answered with error params.
+    Error(Oauth20AccessTokenErrorCode),
+    /// No response was parsed: the stream, the HTTP exchange or the
+    /// response parsing failed.
+    Failure,
+}
+
+/// A completed token endpoint operation, as reported to the hook of
+/// [`Oauth20ClientStd::with_hook`].
+#[derive(Clone, Debug)]
+pub struct Oauth20ClientStdEvent {
+    /// The operation that completed.
+    pub operation: Oauth20ClientStdOperation,
+    /// How the operation ended.
+    pub outcome: Oauth20ClientStdOutcome,
+    /// The time the operation took, stream I/O included.
+    pub duration: Duration,
+}
+
+/// The hook [`Oauth20ClientStd`] calls once per completed token
+/// endpoint operation.
+pub type Oauth20ClientStdHook = Arc<dyn Fn(&Oauth20ClientStdEvent) + Send + Sync>;
+
 /// Std-blocking OAuth 2.0 client wrapping a single boxed stream.
 pub struct Oauth20ClientStd {
     /// The connected stream to the token endpoint.
@@ -127,6 +186,15 @@ pub struct Oauth20ClientStd {
     pub client_id: String,
     /// The client secret, for confidential clients.
     pub client_secret: Option<SecretString>,
+    /// Where the client credentials go on token and device
+    /// authorization requests (see [`Self::with_client_auth_method`]).
+    pub client_auth_method: Oauth20ClientAuthMethod,
+    /// Whether requests may go to a plain `http` endpoint that is not a
+    /// loopback host (see [`Self::allow_insecure_http`]).
+    pub insecure_http: bool,
+    /// The hook called once per completed token endpoint operation
+    /// (see [`Self::with_hook`]).
+    pub hook: Option<Oauth20ClientStdHook>,
 }
 
 impl Oauth20ClientStd {
@@ -142,12 +210,53 @@ impl Oauth20ClientStd {
             token_endpoint,
             client_id: client_id.into(),
             client_secret: None,
+            client_auth_method: Oauth20ClientAuthMethod::default(),
+            insecure_http: false,
+            hook: None,
         }
     }
 
+    /// Allows requests to plain `http` endpoints on any host.
+    ///
+    /// By default every request checks its endpoint with [`check_endpoint`]
+    /// before anything is written to the stream, rejecting a non-loopback
+    /// `http` endpoint that would carry the client secret, the authorization
+    /// code or the tokens in plaintext. Only opt out against test servers.
+    pub fn allow_insecure_http(mut self) -> Self {
+        self.insecure_http = true;
+        self
+    }
+
+    /// Sets the method the client authenticates with.
+    ///
+    /// The method places the client's `client_id` and `client_secret`,
+    /// overriding the ones of the request params: in the Basic
+    /// `Authorization` header by default, in the body for
+    /// `client_secret_post`, and only the `client_id` in the body for
+    /// public clients.
+    pub fn with_client_auth_method(mut self, method: Oauth20ClientAuthMethod) -> Self {
+        self.client_auth_method = method;
+        self
+    }
+
+    /// Sets the hook called once per completed token endpoint
+    /// operation, with its outcome and duration.
+    ///
+    /// Meant for metrics (latency, outcome counts by error code,
+    /// refresh frequency) without wrapping every call site. The hook
+    /// runs on the calling thread, right before the operation returns.
+    pub fn with_hook(
+        mut self,
+        hook: impl Fn(&Oauth20ClientStdEvent) + Send + Sync + 'static,
+    ) -> Self {
+        self.hook = Some(Arc::new(hook));
+        self
+    }
+
     /// Opens a TLS-aware connection to `token_endpoint` and returns a
     /// client ready to issue requests against it. `http://` is plain
-    /// TCP, `https://` is implicit TLS.
+    /// TCP, `https://` is implicit TLS; requests refuse a non-loopback `http`
+    /// endpoint unless [`Self::allow_insecure_http`] is set.
     #[cfg(any(
         feature = "rustls-aws",
         feature = "rustls-ring",
@@ -191,24 +300,27 @@ impl Oauth20ClientStd {
         &mut self,
         params: Oauth20AccessTokenRequestParams<'_>,
     ) -> Result<Oauth20AccessTokenResponse, Oauth20ClientStdError> {
-        let request = self.build_post_request(&self.token_endpoint);
-        let mut coroutine = Oauth20AccessTokenRequest::new(request, params);
-        let mut buf = [0u8; READ_BUFFER_SIZE];
-        let mut arg: Option<&[u8]> = None;
-
-        loop {
-            match coroutine.resume(arg.take()) {
-                Oauth20AccessTokenRequestResult::Ok(res) => return Ok(res),
-                Oauth20AccessTokenRequestResult::WantsRead => {
-                    let n = self.stream.read(&mut buf)?;
-                    arg = Some(&buf[..n]);
-                }
-                Oauth20AccessTokenRequestResult::WantsWrite(bytes) => {
-                    self.stream.write_all(&bytes)?;
+        self.observe(Oauth20ClientStdOperation::AccessTokenRequest, |client| {
+            let request = client.build_post_request(&client.token_endpoint)?;
+            let request = client.authenticate(Oauth20AccessTokenRequest::dry_run(request, params));
+            let mut coroutine = Oauth20AccessTokenRequest::from_request(request);
+            let mut buf = [0u8; READ_BUFFER_SIZE];
+            let mut arg: Option<&[u8]> = None;
+
+            loop {
+                match coroutine.resume(arg.take()) {
+                    Oauth20AccessTokenRequestResult::Ok(res) => return Ok(res),
+                    Oauth20AccessTokenRequestResult::WantsRead => {
+                        let n = client.stream.read(&mut buf)?;
+                        arg = Some(&buf[..n]);
+                    }
+                    Oauth20AccessTokenRequestResult::WantsWrite(bytes) => {
+                        client.stream.write_all(&bytes)?;
+                    }
+                    Oauth20AccessTokenRequestResult::Err(err) => return Err(err.into()),
                 }
-                Oauth20AccessTokenRequestResult::Err(err) => return Err(err.into()),
             }
-        }
+        })
     }
 
     /// Refreshes an access token using a refresh token.
@@ -216,54 +328,64 @@ impl Oauth20ClientStd {
         &mut self,
         params: Oauth20AccessTokenRefreshParams<'_>,
     ) -> Result<Oauth20AccessTokenResponse, Oauth20ClientStdError> {
-        let request = self.build_post_request(&self.token_endpoint);
-        let mut coroutine = Oauth20AccessTokenRefresh::new(request, params);
-        let mut buf = [0u8; READ_BUFFER_SIZE];
-        let mut arg: Option<&[u8]> = None;
-
-        loop {
-            match coroutine.resume(arg.take()) {
-                Oauth20AccessTokenRefreshResult::Ok(res) => return Ok(res),
-                Oauth20AccessTokenRefreshResult::WantsRead => {
-                    let n = self.stream.read(&mut buf)?;
-                    arg = Some(&buf[..n]);
-                }
-                Oauth20AccessTokenRefreshResult::WantsWrite(bytes) => {
-                    self.stream.write_all(&bytes)?;
+        self.observe(Oauth20ClientStdOperation::AccessTokenRefresh, |client| {
+            let request = client.build_post_request(&client.token_endpoint)?;
+            let request = client.authenticate(Oauth20AccessTokenRefresh::dry_run(request, params));
+            let mut coroutine = Oauth20AccessTokenRefresh::from_request(request);
+            let mut buf = [0u8; READ_BUFFER_SIZE];
+            let mut arg: Option<&[u8]> = None;
+
+            loop {
+                match coroutine.resume(arg.take()) {
+                    Oauth20AccessTokenRefreshResult::Ok(res) => return Ok(res),
+                    Oauth20AccessTokenRefreshResult::WantsRead => {
+                        let n = client.stream.read(&mut buf)?;
+                        arg = Some(&buf[..n]);
+                    }
+                    Oauth20AccessTokenRefreshResult::WantsWrite(bytes) => {
+                        client.stream.write_all(&bytes)?;
+                    }
+                    Oauth20AccessTokenRefreshResult::Err(err) => return Err(err.into()),
                 }
-                Oauth20AccessTokenRefreshResult::Err(err) => return Err(err.into()),
             }
-        }
+        })
     }
 
     /// Requests an access token with the client credentials grant.
     ///
-    /// The client authenticates with its own credentials (the Basic
-    /// `Authorization` header set from `client_secret`) and receives a
+    /// The client authenticates with its own credentials (placed per
+    /// [`Self::client_auth_method`] from `client_secret`) and receives a
     /// token scoped to resources under its own control. No refresh
     /// token is issued; the caller repeats the request on expiry.
     pub fn request_client_credentials(
         &mut self,
         params: Oauth20ClientCredentialsRequestParams<'_>,
     ) -> Result<Oauth20AccessTokenResponse, Oauth20ClientStdError> {
-        let request = self.build_post_request(&self.token_endpoint);
-        let mut coroutine = Oauth20ClientCredentialsRequest::new(request, params);
-        let mut buf = [0u8; READ_BUFFER_SIZE];
-        let mut arg: Option<&[u8]> = None;
-
-        loop {
-            match coroutine.resume(arg.take()) {
-                Oauth20ClientCredentialsRequestResult::Ok(res) => return Ok(res),
-                Oauth20ClientCredentialsRequestResult::WantsRead => {
-                    let n = self.stream.read(&mut buf)?;
-                    arg = Some(&buf[..n]);
-                }
-                Oauth20ClientCredentialsRequestResult::WantsWrite(bytes) => {
-                    self.stream.write_all(&bytes)?;
+        self.observe(
+            Oauth20ClientStdOperation::ClientCredentialsRequest,
+            |client| {
+                let request = client.build_post_request(&client.token_endpoint)?;
+                let request =
+                    client.authenticate(Oauth20ClientCredentialsRequest::dry_run(request, params));
+                let mut coroutine = Oauth20ClientCredentialsRequest::from_request(request);
+                let mut buf = [0u8; READ_BUFFER_SIZE];
+                let mut arg: Option<&[u8]> = None;
+
+                loop {
+                    match coroutine.resume(arg.take()) {
+                        Oauth20ClientCredentialsRequestResult::Ok(res) => return Ok(res),
+                        Oauth20ClientCredentialsRequestResult::WantsRead => {
+                            let n = client.stream.read(&mut buf)?;
+                            arg = Some(&buf[..n]);
+                        }
+                        Oauth20ClientCredentialsRequestResult::WantsWrite(bytes) => {
+                            client.stream.write_all(&bytes)?;
+                        }
+                        Oauth20ClientCredentialsRequestResult::Err(err) => return Err(err.into()),
+                    }
                 }
-                Oauth20ClientCredentialsRequestResult::Err(err) => return Err(err.into()),
-            }
-        }
+            },
+        )
     }
 
     /// Requests a device and user code pair from the device authorization
@@ -273,8 +395,9 @@ impl Oauth20ClientStd {
         endpoint: &Url,
         params: Oauth20DeviceAuthRequestParams<'_>,
     ) -> Result<Oauth20DeviceAuthResponse, Oauth20ClientStdError> {
-        let request = self.build_post_request(endpoint);
-        let mut coroutine = Oauth20DeviceAuthRequest::new(request, params);
+        let request = self.build_post_request(endpoint)?;
+        let request = self.authenticate(Oauth20DeviceAuthRequest::dry_run(request, params));
+        let mut coroutine = Oauth20DeviceAuthRequest::from_request(request);
         let mut buf = [0u8; READ_BUFFER_SIZE];
         let mut arg: Option<&[u8]> = None;
 
@@ -303,32 +426,39 @@ impl Oauth20ClientStd {
         &mut self,
         params: Oauth20DeviceAccessTokenRequestParams<'_>,
     ) -> Result<Oauth20AccessTokenResponse, Oauth20ClientStdError> {
-        let request = self.build_post_request(&self.token_endpoint);
-        let mut coroutine = Oauth20DeviceAccessTokenRequest::new(request, params);
-        let mut buf = [0u8; READ_BUFFER_SIZE];
-        let mut arg: Option<&[u8]> = None;
-
-        loop {
-            match coroutine.resume(arg.take()) {
-                Oauth20DeviceAccessTokenRequestResult::Ok(res) => return Ok(res),
-                Oauth20DeviceAccessTokenRequestResult::WantsRead => {
-                    let n = self.stream.read(&mut buf)?;
-                    arg = Some(&buf[..n]);
-                }
-                Oauth20DeviceAccessTokenRequestResult::WantsWrite(bytes) => {
-                    self.stream.write_all(&bytes)?;
+        self.observe(
+            Oauth20ClientStdOperation::DeviceAccessTokenRequest,
+            |client| {
+                let request = client.build_post_request(&client.token_endpoint)?;
+                let request =
+                    client.authenticate(Oauth20DeviceAccessTokenRequest::dry_run(request, params));
+                let mut coroutine = Oauth20DeviceAccessTokenRequest::from_request(request);
+                let mut buf = [0u8; READ_BUFFER_SIZE];
+                let mut arg: Option<&[u8]> = None;
+
+                loop {
+                    match coroutine.resume(arg.take()) {
+                        Oauth20DeviceAccessTokenRequestResult::Ok(res) => return Ok(res),
+                        Oauth20DeviceAccessTokenRequestResult::WantsRead => {
+                            let n = client.stream.read(&mut buf)?;
+                            arg = Some(&buf[..n]);
+                        }
+                        Oauth20DeviceAccessTokenRequestResult::WantsWrite(bytes) => {
+                            client.stream.write_all(&bytes)?;
+                        }
+                        Oauth20DeviceAccessTokenRequestResult::Err(err) => return Err(err.into()),
+                    }
                 }
-                Oauth20DeviceAccessTokenRequestResult::Err(err) => return Err(err.into()),
-            }
-        }
+            },
+        )
     }
 
     /// Polls the token endpoint until the end user completes or denies the
     /// device authorization, then returns the token response.
     ///
     /// Blocks: it sleeps the polling interval between attempts (increased by 5s
-    /// on `slow_down`), reconnects each attempt since servers rarely keep the
-    /// socket alive, and gives up with
+    /// on `slow_down`, or more when `Retry-After` asks so), reconnects each
+    /// attempt since servers rarely keep the socket alive, and gives up with
     /// [`Oauth20ClientStdError::DeviceCodeExpired`] past the code lifetime.
     #[cfg(any(
         feature = "rustls-aws",
@@ -340,13 +470,17 @@ impl Oauth20ClientStd {
         tls: &Tls,
         device: &Oauth20DeviceAuthSuccessParams,
     ) -> Result<Oauth20AccessTokenResponse, Oauth20ClientStdError> {
-        let deadline = Instant::now() + Duration::from_secs(device.expires_in as u64);
-        let mut interval = Duration::from_secs(device.interval as u64);
+        // NOTE: a monotonic clock, so a wall-clock jump neither cuts
+        // the polling short nor extends it
+        let start = Instant::now();
+        let now = || start.elapsed().as_secs();
+
+        let mut polling = Oauth20DevicePolling::new(device, 0);
 
         loop {
-            thread::sleep(interval);
+            thread::sleep(polling.interval());
 
-            if Instant::now() >= deadline {
+            if polling.is_expired(now()) {
                 return Err(Oauth20ClientStdError::DeviceCodeExpired);
             }
 
@@ -361,15 +495,15 @@ impl Oauth20ClientStd {
                 device_code: device.device_code.clone(),
             };
 
-            match self.request_device_access_token(params)? {
-                Ok(success) => return Ok(Ok(success)),
-                Err(err) => match err.error {
-                    Oauth20AccessTokenErrorCode::AuthorizationPending => continue,
-                    Oauth20AccessTokenErrorCode::SlowDown => {
-                        interval += Duration::from_secs(5);
-                    }
-                    _ => return Ok(Err(err)),
-                },
+            let response = self.request_device_access_token(params)?;
+
+            match polling.poll(response, now()) {
+                Oauth20DeviceAccessTokenPoll::Success(success) => return Ok(Ok(success)),
+                Oauth20DeviceAccessTokenPoll::Pending
+                | Oauth20DeviceAccessTokenPoll::SlowDown { .. } => continue,
+                Oauth20DeviceAccessTokenPoll::Denied(err)
+                | Oauth20DeviceAccessTokenPoll::Expired(err)
+                | Oauth20DeviceAccessTokenPoll::Error(err) => return Ok(Err(err)),
             }
         }
     }
@@ -386,7 +520,7 @@ impl Oauth20ClientStd {
         endpoint: &Url,
         params: &Oauth20ClientRegisterParams,
     ) -> Result<Oauth20ClientRegisterResponse, Oauth20ClientStdError> {
-        let request = self.build_post_request(endpoint);
+        let request = self.build_post_request(endpoint)?;
         let mut coroutine = Oauth20ClientRegister::new(request, params)
             .map_err(Oauth20ClientStdError::SerializeClientRegisterParams)?;
         let mut buf = [0u8; READ_BUFFER_SIZE];
@@ -407,24 +541,46 @@ impl Oauth20ClientStd {
         }
     }
 
-    fn build_post_request(&self, endpoint: &Url) -> HttpRequest {
-        let host = endpoint.host_str().unwrap_or("");
-        let port = endpoint.port_or_known_default().unwrap_or(0);
+    /// Runs a token endpoint operation, reporting its outcome and
+    /// duration to the hook, if any.
+    fn observe(
+        &mut self,
+        operation: Oauth20ClientStdOperation,
+        f: impl FnOnce(&mut Self) -> Result<Oauth20AccessTokenResponse, Oauth20ClientStdError>,
+    ) -> Result<Oauth20AccessTokenResponse, Oauth20ClientStdError> {
+        let start = Instant::now();
 
-        let mut request = HttpRequest {
-            method: "POST".into(),
-            url: endpoint.clone(),
-            headers: Vec::new(),
-            body: Vec::new(),
+        let res = f(self);
+
+        if let Some(hook) = &self.hook {
+            let outcome = match &res {
+                Ok(Ok(_)) => Oauth20ClientStdOutcome::Success,
+                Ok(Err(err)) => Oauth20ClientStdOutcome::Error(err.error.clone()),
+                Err(_) => Oauth20ClientStdOutcome::Failure,
+            };
+
+            hook(&Oauth20ClientStdEvent {
+                operation,
+                outcome,
+                duration: start.elapsed(),
+            });
         }
-        .header("Host", format!("{host}:{port}"));
 
-        if let Some(secret) = &self.client_secret {
-            let creds = HttpAuthBasic::new(self.client_id.clone(), secret.expose_secret());
-            request = request.header("Authorization", creds.to_authorization());
+        res
+    }
+
+    fn build_post_request(&self, endpoint: &Url) -> Result<HttpRequest, Oauth20ClientStdError> {
+        if !self.insecure_http {
+            check_endpoint(endpoint)?;
         }
 
-        request
+        Ok(post_request(endpoint))
+    }
+
+    fn authenticate(&self, request: HttpRequest) -> HttpRequest {
+        let secret = self.client_secret.as_ref();
+        self.client_auth_method
+            .authenticate(request, &self.client_id, secret)
     }
 }
 
@@ -470,3 +626,74 @@ pub fn await_redirect(redirect_uri: &Url) -> Result<Url, Oauth20ClientStdError>
 
     Ok(redirected_uri)
 }
+
+#[cfg(test)]
+mod tests {
+    use alloc::{vec, vec::Vec};
+    use std::{
+        io::{self, Cursor, Read, Write},
+        sync::{Arc, Mutex},
+    };
+
+    use url::Url;
+
+    use crate::{
+        client::*, rfc6749::client_credentials::Oauth20ClientCredentialsRequestParams,
+        test_util::http_response,
+    };
+
+    struct CannedStream(Cursor<Vec<u8>>);
+
+    impl Read for CannedStream {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.0.read(buf)
+        }
+    }
+
+    impl Write for CannedStream {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn hook_reports_each_token_operation() {
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let hook_events = events.clone();
+
+        let stream = |response: Vec<u8>| CannedStream(Cursor::new(response));
+        let endpoint = Url::parse("http://127.0.0.1/token").unwrap();
+        let mut client = Oauth20ClientStd::new(stream(Vec::new()), endpoint, "client-id")
+            .with_hook(move |event| hook_events.lock().unwrap().push(event.clone()));
+
+        let responses = [
+            http_response(200, r#"{"access_token":"a","token_type":"Bearer"}"#),
+            http_response(400, r#"{"error":"invalid_client"}"#),
+            b"garbage\r\n\r\n".to_vec(),
+        ];
+
+        for response in responses {
+            client.set_stream(stream(response));
+            let params = Oauth20ClientCredentialsRequestParams::default();
+            let _ = client.request_client_credentials(params);
+        }
+
+        let events = events.lock().unwrap();
+        let outcomes: Vec<_> = events.iter().map(|event| event.outcome.clone()).collect();
+        assert_eq!(
+            outcomes,
+            vec![
+                Oauth20ClientStdOutcome::Success,
+                Oauth20ClientStdOutcome::Error(Oauth20AccessTokenErrorCode::InvalidClient),
+                Oauth20ClientStdOutcome::Failure,
+            ]
+        );
+        assert!(events.iter().all(|event| {
+            event.operation == Oauth20ClientStdOperation::ClientCredentialsRequest
+        }));
+    }
+}
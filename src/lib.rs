@@ -26,19 +26,28 @@
 //! [`rfc6749`] holds the OAuth 2.0 Authorization Framework: the
 //! authorization code grant through [`rfc6749::auth_request`],
 //! [`rfc6749::auth_response`], [`rfc6749::access_token_request`] and
-//! [`rfc6749::state`] (the section 10.12 CSRF value); the client
-//! credentials grant through [`rfc6749::client_credentials`]; and the
-//! token issuance and refresh exchanges shared by every grant through
+//! [`rfc6749::state`] (the section 10.12 CSRF value), with
+//! [`rfc6749::redirect_uri`] keeping the redirection URI identical on
+//! both legs, [`rfc6749::issuer`] comparing the RFC 9207 issuer
+//! exactly, and [`rfc6749::auth_code_grant`] bundling them into single
+//! calls; the client credentials grant through
+//! [`rfc6749::client_credentials`]; the token issuance and refresh
+//! exchanges shared by every grant through
 //! [`rfc6749::issue_access_token`] and
-//! [`rfc6749::refresh_access_token`].
+//! [`rfc6749::refresh_access_token`]; the scope syntax through
+//! [`rfc6749::scope`]; the client authentication methods through
+//! [`rfc6749::client_auth`]; and the TLS requirement on the endpoints
+//! they talk to through [`rfc6749::endpoint`].
 //!
 //! Around it, [`rfc7636`] provides PKCE ([`rfc7636::pkce`]), consumed
 //! by the authorization code grant; [`rfc8628`] the device
 //! authorization grant, with the device and user code request in
 //! [`rfc8628::auth`] and the token endpoint polling in
-//! [`rfc8628::token`]; and [`rfc7591`] dynamic client registration
-//! ([`rfc7591::register`]), plus the preference order between the
-//! ways a client obtains its registration ([`rfc7591::source`]).
+//! [`rfc8628::token`]; [`rfc8693`] token exchange
+//! ([`rfc8693::exchange`]); and [`rfc7591`] dynamic client
+//! registration ([`rfc7591::register`]), plus the preference order
+//! between the ways a client obtains its registration
+//! ([`rfc7591::source`]).
 //!
 //! The optional [`client`] module (`client` feature) is the
 //! std-blocking [`client::Oauth20ClientStd`] pump: a light client
@@ -48,19 +57,109 @@
 //! dynamic client registration), which is why it lives at the crate
 //! root rather than under one of them; a future OAuth version would
 //! add its own client alongside, unified behind a version-agnostic
-//! wrapper only once one exists.
+//! wrapper only once one exists. Driving the coroutines itself, it
+//! reports the outcome and duration of each token operation to the
+//! metrics hook of `Oauth20ClientStd::with_hook`.
+//!
+//! The [`provider`] module holds provider configuration: the
+//! endpoints, default scope and client authentication method of an
+//! authorization server as plain serde data, with built-in presets
+//! for well-known providers (`presets` feature). Like the client, it
+//! spans the RFC modules, hence its place at the crate root.
+//!
+//! The optional `test_util` module (`test-util` feature) is an
+//! in-memory mock authorization server, answering the authorization
+//! URL and the raw bytes of token requests so consumers can drive the
+//! coroutines in their own tests without a real provider.
+//!
+//! The `oauth21` feature turns OAuth 2.1 recommendations into hard
+//! requirements: authorization params without a PKCE code challenge
+//! fail validation, and the authorization code grant only accepts a
+//! redirection landing exactly on its redirection URI.
+//!
+//! The `legacy-bearer-query` feature adds
+//! `Oauth20AccessTokenSuccessParams::append_access_token_query`, the
+//! `access_token` URI query parameter of RFC 6750 section 2.3, for
+//! legacy APIs reading the token nowhere else. It stays opt-in: the
+//! specification discourages it, since the token ends up in logs,
+//! history and `Referer` headers.
 //!
 //! ## Intentional omissions
 //!
 //! The implicit grant (section 4.2) and the resource owner password
 //! credentials grant (section 4.3) are removed by OAuth 2.1 and the
 //! OAuth Security BCP (RFC 9700); they are deliberately not
-//! implemented. Extension grants (section 4.5) are a
-//! `grant_type=<URI>` mechanism, not a concrete flow, so there is
-//! nothing generic to ship. Accessing protected resources (section 7)
-//! means sending the issued bearer token on each request, which is
-//! RFC 6750 and lives in io-http, not here: io-oauth issues the
-//! token, the consumer uses it.
+//! implemented.
+//!
+//! Extension grants (section 4.5) are a `grant_type=<URI>` mechanism,
+//! not a concrete flow, so there is nothing generic to ship; a
+//! registered one with its own RFC gets its own module, like token
+//! exchange in [`rfc8693`].
+//!
+//! Accessing protected resources (section 7) means sending the issued
+//! bearer token on each request, which is RFC 6750 and lives in
+//! io-http, not here: io-oauth issues the token, the consumer uses it.
+//! That includes the resource server's `WWW-Authenticate: Bearer`
+//! challenge, which io-http's `HttpResponse::challenges` already
+//! parses: an `invalid_token` `error` parameter is the consumer's cue
+//! to refresh and retry.
+//!
+//! Nor do coroutines chain into resource requests: calling an endpoint
+//! right after the exchange (the OpenID Connect `userinfo` one, say)
+//! is a second loop driving an io-http coroutine, with the
+//! `Authorization` header of `Oauth20AccessTokenSuccessParams::bearer`.
+//!
+//! Validating a JWT access token locally (fetching and caching the
+//! JWKS, following key rotation, checking signatures) is the resource
+//! server's job, not the client's: io-oauth treats access tokens as
+//! opaque, as RFC 6749 section 1.4 asks clients to.
+//!
+//! OpenID Connect ID tokens, signed (JWS) or encrypted (JWE), are
+//! likewise not parsed: decrypting and validating them needs a JOSE
+//! stack and key management, which belong to an OIDC layer above this
+//! crate, along with the replay tracking of their `jti` and `nonce`
+//! claims. So does the `userinfo` endpoint: its standard claims, and
+//! its signed `application/jwt` responses needing the same JOSE stack,
+//! are OpenID Connect, not OAuth 2.0.
+//!
+//! Persisting tokens (a plaintext file, the OS keyring, a secret
+//! manager) is the consumer's choice: the success params serialize to
+//! and parse from JSON strings, to be handed to whatever secret store
+//! the application already uses.
+//!
+//! The coroutines take no metrics hook, having no clock: their caller
+//! drives them to completion, so it holds the clock and the typed
+//! outcome, with `Oauth20AccessTokenErrorCode::as_str` as a ready-made
+//! label.
+//!
+//! The token coroutines speak HTTP/1.1 only, through io-http's
+//! `Http11Send`; io-http has no HTTP/2 support to plug in yet. Token
+//! endpoints accept HTTP/1.1, so a TLS stream must negotiate
+//! `http/1.1` (or no protocol) through ALPN. A consumer bound to an
+//! HTTP/2 stack can still build the request with the coroutines'
+//! `dry_run`, send it there, then parse the response with
+//! `parse_access_token_response`.
+//!
+//! No async client ships, `reqwest`-based or otherwise: an async pump
+//! would tie the crate to one runtime and one HTTP stack, which the
+//! coroutine design exists to avoid. The std-blocking `client` is
+//! the only batteries-included pump; an async consumer drives the
+//! coroutines over its own stream in a few lines, or hands the
+//! `dry_run` request to its HTTP client as above, connection pool,
+//! TLS and redirects included. The same goes for a `hyper` adapter:
+//! the coroutines emit io-http requests, not `http::Request`s, and
+//! mapping method, URL, headers and body onto a `hyper` request (then
+//! status, headers and body back onto an `HttpResponse`) is a dozen
+//! lines best kept next to the consumer's own middleware.
+//!
+//! Bodies are plain `Vec<u8>`, the body type of io-http's
+//! `HttpRequest`, on both ways: coroutines are not generic over a
+//! body type, and the crate depends on neither `http` nor `bytes`.
+//! Token bodies are small, so an HTTP stack working with `Bytes` or
+//! its own body type converts at the boundary: `Vec::from(bytes)` or
+//! `Bytes::from(request.body)` on the way out, a byte slice into
+//! `resume` (or the `Vec<u8>` body of the `HttpResponse` handed to
+//! `parse_access_token_response`) on the way back.
 //!
 //! ## Discovery lives elsewhere
 //!
@@ -81,10 +180,35 @@
 //! `Oauth20DeviceAuthRequest`, never a bare `DeviceAuthRequest`. Each
 //! coroutine exposes `new` plus `resume(Option<&[u8]>)` returning a
 //! `Result`-suffixed enum that yields `WantsRead` and `WantsWrite`,
-//! and surfaces an unexpected 3xx as a `Redirect` error. RFC wire
-//! tokens are never renamed: the `authorization_pending` and
-//! `authorization_declined` error codes keep their spelling even
-//! though identifiers otherwise shorten authorization to auth.
+//! and surfaces an unexpected 3xx as a `Redirect` error. A coroutine
+//! runs a single exchange: sequential requests over one persistent
+//! stream each get a fresh coroutine, once the previous one reports
+//! `keep_alive`. RFC wire tokens are never renamed: the
+//! `authorization_pending` and `authorization_declined` error codes
+//! keep their spelling even though identifiers otherwise shorten
+//! authorization to auth.
+//!
+//! Coroutines, params and responses are `Send` and `Sync`, and the
+//! coroutines and owned params `'static` too, so an async runtime can
+//! hold them across `.await` points on any thread; a test locks it.
+//!
+//! Request bodies are form-urlencoded as UTF-8, as RFC 6749
+//! appendix B requires: a non-ASCII scope or client identifier is
+//! percent-encoded byte by byte (`café` becomes `caf%C3%A9`), never
+//! transcoded to a legacy charset. Their fields come in a fixed
+//! order, `grant_type` first, then the grant's own fields in the
+//! order of its RFC section (for the authorization code: `code`,
+//! `redirect_uri`, `client_id`, `client_secret`, `code_verifier`),
+//! scope tokens sorted; client authentication rewrites the body with
+//! the credentials in place of `client_id`, appended when absent.
+//! Golden tests lock it, for request signing and reproducible
+//! debugging.
+//!
+//! Logging goes through the `log` facade and never carries a secret,
+//! whatever the level: coroutines log their steps, the endpoint URL
+//! and the response status, never a request nor a response body, and
+//! io-http redacts the bodies and credential headers of the requests
+//! it traces.
 
 extern crate alloc;
 #[cfg(feature = "client")]
@@ -92,7 +216,65 @@ extern crate std;
 
 #[cfg(feature = "client")]
 pub mod client;
+pub mod provider;
 pub mod rfc6749;
 pub mod rfc7591;
 pub mod rfc7636;
 pub mod rfc8628;
+pub mod rfc8693;
+#[cfg(any(test, feature = "test-util"))]
+pub mod test_util;
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        provider::*,
+        rfc6749::{
+            access_token_request::*, auth_code_grant::*, auth_request::*, auth_response::*,
+            client_credentials::*, issue_access_token::*, refresh_access_token::*,
+        },
+        rfc7591::register::*,
+        rfc7636::pkce::*,
+        rfc8628::{auth::*, token::*},
+        rfc8693::exchange::*,
+    };
+
+    fn assert_send_sync<T: Send + Sync>() {}
+    fn assert_send_sync_static<T: Send + Sync + 'static>() {}
+
+    #[test]
+    fn public_types_are_send_and_sync() {
+        assert_send_sync_static::<Oauth20AccessTokenRequest>();
+        assert_send_sync_static::<Oauth20AccessTokenRefresh>();
+        assert_send_sync_static::<Oauth20ClientCredentialsRequest>();
+        assert_send_sync_static::<Oauth20DeviceAuthRequest>();
+        assert_send_sync_static::<Oauth20DeviceAccessTokenRequest>();
+        assert_send_sync_static::<Oauth20ClientRegister>();
+        assert_send_sync_static::<Oauth20TokenExchangeRequest>();
+
+        assert_send_sync_static::<Oauth20AccessTokenRequestResult>();
+        assert_send_sync_static::<Oauth20AccessTokenRefreshResult>();
+        assert_send_sync_static::<Oauth20ClientCredentialsRequestResult>();
+        assert_send_sync_static::<Oauth20DeviceAuthRequestResult>();
+        assert_send_sync_static::<Oauth20DeviceAccessTokenRequestResult>();
+        assert_send_sync_static::<Oauth20ClientRegisterResult>();
+        assert_send_sync_static::<Oauth20TokenExchangeRequestResult>();
+
+        assert_send_sync::<Oauth20AuthRequestParams<'_>>();
+        assert_send_sync::<Oauth20AuthParams<'_>>();
+        assert_send_sync::<Oauth20AccessTokenRequestParams<'_>>();
+        assert_send_sync::<Oauth20AccessTokenRefreshParams<'_>>();
+        assert_send_sync::<Oauth20ClientCredentialsRequestParams<'_>>();
+        assert_send_sync::<Oauth20DeviceAuthRequestParams<'_>>();
+        assert_send_sync::<Oauth20DeviceAccessTokenRequestParams<'_>>();
+        assert_send_sync_static::<Oauth20ClientRegisterParams>();
+        assert_send_sync::<Oauth20TokenExchangeRequestParams<'_>>();
+
+        assert_send_sync_static::<Oauth20AccessTokenResponse>();
+        assert_send_sync_static::<Oauth20DeviceAuthResponse>();
+        assert_send_sync_static::<Oauth20ClientRegisterResponse>();
+        assert_send_sync_static::<Oauth20AuthCodeGrant>();
+        assert_send_sync_static::<Oauth20PkceCodeVerifier>();
+        assert_send_sync_static::<Oauth20ProviderConfig>();
+    }
+}
@@ -0,0 +1,379 @@
+//! Provider configuration.
+//!
+//! The endpoints, default scope and client authentication method of
+//! an authorization server, as plain data: deserialize custom
+//! providers from the application config (TOML, JSON), or start from
+//! the built-in presets (`presets` feature), whose endpoints are also
+//! exposed as constants (`GOOGLE_TOKEN`, or as a [`Url`] through
+//! `Oauth20PresetEndpoint`). Providers publishing RFC 8414 metadata
+//! are better discovered than hardcoded, see the crate documentation.
+//!
+//! The client side of the configuration, its identifier, secret and
+//! redirection URI, deserializes into [`Oauth20ClientConfig`]. serde
+//! is a mandatory dependency, so no feature gates either.
+
+#[cfg(feature = "presets")]
+use alloc::vec;
+use alloc::{collections::BTreeSet, string::String, vec::Vec};
+
+use secrecy::SecretString;
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+use crate::{
+    rfc6749::{client_auth::Oauth20ClientAuthMethod, redirect_uri::Oauth20RedirectUri},
+    rfc7636::pkce::Oauth20PkceCodeChallengeMethod,
+};
+
+/// Google authorization endpoint.
+#[cfg(feature = "presets")]
+pub const GOOGLE_AUTH: &str = "https://accounts.google.com/o/oauth2/v2/auth";
+/// Google token endpoint.
+#[cfg(feature = "presets")]
+pub const GOOGLE_TOKEN: &str = "https://oauth2.googleapis.com/token";
+/// Google device authorization endpoint.
+#[cfg(feature = "presets")]
+pub const GOOGLE_DEVICE: &str = "https://oauth2.googleapis.com/device/code";
+/// Google revocation endpoint.
+#[cfg(feature = "presets")]
+pub const GOOGLE_REVOKE: &str = "https://oauth2.googleapis.com/revoke";
+
+/// Microsoft Entra ID authorization endpoint, `common` tenant.
+#[cfg(feature = "presets")]
+pub const MICROSOFT_COMMON_AUTH: &str =
+    "https://login.microsoftonline.com/common/oauth2/v2.0/authorize";
+/// Microsoft Entra ID token endpoint, `common` tenant.
+#[cfg(feature = "presets")]
+pub const MICROSOFT_COMMON_TOKEN: &str =
+    "https://login.microsoftonline.com/common/oauth2/v2.0/token";
+/// Microsoft Entra ID device authorization endpoint, `common` tenant.
+#[cfg(feature = "presets")]
+pub const MICROSOFT_COMMON_DEVICE: &str =
+    "https://login.microsoftonline.com/common/oauth2/v2.0/devicecode";
+
+/// GitHub authorization endpoint.
+#[cfg(feature = "presets")]
+pub const GITHUB_AUTH: &str = "https://github.com/login/oauth/authorize";
+/// GitHub token endpoint.
+#[cfg(feature = "presets")]
+pub const GITHUB_TOKEN: &str = "https://github.com/login/oauth/access_token";
+/// GitHub device authorization endpoint.
+#[cfg(feature = "presets")]
+pub const GITHUB_DEVICE: &str = "https://github.com/login/device/code";
+
+/// GitLab.com authorization endpoint.
+#[cfg(feature = "presets")]
+pub const GITLAB_AUTH: &str = "https://gitlab.com/oauth/authorize";
+/// GitLab.com token endpoint.
+#[cfg(feature = "presets")]
+pub const GITLAB_TOKEN: &str = "https://gitlab.com/oauth/token";
+/// GitLab.com device authorization endpoint.
+#[cfg(feature = "presets")]
+pub const GITLAB_DEVICE: &str = "https://gitlab.com/oauth/authorize_device";
+/// GitLab.com revocation endpoint.
+#[cfg(feature = "presets")]
+pub const GITLAB_REVOKE: &str = "https://gitlab.com/oauth/revoke";
+
+/// The endpoint constants of this module, as an enum returning them
+/// as a [`Url`].
+#[cfg(feature = "presets")]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Oauth20PresetEndpoint {
+    /// [`GOOGLE_AUTH`].
+    GoogleAuth,
+    /// [`GOOGLE_TOKEN`].
+    GoogleToken,
+    /// [`GOOGLE_DEVICE`].
+    GoogleDevice,
+    /// [`GOOGLE_REVOKE`].
+    GoogleRevoke,
+    /// [`MICROSOFT_COMMON_AUTH`].
+    MicrosoftCommonAuth,
+    /// [`MICROSOFT_COMMON_TOKEN`].
+    MicrosoftCommonToken,
+    /// [`MICROSOFT_COMMON_DEVICE`].
+    MicrosoftCommonDevice,
+    /// [`GITHUB_AUTH`].
+    GithubAuth,
+    /// [`GITHUB_TOKEN`].
+    GithubToken,
+    /// [`GITHUB_DEVICE`].
+    GithubDevice,
+    /// [`GITLAB_AUTH`].
+    GitlabAuth,
+    /// [`GITLAB_TOKEN`].
+    GitlabToken,
+    /// [`GITLAB_DEVICE`].
+    GitlabDevice,
+    /// [`GITLAB_REVOKE`].
+    GitlabRevoke,
+}
+
+#[cfg(feature = "presets")]
+impl Oauth20PresetEndpoint {
+    /// Every preset endpoint.
+    pub const ALL: [Self; 14] = [
+        Self::GoogleAuth,
+        Self::GoogleToken,
+        Self::GoogleDevice,
+        Self::GoogleRevoke,
+        Self::MicrosoftCommonAuth,
+        Self::MicrosoftCommonToken,
+        Self::MicrosoftCommonDevice,
+        Self::GithubAuth,
+        Self::GithubToken,
+        Self::GithubDevice,
+        Self::GitlabAuth,
+        Self::GitlabToken,
+        Self::GitlabDevice,
+        Self::GitlabRevoke,
+    ];
+
+    /// Returns the endpoint constant.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::GoogleAuth => GOOGLE_AUTH,
+            Self::GoogleToken => GOOGLE_TOKEN,
+            Self::GoogleDevice => GOOGLE_DEVICE,
+            Self::GoogleRevoke => GOOGLE_REVOKE,
+            Self::MicrosoftCommonAuth => MICROSOFT_COMMON_AUTH,
+            Self::MicrosoftCommonToken => MICROSOFT_COMMON_TOKEN,
+            Self::MicrosoftCommonDevice => MICROSOFT_COMMON_DEVICE,
+            Self::GithubAuth => GITHUB_AUTH,
+            Self::GithubToken => GITHUB_TOKEN,
+            Self::GithubDevice => GITHUB_DEVICE,
+            Self::GitlabAuth => GITLAB_AUTH,
+            Self::GitlabToken => GITLAB_TOKEN,
+            Self::GitlabDevice => GITLAB_DEVICE,
+            Self::GitlabRevoke => GITLAB_REVOKE,
+        }
+    }
+
+    /// Returns the endpoint as a [`Url`].
+    pub fn url(&self) -> Url {
+        endpoint(self.as_str())
+    }
+}
+
+/// The endpoints and defaults of an authorization server.
+///
+/// Field names follow the RFC 8414 server metadata.
+///
+/// Refs: <https://datatracker.ietf.org/doc/html/rfc8414#section-2>
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct Oauth20ProviderConfig {
+    /// The authorization endpoint, for the authorization code grant.
+    #[serde(default)]
+    pub authorization_endpoint: Option<Url>,
+    /// The token endpoint.
+    pub token_endpoint: Url,
+    /// The device authorization endpoint, for the device authorization
+    /// grant (RFC 8628).
+    #[serde(default)]
+    pub device_authorization_endpoint: Option<Url>,
+    /// The token revocation endpoint (RFC 7009).
+    #[serde(default)]
+    pub revocation_endpoint: Option<Url>,
+    /// The scope requested when the application asks for none.
+    #[serde(default)]
+    pub scope: BTreeSet<String>,
+    /// The method the client authenticates with on the token endpoint.
+    #[serde(default)]
+    pub token_endpoint_auth_method: Oauth20ClientAuthMethod,
+    /// The PKCE methods the server supports, empty when PKCE is not
+    /// supported or not advertised. Pick one with
+    /// [`Oauth20PkceCodeChallenge::negotiate`].
+    ///
+    /// [`Oauth20PkceCodeChallenge::negotiate`]: crate::rfc7636::pkce::Oauth20PkceCodeChallenge::negotiate
+    #[serde(default)]
+    pub code_challenge_methods_supported: Vec<Oauth20PkceCodeChallengeMethod>,
+}
+
+/// The registration of a client with an authorization server.
+///
+/// Deserialize only: the secret never serializes back. Its value is
+/// redacted from the `Debug` output, and zeroed on drop.
+///
+/// Refs: <https://datatracker.ietf.org/doc/html/rfc6749#section-2>
+#[derive(Clone, Debug, Deserialize)]
+pub struct Oauth20ClientConfig {
+    /// The client identifier.
+    pub client_id: String,
+    /// The client secret, for confidential clients.
+    #[serde(default)]
+    pub client_secret: Option<SecretString>,
+    /// The registered redirection URI, for the authorization code grant.
+    #[serde(default)]
+    pub redirect_uri: Option<Oauth20RedirectUri>,
+}
+
+/// Built-in presets for well-known providers.
+///
+/// The presets only carry the scope a provider needs for the grant
+/// itself (Microsoft's `offline_access`, without which no refresh
+/// token is issued): add the API scopes the application needs.
+#[cfg(feature = "presets")]
+impl Oauth20ProviderConfig {
+    /// Google.
+    ///
+    /// Refs: <https://developers.google.com/identity/protocols/oauth2/native-app>
+    pub fn google() -> Self {
+        Self {
+            authorization_endpoint: Some(Oauth20PresetEndpoint::GoogleAuth.url()),
+            token_endpoint: Oauth20PresetEndpoint::GoogleToken.url(),
+            device_authorization_endpoint: Some(Oauth20PresetEndpoint::GoogleDevice.url()),
+            revocation_endpoint: Some(Oauth20PresetEndpoint::GoogleRevoke.url()),
+            scope: BTreeSet::new(),
+            token_endpoint_auth_method: Oauth20ClientAuthMethod::ClientSecretPost,
+            code_challenge_methods_supported: vec![Oauth20PkceCodeChallengeMethod::Sha256],
+        }
+    }
+
+    /// Microsoft Entra ID, `common` tenant.
+    ///
+    /// Refs: <https://learn.microsoft.com/en-us/entra/identity-platform/v2-oauth2-auth-code-flow>
+    pub fn microsoft() -> Self {
+        Self {
+            authorization_endpoint: Some(Oauth20PresetEndpoint::MicrosoftCommonAuth.url()),
+            token_endpoint: Oauth20PresetEndpoint::MicrosoftCommonToken.url(),
+            device_authorization_endpoint: Some(Oauth20PresetEndpoint::MicrosoftCommonDevice.url()),
+            revocation_endpoint: None,
+            scope: BTreeSet::from([String::from("offline_access")]),
+            token_endpoint_auth_method: Oauth20ClientAuthMethod::ClientSecretPost,
+            code_challenge_methods_supported: vec![Oauth20PkceCodeChallengeMethod::Sha256],
+        }
+    }
+
+    /// GitHub.
+    ///
+    /// Refs: <https://docs.github.com/en/apps/oauth-apps/building-oauth-apps/authorizing-oauth-apps>
+    pub fn github() -> Self {
+        Self {
+            authorization_endpoint: Some(Oauth20PresetEndpoint::GithubAuth.url()),
+            token_endpoint: Oauth20PresetEndpoint::GithubToken.url(),
+            device_authorization_endpoint: Some(Oauth20PresetEndpoint::GithubDevice.url()),
+            revocation_endpoint: None,
+            scope: BTreeSet::new(),
+            token_endpoint_auth_method: Oauth20ClientAuthMethod::ClientSecretPost,
+            code_challenge_methods_supported: vec![Oauth20PkceCodeChallengeMethod::Sha256],
+        }
+    }
+
+    /// GitLab.com.
+    ///
+    /// Refs: <https://docs.gitlab.com/api/oauth2/>
+    pub fn gitlab() -> Self {
+        Self {
+            authorization_endpoint: Some(Oauth20PresetEndpoint::GitlabAuth.url()),
+            token_endpoint: Oauth20PresetEndpoint::GitlabToken.url(),
+            device_authorization_endpoint: Some(Oauth20PresetEndpoint::GitlabDevice.url()),
+            revocation_endpoint: Some(Oauth20PresetEndpoint::GitlabRevoke.url()),
+            scope: BTreeSet::new(),
+            token_endpoint_auth_method: Oauth20ClientAuthMethod::ClientSecretBasic,
+            code_challenge_methods_supported: vec![Oauth20PkceCodeChallengeMethod::Sha256],
+        }
+    }
+}
+
+/// Parses one of the endpoint constants of this module.
+///
+/// Reached through [`Oauth20PresetEndpoint::url`] only: panics on an
+/// invalid URL, which the tests rule out for every constant.
+#[cfg(feature = "presets")]
+fn endpoint(endpoint: &'static str) -> Url {
+    // SAFETY: the constants are valid static URLs, checked by the tests
+    Url::parse(endpoint).unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::format;
+
+    use crate::provider::*;
+
+    #[test]
+    fn custom_provider_deserializes_with_defaults() {
+        let config: Oauth20ProviderConfig = serde_json::from_str(
+            r#"{
+                "authorization_endpoint": "https://as.example/authorize",
+                "token_endpoint": "https://as.example/token",
+                "scope": ["openid", "email"],
+                "token_endpoint_auth_method": "none",
+                "code_challenge_methods_supported": ["plain", "S256"]
+            }"#,
+        )
+        .unwrap();
+
+        assert_eq!(config.token_endpoint.as_str(), "https://as.example/token");
+        assert_eq!(config.device_authorization_endpoint, None);
+        assert_eq!(config.scope.len(), 2);
+        assert_eq!(
+            config.token_endpoint_auth_method,
+            Oauth20ClientAuthMethod::None
+        );
+        assert_eq!(
+            config.code_challenge_methods_supported,
+            [
+                Oauth20PkceCodeChallengeMethod::Plain,
+                Oauth20PkceCodeChallengeMethod::Sha256
+            ]
+        );
+    }
+
+    #[test]
+    fn client_config_redacts_its_secret() {
+        let config: Oauth20ClientConfig = serde_json::from_str(
+            r#"{
+                "client_id": "client-id",
+                "client_secret": "s3cr3t",
+                "redirect_uri": "HTTP://127.0.0.1:8080"
+            }"#,
+        )
+        .unwrap();
+
+        let redirect_uri = config.redirect_uri.as_ref().unwrap();
+        assert_eq!(redirect_uri.as_str(), "HTTP://127.0.0.1:8080");
+
+        let debug = format!("{config:?}");
+        assert!(debug.contains("client-id"), "{debug}");
+        assert!(!debug.contains("s3cr3t"), "{debug}");
+    }
+
+    #[cfg(feature = "presets")]
+    #[test]
+    fn presets_use_tls_endpoints() {
+        use crate::rfc6749::endpoint::check_endpoint;
+
+        let presets = [
+            Oauth20ProviderConfig::google(),
+            Oauth20ProviderConfig::microsoft(),
+            Oauth20ProviderConfig::github(),
+            Oauth20ProviderConfig::gitlab(),
+        ];
+
+        for preset in presets {
+            let endpoints = [
+                preset.authorization_endpoint,
+                Some(preset.token_endpoint),
+                preset.device_authorization_endpoint,
+                preset.revocation_endpoint,
+            ];
+
+            for endpoint in endpoints.iter().flatten() {
+                assert!(check_endpoint(endpoint).is_ok());
+            }
+        }
+    }
+
+    #[cfg(feature = "presets")]
+    #[test]
+    fn constants_are_valid_endpoints() {
+        use crate::rfc6749::endpoint::parse_endpoint;
+
+        for preset in Oauth20PresetEndpoint::ALL {
+            let url = parse_endpoint(preset.as_str()).unwrap();
+            assert_eq!(url.as_str(), preset.as_str());
+            assert_eq!(preset.url(), url);
+        }
+    }
+}
@@ -12,18 +12,11 @@
 //!     net::TcpStream,
 //! };
 //!
-//! use io_http::rfc9110::request::HttpRequest;
-//! use io_oauth::rfc6749::access_token_request::*;
+//! use io_oauth::rfc6749::{access_token_request::*, endpoint::post_request};
 //! use url::Url;
 //!
 //! let token_url = Url::parse("https://example.com/token").unwrap();
-//! let request = HttpRequest {
-//!     method: "POST".into(),
-//!     url: token_url.clone(),
-//!     headers: Vec::new(),
-//!     body: Vec::new(),
-//! }
-//! .header("Host", token_url.host_str().unwrap());
+//! let request = post_request(&token_url);
 //!
 //! let params = Oauth20AccessTokenRequestParams {
 //!     code: "the-authorization-code".into(),
@@ -56,30 +49,20 @@
 
 use core::fmt;
 
-use alloc::{
-    borrow::Cow,
-    string::{String, ToString},
-    vec::Vec,
-};
+use alloc::{borrow::Cow, string::String, vec::Vec};
 
-use io_http::{
-    coroutine::*,
-    rfc9110::{
-        request::HttpRequest,
-        send::{HttpSendOutput, HttpSendYield},
-    },
-    rfc9112::send::{Http11Send, Http11SendError},
-};
-use log::{debug, trace};
+use io_http::{rfc9110::request::HttpRequest, rfc9112::send::Http11SendError};
 use secrecy::{ExposeSecret, SecretString};
 use thiserror::Error;
 use url::{Url, form_urlencoded::Serializer};
 
 use crate::{
     rfc6749::issue_access_token::{
-        Oauth20AccessTokenErrorParams, Oauth20AccessTokenResponse, Oauth20AccessTokenSuccessParams,
-        parse_http_date,
+        Oauth20AccessTokenResponse, Oauth20AccessTokenResponseError, Oauth20CoroutineState,
+        Oauth20RequestError, Oauth20TokenCoroutine, Oauth20TokenCoroutineError,
+        Oauth20TokenCoroutineResult, accept_json,
     },
+    rfc6749::redirect_uri::Oauth20RedirectUri,
     rfc7636::pkce::Oauth20PkceCodeVerifier,
 };
 
@@ -91,8 +74,11 @@ pub struct Oauth20AccessTokenRequestParams<'a> {
     pub code: Cow<'a, str>,
     /// The redirection URI, when it was part of the authorization request.
     ///
-    /// Refs: <https://datatracker.ietf.org/doc/html/rfc6749#section-3.1.2>
-    pub redirect_uri: Option<Cow<'a, str>>,
+    /// Must be the value sent on the authorization request, which the
+    /// server compares byte for byte.
+    ///
+    /// Refs: <https://datatracker.ietf.org/doc/html/rfc6749#section-4.1.3>
+    pub redirect_uri: Option<Oauth20RedirectUri>,
     /// The client identifier.
     ///
     /// Refs: <https://datatracker.ietf.org/doc/html/rfc6749#section-2.2>
@@ -102,10 +88,34 @@ pub struct Oauth20AccessTokenRequestParams<'a> {
     /// Refs: <https://datatracker.ietf.org/doc/html/rfc6749#section-2.3.1>
     pub client_secret: Option<SecretString>,
     /// The PKCE code verifier, when the flow uses PKCE (RFC 7636).
+    ///
+    /// Pass `Cow::Borrowed(&verifier)` to keep the params from cloning
+    /// the secret; the request body still carries it in clear, and
+    /// [`Oauth20AccessTokenRequest::from_request_retained`] copies that
+    /// body. `Cow::Owned`, like [`Self::into_owned`], clones the secret.
     pub pkce_code_verifier: Option<Cow<'a, Oauth20PkceCodeVerifier>>,
 }
 
+/// The access token request params, owning every value.
+pub type Oauth20AccessTokenRequestParamsOwned = Oauth20AccessTokenRequestParams<'static>;
+
 impl<'a> Oauth20AccessTokenRequestParams<'a> {
+    /// Converts every borrowed value into an owned one, so the params
+    /// outlive the strings they were built from.
+    ///
+    /// A borrowed PKCE code verifier is cloned, copying the secret.
+    pub fn into_owned(self) -> Oauth20AccessTokenRequestParams<'static> {
+        Oauth20AccessTokenRequestParams {
+            code: Cow::Owned(self.code.into_owned()),
+            redirect_uri: self.redirect_uri,
+            client_id: Cow::Owned(self.client_id.into_owned()),
+            client_secret: self.client_secret,
+            pkce_code_verifier: self
+                .pkce_code_verifier
+                .map(|verifier| Cow::Owned(verifier.into_owned())),
+        }
+    }
+
     /// Serializes the params into the form-urlencoded request body.
     // SAFETY: this function exposes the code and the PKCE code verifier
     pub fn to_form_url_encoded_serializer(&self) -> Serializer<'a, String> {
@@ -115,7 +125,7 @@ impl<'a> Oauth20AccessTokenRequestParams<'a> {
         serializer.append_pair("code", &self.code);
 
         if let Some(uri) = &self.redirect_uri {
-            serializer.append_pair("redirect_uri", uri);
+            serializer.append_pair("redirect_uri", uri.as_str());
         }
 
         serializer.append_pair("client_id", &self.client_id);
@@ -125,6 +135,8 @@ impl<'a> Oauth20AccessTokenRequestParams<'a> {
         }
 
         if let Some(verifier) = &self.pkce_code_verifier {
+            // NOTE: the verifier is ASCII, so this borrows it rather
+            // than making another plaintext copy
             let verifier = String::from_utf8_lossy(verifier.expose());
             serializer.append_pair("code_verifier", &verifier);
         }
@@ -133,6 +145,18 @@ impl<'a> Oauth20AccessTokenRequestParams<'a> {
     }
 }
 
+impl fmt::Debug for Oauth20AccessTokenRequestParams<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Oauth20AccessTokenRequestParams")
+            .field("code", &format_args!("[REDACTED]"))
+            .field("redirect_uri", &self.redirect_uri)
+            .field("client_id", &self.client_id)
+            .field("client_secret", &self.client_secret)
+            .field("pkce_code_verifier", &self.pkce_code_verifier)
+            .finish()
+    }
+}
+
 impl fmt::Display for Oauth20AccessTokenRequestParams<'_> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.write_str(&self.to_form_url_encoded_serializer().finish())
@@ -147,7 +171,7 @@ pub enum Oauth20AccessTokenRequestError {
     SendHttpRequest(#[from] Http11SendError),
     /// The HTTP response could not be parsed.
     #[error(transparent)]
-    ParseHttpResponse(#[from] serde_json::Error),
+    ParseHttpResponse(#[from] Oauth20AccessTokenResponseError),
     /// The server answered with an unexpected redirection.
     #[error("Unexpected redirection {code} to {url}")]
     Redirect {
@@ -156,6 +180,9 @@ pub enum Oauth20AccessTokenRequestError {
         /// The redirection HTTP status code.
         code: u16,
     },
+    /// The coroutine was cancelled by the caller.
+    #[error("OAuth 2.0 access token request cancelled")]
+    Cancelled,
 }
 
 /// Result returned by the coroutine's resume function.
@@ -167,8 +194,9 @@ pub enum Oauth20AccessTokenRequestResult {
     WantsRead,
     /// The coroutine wants the given bytes to be written to the socket.
     WantsWrite(Vec<u8>),
-    /// The coroutine encountered an error.
-    Err(Oauth20AccessTokenRequestError),
+    /// The coroutine encountered an error, along the identifier of
+    /// the request.
+    Err(Oauth20RequestError<Oauth20AccessTokenRequestError>),
 }
 
 /// The I/O-free coroutine to exchange an authorization code for an access
@@ -177,65 +205,232 @@ pub enum Oauth20AccessTokenRequestResult {
 /// Refs: <https://datatracker.ietf.org/doc/html/rfc6749#section-4.1>
 #[derive(Debug)]
 pub struct Oauth20AccessTokenRequest {
-    send: Http11Send,
+    inner: Oauth20TokenCoroutine,
 }
 
 impl Oauth20AccessTokenRequest {
     /// Creates the coroutine to exchange an authorization code.
     pub fn new(request: HttpRequest, body: Oauth20AccessTokenRequestParams<'_>) -> Self {
-        debug!("prepare access token request");
-        trace!("url: {}", request.url);
+        Self::from_request(Self::dry_run(request, body))
+    }
 
-        let request = request
-            .header("Content-Type", "application/x-www-form-urlencoded")
-            .body(body.to_string().into_bytes());
+    /// Creates the coroutine sending `request` as-is.
+    ///
+    /// Meant for a request built by [`Self::dry_run`] then adjusted, for
+    /// instance by [`Oauth20ClientAuthMethod::authenticate`].
+    ///
+    /// [`Oauth20ClientAuthMethod::authenticate`]: crate::rfc6749::client_auth::Oauth20ClientAuthMethod::authenticate
+    pub fn from_request(request: HttpRequest) -> Self {
+        Self {
+            inner: Oauth20TokenCoroutine::new("access token", request),
+        }
+    }
 
+    /// Creates the coroutine sending `request` as-is, like
+    /// [`Self::from_request`], keeping a copy of it for
+    /// [`Self::into_request`].
+    ///
+    /// The copy holds the body a second time, secrets included, for the
+    /// whole life of the coroutine; hence opt-in.
+    // SAFETY: the retained body keeps the params' secrets in memory
+    pub fn from_request_retained(request: HttpRequest) -> Self {
         Self {
-            send: Http11Send::new(request),
+            inner: Oauth20TokenCoroutine::retained("access token", request),
         }
     }
 
+    /// Returns the HTTP request the coroutine would send, without sending it.
+    ///
+    /// Meant for inspecting or replaying a request (with `curl`, say) when a
+    /// provider misbehaves. The `Debug` output of the request redacts its
+    /// body, but the body itself carries the form params in clear.
+    ///
+    /// Asks for a JSON response with `Accept: application/json`, unless
+    /// `request` already carries an `Accept` header.
+    // SAFETY: the returned body exposes the params' secrets
+    pub fn dry_run(request: HttpRequest, body: Oauth20AccessTokenRequestParams<'_>) -> HttpRequest {
+        accept_json(request)
+            .header("Content-Type", "application/x-www-form-urlencoded")
+            .body(body.to_form_url_encoded_serializer().finish().into_bytes())
+    }
+
+    /// Returns the identifier of the request, when the caller set one.
+    ///
+    /// Read from the [`OAUTH20_REQUEST_ID_HEADER`] of the request, it tags
+    /// the errors returned by [`Self::resume`].
+    ///
+    /// [`OAUTH20_REQUEST_ID_HEADER`]: crate::rfc6749::issue_access_token::OAUTH20_REQUEST_ID_HEADER
+    pub fn request_id(&self) -> Option<&str> {
+        self.inner.request_id()
+    }
+
+    /// Returns whether the server kept the connection alive for another
+    /// token request, `false` until [`Self::resume`] returned the response.
+    pub fn keep_alive(&self) -> bool {
+        self.inner.keep_alive()
+    }
+
+    /// Returns the progress of the coroutine, for diagnostics.
+    pub fn state(&self) -> Oauth20CoroutineState {
+        self.inner.state()
+    }
+
+    /// Returns the request the coroutine was created with, to retry
+    /// through a fresh [`Self::from_request`] once it failed.
+    ///
+    /// `None` unless the coroutine was created by
+    /// [`Self::from_request_retained`], the only one keeping it.
+    // SAFETY: the returned body exposes the params' secrets
+    pub fn into_request(self) -> Option<HttpRequest> {
+        self.inner.into_request()
+    }
+
+    /// Cancels the coroutine.
+    ///
+    /// Every later [`Self::resume`] returns a `Cancelled` error without
+    /// yielding any more I/O. The request may have been partially written, or
+    /// its response partially read: drop the stream rather than reusing it
+    /// for another request.
+    pub fn cancel(&mut self) {
+        self.inner.cancel()
+    }
+
     /// Makes the coroutine progress.
     pub fn resume(&mut self, arg: Option<&[u8]>) -> Oauth20AccessTokenRequestResult {
-        match self.send.resume(arg) {
-            HttpCoroutineState::Complete(Ok(HttpSendOutput { response, .. }))
-                if response.status.is_success() =>
-            {
-                debug!("received access token response");
-                trace!("status: {}", *response.status);
-
-                match Oauth20AccessTokenSuccessParams::try_from(response.body.as_slice()) {
-                    Ok(mut res) => {
-                        res.issued_at = response.header("date").and_then(parse_http_date);
-                        Oauth20AccessTokenRequestResult::Ok(Ok(res))
-                    }
-                    Err(err) => Oauth20AccessTokenRequestResult::Err(err.into()),
-                }
-            }
-            HttpCoroutineState::Complete(Ok(HttpSendOutput { response, .. })) => {
-                debug!("received access token error response");
-                trace!("status: {}", *response.status);
-
-                match Oauth20AccessTokenErrorParams::try_from(response.body.as_slice()) {
-                    Ok(res) => Oauth20AccessTokenRequestResult::Ok(Err(res)),
-                    Err(err) => Oauth20AccessTokenRequestResult::Err(err.into()),
-                }
-            }
-            HttpCoroutineState::Yielded(HttpSendYield::WantsRead) => {
-                Oauth20AccessTokenRequestResult::WantsRead
-            }
-            HttpCoroutineState::Yielded(HttpSendYield::WantsWrite(bytes)) => {
+        match self.inner.resume(arg) {
+            Oauth20TokenCoroutineResult::Ok(res) => Oauth20AccessTokenRequestResult::Ok(res),
+            Oauth20TokenCoroutineResult::WantsRead => Oauth20AccessTokenRequestResult::WantsRead,
+            Oauth20TokenCoroutineResult::WantsWrite(bytes) => {
                 Oauth20AccessTokenRequestResult::WantsWrite(bytes)
             }
-            HttpCoroutineState::Yielded(HttpSendYield::WantsRedirect { url, response, .. }) => {
-                Oauth20AccessTokenRequestResult::Err(Oauth20AccessTokenRequestError::Redirect {
-                    url,
-                    code: *response.status,
-                })
-            }
-            HttpCoroutineState::Complete(Err(err)) => {
-                Oauth20AccessTokenRequestResult::Err(err.into())
-            }
+            Oauth20TokenCoroutineResult::Err(err) => Oauth20AccessTokenRequestResult::Err(err),
         }
     }
 }
+
+impl Oauth20TokenCoroutineError for Oauth20AccessTokenRequestError {
+    fn redirect(url: Url, code: u16) -> Self {
+        Self::Redirect { url, code }
+    }
+
+    fn cancelled() -> Self {
+        Self::Cancelled
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::{
+        borrow::Cow,
+        format,
+        string::{String, ToString},
+        vec::Vec,
+    };
+    use core::str::FromStr;
+
+    use io_http::rfc9110::request::HttpRequest;
+    use url::Url;
+
+    use crate::{
+        rfc6749::{access_token_request::*, endpoint::post_request},
+        rfc7636::pkce::Oauth20PkceCodeVerifier,
+    };
+
+    fn request_and_params() -> (HttpRequest, Oauth20AccessTokenRequestParams<'static>) {
+        let request = HttpRequest {
+            method: "POST".into(),
+            url: Url::parse("https://example.com/token").unwrap(),
+            headers: Vec::new(),
+            body: Vec::new(),
+        };
+        let params = Oauth20AccessTokenRequestParams {
+            code: "code".into(),
+            redirect_uri: None,
+            client_id: "client-id".into(),
+            client_secret: None,
+            pkce_code_verifier: None,
+        };
+
+        (request, params)
+    }
+
+    #[test]
+    fn dry_run_returns_the_request_to_send() {
+        let (request, params) = request_and_params();
+
+        let request = Oauth20AccessTokenRequest::dry_run(request, params);
+        assert_eq!(request.method, "POST");
+        assert!(
+            request
+                .headers
+                .iter()
+                .any(|(k, v)| { k == "Content-Type" && v == "application/x-www-form-urlencoded" })
+        );
+        assert_eq!(
+            request.body,
+            b"grant_type=authorization_code&code=code&client_id=client-id"
+        );
+    }
+
+    #[test]
+    fn dry_run_accepts_json_unless_told_otherwise() {
+        let url = Url::parse("https://example.com/token").unwrap();
+        let params = || request_and_params().1;
+        let accept = |request: &HttpRequest| -> Vec<String> {
+            request
+                .headers
+                .iter()
+                .filter(|(k, _)| k.eq_ignore_ascii_case("Accept"))
+                .map(|(_, v)| v.clone())
+                .collect()
+        };
+
+        let request = Oauth20AccessTokenRequest::dry_run(post_request(&url), params());
+        assert_eq!(accept(&request), ["application/json"]);
+
+        let request = post_request(&url).header("accept", "application/vnd.custom+json");
+        let request = Oauth20AccessTokenRequest::dry_run(request, params());
+        assert_eq!(accept(&request), ["application/vnd.custom+json"]);
+    }
+
+    #[test]
+    fn params_debug_redacts_secrets() {
+        let params = Oauth20AccessTokenRequestParams {
+            code: "s3cr3t-c0de".into(),
+            redirect_uri: None,
+            client_id: "client-id".into(),
+            client_secret: Some("s3cr3t-client".into()),
+            pkce_code_verifier: Some(Cow::Owned(
+                Oauth20PkceCodeVerifier::from_str("s3cr3t-verifier").unwrap(),
+            )),
+        };
+
+        let debug = format!("{params:?}");
+        assert!(debug.contains("client-id"), "{debug}");
+        assert!(!debug.contains("s3cr3t"), "{debug}");
+    }
+
+    #[test]
+    fn access_token_request_body_is_golden() {
+        let verifier = "dBjftJeZ4CVP-mB92K27uhbUJU1p1r_wW1gFWFOEjXk";
+        let params = Oauth20AccessTokenRequestParams {
+            code: "a+b c/d=e&f".into(),
+            redirect_uri: Some("https://client.example.com/cb?x=1&y=a b".parse().unwrap()),
+            client_id: "s6BhdRkqt3".into(),
+            client_secret: Some("7Fjfp0ZBr1KtDRbnfVdmIw".into()),
+            pkce_code_verifier: Some(Cow::Owned(
+                Oauth20PkceCodeVerifier::from_str(verifier).unwrap(),
+            )),
+        };
+
+        assert_eq!(
+            params.to_string(),
+            "grant_type=authorization_code\
+             &code=a%2Bb+c%2Fd%3De%26f\
+             &redirect_uri=https%3A%2F%2Fclient.example.com%2Fcb%3Fx%3D1%26y%3Da+b\
+             &client_id=s6BhdRkqt3\
+             &client_secret=7Fjfp0ZBr1KtDRbnfVdmIw\
+             &code_verifier=dBjftJeZ4CVP-mB92K27uhbUJU1p1r_wW1gFWFOEjXk",
+        );
+    }
+}
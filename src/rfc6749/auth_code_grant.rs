@@ -0,0 +1,586 @@
+//! Authorization code grant, end to end (RFC 6749 section 4.1).
+//!
+//! The building blocks of the grant ([`super::auth_request`],
+//! [`super::auth_response`], [`super::access_token_request`], PKCE
+//! and state) leave the wiring to the caller: generating the verifier
+//! and the state, deriving the challenge, then handing the very same
+//! values back on the callback. This module bundles the common native
+//! app setup, PKCE `S256` plus a random state, into single calls:
+//! [`Oauth20AuthCodeGrant::begin_authorization`] builds the
+//! authorization URL, then
+//! [`Oauth20AuthCodeGrant::complete_authorization`] turns the
+//! redirection into the token request, so the state check cannot be
+//! skipped, the verifier is the one the challenge was derived from,
+//! and the redirection URI is the one sent on the first leg. A retry
+//! goes through [`Oauth20AuthCodeGrant::regenerate`], so no state nor
+//! verifier outlives its attempt. A code is exchanged at most once per
+//! grant: a redirection handled twice (double submit, browser retry)
+//! fails locally with [`Oauth20AuthCodeGrantError::CodeAlreadyUsed`]
+//! rather than with the server's `invalid_grant`.
+//!
+//! Generating the random values needs the `client` feature.
+
+#[cfg(feature = "client")]
+use alloc::collections::BTreeMap;
+use alloc::{
+    borrow::Cow,
+    collections::BTreeSet,
+    string::{String, ToString},
+};
+
+use sha2::{Digest, Sha256};
+use thiserror::Error;
+use url::Url;
+
+#[cfg(feature = "client")]
+use crate::rfc6749::auth_request::Oauth20AuthRequestParams;
+#[cfg(feature = "client")]
+use crate::rfc7636::pkce::Oauth20PkceCodeChallenge;
+use crate::{
+    rfc6749::{
+        access_token_request::{Oauth20AccessTokenRequest, Oauth20AccessTokenRequestParams},
+        auth_response::{Oauth20AuthParams, Oauth20AuthParamsValidationError},
+        endpoint::{Oauth20EndpointError, check_endpoint, post_request},
+        issuer::Oauth20Issuer,
+        redirect_uri::Oauth20RedirectUri,
+        state::Oauth20State,
+    },
+    rfc7636::pkce::Oauth20PkceCodeVerifier,
+};
+
+/// Errors that can occur while completing the grant.
+#[derive(Debug, Error)]
+pub enum Oauth20AuthCodeGrantError<'a> {
+    /// The authorization response is an error, or its state or issuer
+    /// does not match.
+    ///
+    /// Borrows the redirection URL, so it is not exposed as the error
+    /// source.
+    #[error("{0}")]
+    Validate(Oauth20AuthParamsValidationError<'a>),
+    /// The token endpoint is not a valid TLS endpoint.
+    #[error(transparent)]
+    Endpoint(#[from] Oauth20EndpointError),
+    /// The authorization code was already exchanged by this grant.
+    ///
+    /// Refs: <https://datatracker.ietf.org/doc/html/rfc6749#section-4.1.2>
+    #[error("OAuth 2.0 authorization code already used")]
+    CodeAlreadyUsed,
+    /// The redirection does not land on the redirection URI sent on the
+    /// first leg, compared exactly as OAuth 2.1 requires.
+    ///
+    /// Only reported with the `oauth21` feature.
+    #[error("OAuth 2.1 redirection `{0}` does not exactly match the redirection URI")]
+    RedirectUriMismatch(String),
+}
+
+impl<'a> From<Oauth20AuthParamsValidationError<'a>> for Oauth20AuthCodeGrantError<'a> {
+    fn from(err: Oauth20AuthParamsValidationError<'a>) -> Self {
+        Self::Validate(err)
+    }
+}
+
+/// The authorization code grant of one client.
+///
+/// Holds the client identifier and the redirection URI once, and sends
+/// them on both the authorization and the access token requests, so the
+/// two cannot diverge into an `invalid_grant`.
+///
+/// Not `Clone`: a copy would carry its own set of consumed codes, and
+/// could exchange a code the original already did.
+///
+/// Refs: <https://datatracker.ietf.org/doc/html/rfc6749#section-4.1.3>
+#[derive(Debug)]
+pub struct Oauth20AuthCodeGrant {
+    client_id: String,
+    redirect_uri: Oauth20RedirectUri,
+    // NOTE: digests rather than the codes themselves, so the grant
+    // neither keeps nor prints them
+    consumed_codes: BTreeSet<[u8; 32]>,
+}
+
+impl Oauth20AuthCodeGrant {
+    /// Creates the grant of the given client.
+    pub fn new(client_id: impl ToString, redirect_uri: Oauth20RedirectUri) -> Self {
+        Self {
+            client_id: client_id.to_string(),
+            redirect_uri,
+            consumed_codes: BTreeSet::new(),
+        }
+    }
+
+    /// Returns the client identifier.
+    pub fn client_id(&self) -> &str {
+        &self.client_id
+    }
+
+    /// Returns the redirection URI sent on both requests.
+    pub fn redirect_uri(&self) -> &Oauth20RedirectUri {
+        &self.redirect_uri
+    }
+
+    /// Starts the grant: generates a PKCE `S256` pair and a state, then
+    /// builds the authorization URL carrying the challenge and the state.
+    ///
+    /// Send the user to the returned URL, and stash the verifier and the
+    /// state until the redirection comes back: the verifier is the one the
+    /// challenge in the URL derives from.
+    ///
+    /// Fails when `endpoint` is neither `https` nor loopback `http`, as
+    /// [`Self::complete_authorization`] does for the token endpoint.
+    ///
+    /// Refs: <https://datatracker.ietf.org/doc/html/rfc6749#section-3.1>,
+    ///       <https://datatracker.ietf.org/doc/html/rfc6749#section-4.1.1>,
+    ///       <https://datatracker.ietf.org/doc/html/rfc7636#section-4.3>
+    #[cfg(feature = "client")]
+    pub fn begin_authorization<'a>(
+        &self,
+        scope: impl IntoIterator<Item = &'a str>,
+        endpoint: &Url,
+    ) -> Result<(Url, Oauth20PkceCodeVerifier, Oauth20State), Oauth20EndpointError> {
+        check_endpoint(endpoint)?;
+
+        let state = Oauth20State::default();
+        let challenge = Oauth20PkceCodeChallenge::default();
+
+        let url = Oauth20AuthRequestParams {
+            client_id: self.client_id.as_str().into(),
+            redirect_uri: Some(self.redirect_uri.clone()),
+            scope: scope.into_iter().map(Cow::Borrowed).collect(),
+            scope_delimiter: Default::default(),
+            state: Some(Cow::Borrowed(&state)),
+            pkce_code_challenge: Some(Cow::Borrowed(&challenge)),
+            extras: BTreeMap::new(),
+        }
+        .build_url_unchecked(endpoint);
+
+        Ok((url, challenge.verifier, state))
+    }
+
+    /// Restarts the grant after a failed or abandoned attempt: rebuilds
+    /// the `previous` authorization URL with a fresh PKCE `S256` pair and
+    /// a fresh state, keeping every other param (scope, hints, extras).
+    ///
+    /// A state and a verifier belong to a single attempt: never reuse
+    /// them on a retry, or a code leaked from the abandoned attempt could
+    /// be redeemed with them. Discard the previous verifier and state, and
+    /// stash the returned ones instead.
+    ///
+    /// Refs: <https://datatracker.ietf.org/doc/html/rfc9700#section-2.1.1>
+    #[cfg(feature = "client")]
+    pub fn regenerate(&self, previous: &Url) -> (Url, Oauth20PkceCodeVerifier, Oauth20State) {
+        let state = Oauth20State::default();
+        let challenge = Oauth20PkceCodeChallenge::default();
+
+        let mut params: BTreeMap<String, String> = previous
+            .query_pairs()
+            .map(|(k, v)| (k.into_owned(), v.into_owned()))
+            .collect();
+
+        params.insert(
+            "state".into(),
+            String::from_utf8_lossy(state.expose()).into_owned(),
+        );
+        params.insert("code_challenge".into(), challenge.encode().into_owned());
+        params.insert(
+            "code_challenge_method".into(),
+            challenge.method.as_str().into(),
+        );
+
+        let mut url = previous.clone();
+        url.query_pairs_mut().clear().extend_pairs(params);
+
+        (url, challenge.verifier, state)
+    }
+
+    /// Completes the grant: parses the redirection, checks its issuer
+    /// against `issuer` and its state against `expected_state`, then
+    /// builds the access token request carrying the code and the PKCE
+    /// `verifier`.
+    ///
+    /// Pass the verifier and the state [`Self::begin_authorization`]
+    /// returned. The returned coroutine is ready to be resumed against the
+    /// token endpoint.
+    ///
+    /// The issuer is verified as [`Oauth20AuthParams::verify_issuer`]
+    /// does, `required` being the
+    /// `authorization_response_iss_parameter_supported` value of the
+    /// server metadata; `None` skips the check, for servers whose issuer
+    /// is unknown.
+    ///
+    /// The code is marked as consumed once the request is built: a second
+    /// call with the same code fails with
+    /// [`Oauth20AuthCodeGrantError::CodeAlreadyUsed`].
+    ///
+    /// Refs: <https://datatracker.ietf.org/doc/html/rfc6749#section-4.1.2>,
+    ///       <https://datatracker.ietf.org/doc/html/rfc6749#section-4.1.3>,
+    ///       <https://datatracker.ietf.org/doc/html/rfc9207#section-2.4>
+    pub fn complete_authorization<'a>(
+        &mut self,
+        redirect_url: &'a Url,
+        expected_state: &Oauth20State,
+        issuer: Option<&Oauth20Issuer>,
+        required: bool,
+        verifier: &Oauth20PkceCodeVerifier,
+        token_endpoint: &Url,
+    ) -> Result<Oauth20AccessTokenRequest, Oauth20AuthCodeGrantError<'a>> {
+        check_endpoint(token_endpoint)?;
+
+        #[cfg(feature = "oauth21")]
+        self.check_redirect_url(redirect_url)?;
+
+        let params = Oauth20AuthParams::from(redirect_url);
+
+        if let Some(issuer) = issuer {
+            params.verify_issuer(issuer, required)?;
+        }
+
+        let code = params.validate(Some(expected_state))?;
+
+        let digest: [u8; 32] = Sha256::digest(code.as_bytes()).into();
+
+        if self.consumed_codes.contains(&digest) {
+            return Err(Oauth20AuthCodeGrantError::CodeAlreadyUsed);
+        }
+
+        let request = post_request(token_endpoint);
+
+        let params = Oauth20AccessTokenRequestParams {
+            code,
+            redirect_uri: Some(self.redirect_uri.clone()),
+            client_id: self.client_id.as_str().into(),
+            client_secret: None,
+            pkce_code_verifier: Some(Cow::Borrowed(verifier)),
+        };
+
+        let request = Oauth20AccessTokenRequest::new(request, params);
+        self.consumed_codes.insert(digest);
+
+        Ok(request)
+    }
+
+    /// Checks the redirection lands exactly on the redirection URI:
+    /// same scheme, host, port and path, with the query of the
+    /// registered URI (if any) as its leading params. No prefix nor
+    /// case-insensitive matching.
+    ///
+    /// Refs: <https://datatracker.ietf.org/doc/html/draft-ietf-oauth-v2-1#section-2.3.1>
+    #[cfg(feature = "oauth21")]
+    fn check_redirect_url<'a>(
+        &self,
+        redirect_url: &Url,
+    ) -> Result<(), Oauth20AuthCodeGrantError<'a>> {
+        let mut url = redirect_url.clone();
+        url.set_fragment(None);
+
+        // NOTE: the redirection is parsed, so compare it with the
+        // parsed URI rather than the one sent
+        let expected = self.redirect_uri.as_url().as_str();
+        let actual = url.as_str();
+
+        // NOTE: the server appends its params to the registered query,
+        // so only what follows must start a new param
+        let matches = match actual.strip_prefix(expected) {
+            Some("") => true,
+            Some(rest) if expected.contains('?') => rest.starts_with('&'),
+            Some(rest) => rest.starts_with('?'),
+            None => false,
+        };
+
+        if matches {
+            Ok(())
+        } else {
+            let url = redirect_url.to_string();
+            Err(Oauth20AuthCodeGrantError::RedirectUriMismatch(url))
+        }
+    }
+}
+
+#[cfg(all(test, feature = "client"))]
+mod tests {
+    use alloc::{string::String, vec::Vec};
+
+    use secrecy::ExposeSecret;
+    use url::Url;
+
+    use crate::{
+        rfc6749::{access_token_request::Oauth20AccessTokenRequestResult, auth_code_grant::*},
+        rfc7636::pkce::Oauth20PkceCodeChallengeMethod,
+        test_util::Oauth20MockServer,
+    };
+
+    #[test]
+    fn begin_authorization_binds_challenge_and_state() {
+        let redirect_uri = Oauth20RedirectUri::parse("http://127.0.0.1/cb").unwrap();
+        let endpoint = Url::parse("https://example.com/authorize").unwrap();
+        let grant = Oauth20AuthCodeGrant::new("client-id", redirect_uri.clone());
+        let (url, verifier, state) = grant.begin_authorization(["openid"], &endpoint).unwrap();
+
+        let param = |name: &str| {
+            url.query_pairs()
+                .find(|(k, _)| k == name)
+                .map(|(_, v)| v.into_owned())
+        };
+
+        let challenge = Oauth20PkceCodeChallengeMethod::Sha256.transform(verifier.expose());
+        assert_eq!(param("code_challenge").as_deref(), Some(challenge.as_ref()));
+        assert_eq!(param("code_challenge_method").as_deref(), Some("S256"));
+        assert_eq!(param("scope").as_deref(), Some("openid"));
+
+        let mut server =
+            Oauth20MockServer::new("client-id", redirect_uri.as_str().parse().unwrap());
+        let redirected = server.authorize(&url).unwrap();
+        let code = Oauth20AuthParams::from(&redirected).validate(Some(&state));
+        assert_eq!(code.unwrap(), server.code.as_str());
+    }
+
+    #[test]
+    fn begin_authorization_refuses_an_insecure_endpoint() {
+        let redirect_uri = Oauth20RedirectUri::parse("http://127.0.0.1/cb").unwrap();
+        let endpoint = Url::parse("http://example.com/authorize").unwrap();
+        let grant = Oauth20AuthCodeGrant::new("client-id", redirect_uri);
+
+        let res = grant.begin_authorization(["openid"], &endpoint);
+        assert!(matches!(
+            res,
+            Err(Oauth20EndpointError::InsecureEndpoint(_))
+        ));
+    }
+
+    #[test]
+    fn regenerate_rotates_state_and_challenge_only() {
+        let redirect_uri = Oauth20RedirectUri::parse("http://127.0.0.1/cb").unwrap();
+        let endpoint = Url::parse("https://example.com/authorize?tenant=x").unwrap();
+        let grant = Oauth20AuthCodeGrant::new("client-id", redirect_uri);
+        let (url, verifier, state) = grant
+            .begin_authorization(["openid", "email"], &endpoint)
+            .unwrap();
+        let (retry, new_verifier, new_state) = grant.regenerate(&url);
+
+        let param = |url: &Url, name: &str| {
+            url.query_pairs()
+                .find(|(k, _)| k == name)
+                .map(|(_, v)| v.into_owned())
+        };
+
+        assert_ne!(new_state.expose(), state.expose());
+        assert_ne!(new_verifier.expose(), verifier.expose());
+
+        let challenge = Oauth20PkceCodeChallengeMethod::Sha256.transform(new_verifier.expose());
+        assert_eq!(
+            param(&retry, "code_challenge").as_deref(),
+            Some(challenge.as_ref())
+        );
+        let new_state = String::from_utf8_lossy(new_state.expose());
+        assert_eq!(param(&retry, "state").as_deref(), Some(new_state.as_ref()));
+
+        for name in [
+            "client_id",
+            "redirect_uri",
+            "scope",
+            "tenant",
+            "response_type",
+        ] {
+            assert_eq!(param(&retry, name), param(&url, name), "{name}");
+        }
+    }
+
+    #[test]
+    fn complete_authorization_exchanges_the_code() {
+        let redirect_uri = Oauth20RedirectUri::parse("http://127.0.0.1/cb").unwrap();
+        let auth_endpoint = Url::parse("http://127.0.0.1/authorize").unwrap();
+        let token_endpoint = Url::parse("http://127.0.0.1/token").unwrap();
+        let mut grant = Oauth20AuthCodeGrant::new("client-id", redirect_uri.clone());
+        let (url, verifier, state) = grant
+            .begin_authorization(["openid"], &auth_endpoint)
+            .unwrap();
+
+        let mut server =
+            Oauth20MockServer::new("client-id", redirect_uri.as_str().parse().unwrap());
+        let redirected = server.authorize(&url).unwrap();
+
+        let other_state = Oauth20State::default();
+        let err = grant.complete_authorization(
+            &redirected,
+            &other_state,
+            None,
+            false,
+            &verifier,
+            &token_endpoint,
+        );
+        assert!(matches!(
+            err,
+            Err(Oauth20AuthCodeGrantError::Validate(
+                Oauth20AuthParamsValidationError::StateMismatch
+            ))
+        ));
+
+        let mut coroutine = grant
+            .complete_authorization(&redirected, &state, None, false, &verifier, &token_endpoint)
+            .unwrap();
+
+        let mut response = Vec::new();
+        let mut arg: Option<Vec<u8>> = None;
+
+        let res = loop {
+            match coroutine.resume(arg.take().as_deref()) {
+                Oauth20AccessTokenRequestResult::Ok(res) => break res,
+                Oauth20AccessTokenRequestResult::WantsRead => {
+                    arg = Some(core::mem::take(&mut response));
+                }
+                Oauth20AccessTokenRequestResult::WantsWrite(bytes) => {
+                    response = server.token(&bytes);
+                }
+                Oauth20AccessTokenRequestResult::Err(err) => panic!("{err}"),
+            }
+        };
+
+        let res = res.unwrap();
+        assert_eq!(res.access_token.expose_secret(), "mock-access-token");
+    }
+
+    #[test]
+    fn complete_authorization_refuses_a_used_code() {
+        let redirect_uri = Oauth20RedirectUri::parse("http://127.0.0.1/cb").unwrap();
+        let auth_endpoint = Url::parse("http://127.0.0.1/authorize").unwrap();
+        let token_endpoint = Url::parse("http://127.0.0.1/token").unwrap();
+        let mut grant = Oauth20AuthCodeGrant::new("client-id", redirect_uri.clone());
+        let (url, verifier, state) = grant
+            .begin_authorization(["openid"], &auth_endpoint)
+            .unwrap();
+
+        let mut server =
+            Oauth20MockServer::new("client-id", redirect_uri.as_str().parse().unwrap());
+        let redirected = server.authorize(&url).unwrap();
+
+        let res = grant.complete_authorization(
+            &redirected,
+            &state,
+            None,
+            false,
+            &verifier,
+            &token_endpoint,
+        );
+        assert!(res.is_ok());
+
+        let res = grant.complete_authorization(
+            &redirected,
+            &state,
+            None,
+            false,
+            &verifier,
+            &token_endpoint,
+        );
+        assert!(matches!(
+            res,
+            Err(Oauth20AuthCodeGrantError::CodeAlreadyUsed)
+        ));
+    }
+
+    #[test]
+    fn complete_authorization_verifies_the_issuer() {
+        let redirect_uri = Oauth20RedirectUri::parse("http://127.0.0.1/cb").unwrap();
+        let token_endpoint = Url::parse("http://127.0.0.1/token").unwrap();
+        let issuer = Oauth20Issuer::parse("https://as.example").unwrap();
+        let mut grant = Oauth20AuthCodeGrant::new("client-id", redirect_uri);
+        let verifier = Oauth20PkceCodeVerifier::default();
+        let state = Oauth20State::default();
+
+        let redirected = |query: &str| {
+            let mut url = Url::parse(&alloc::format!("http://127.0.0.1/cb?{query}")).unwrap();
+            let state = String::from_utf8_lossy(state.expose()).into_owned();
+            url.query_pairs_mut().append_pair("state", &state);
+            url
+        };
+
+        let url = redirected("code=a&iss=https%3A%2F%2Fevil.example");
+        let res = grant.complete_authorization(
+            &url,
+            &state,
+            Some(&issuer),
+            false,
+            &verifier,
+            &token_endpoint,
+        );
+        assert!(matches!(
+            res,
+            Err(Oauth20AuthCodeGrantError::Validate(
+                Oauth20AuthParamsValidationError::IssuerMismatch
+            ))
+        ));
+
+        let url = redirected("code=b");
+        let res = grant.complete_authorization(
+            &url,
+            &state,
+            Some(&issuer),
+            true,
+            &verifier,
+            &token_endpoint,
+        );
+        assert!(matches!(
+            res,
+            Err(Oauth20AuthCodeGrantError::Validate(
+                Oauth20AuthParamsValidationError::IssuerMissing
+            ))
+        ));
+
+        let url = redirected("code=c&iss=https%3A%2F%2Fas.example");
+        let res = grant.complete_authorization(
+            &url,
+            &state,
+            Some(&issuer),
+            true,
+            &verifier,
+            &token_endpoint,
+        );
+        assert!(res.is_ok());
+    }
+
+    #[cfg(feature = "oauth21")]
+    #[test]
+    fn complete_authorization_requires_exact_redirect_uri() {
+        let redirect_uri = Oauth20RedirectUri::parse("http://127.0.0.1/cb?app=x").unwrap();
+        let token_endpoint = Url::parse("http://127.0.0.1/token").unwrap();
+        let mut grant = Oauth20AuthCodeGrant::new("client-id", redirect_uri);
+        let verifier = Oauth20PkceCodeVerifier::default();
+        let state = Oauth20State::default();
+        let state_param = String::from_utf8_lossy(state.expose()).into_owned();
+
+        for redirected in [
+            "http://127.0.0.1/cb/evil?app=x&code=c",
+            "http://127.0.0.1/cb?app=xy&code=c",
+            "http://127.0.0.1:8080/cb?app=x&code=c",
+            "http://127.0.0.1/CB?app=x&code=c",
+        ] {
+            let redirected =
+                Url::parse(&alloc::format!("{redirected}&state={state_param}")).unwrap();
+            let err = grant.complete_authorization(
+                &redirected,
+                &state,
+                None,
+                false,
+                &verifier,
+                &token_endpoint,
+            );
+            assert!(
+                matches!(err, Err(Oauth20AuthCodeGrantError::RedirectUriMismatch(_))),
+                "{redirected}"
+            );
+        }
+
+        let mut redirected = Url::parse("http://127.0.0.1/cb?app=x&code=c").unwrap();
+        redirected
+            .query_pairs_mut()
+            .append_pair("state", &state_param);
+        let res = grant.complete_authorization(
+            &redirected,
+            &state,
+            None,
+            false,
+            &verifier,
+            &token_endpoint,
+        );
+        assert!(res.is_ok());
+    }
+}
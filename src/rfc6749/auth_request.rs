@@ -2,17 +2,56 @@
 //!
 //! Builds the authorization URL the end user browses to, opening the
 //! authorization code grant; the redirect back is parsed by the auth
-//! response sibling module.
+//! response sibling module. The params can also travel as a signed
+//! request object (RFC 9101); signing it is left to the caller's JOSE
+//! library, io-oauth stays free of key handling.
 
 use alloc::{
     borrow::Cow,
     collections::{BTreeMap, BTreeSet},
-    string::String,
+    string::{String, ToString},
+    vec::Vec,
 };
 
-use url::Url;
+use log::debug;
+use serde_json::{Map, Value};
+use thiserror::Error;
+use url::{Url, form_urlencoded::Serializer};
 
-use crate::{rfc6749::state::Oauth20State, rfc7636::pkce::Oauth20PkceCodeChallenge};
+use crate::{
+    rfc6749::{
+        redirect_uri::Oauth20RedirectUri,
+        scope::{
+            Oauth20ScopeDelimiter, collect_scope, into_owned_scope, is_valid_scope_token,
+            join_scope,
+        },
+        state::Oauth20State,
+    },
+    rfc7636::pkce::Oauth20PkceCodeChallenge,
+};
+
+/// Problems found by [`Oauth20AuthRequestParams::validate`].
+#[derive(Debug, Eq, Error, PartialEq)]
+pub enum Oauth20AuthRequestParamsError {
+    /// The client identifier is empty.
+    #[error("OAuth 2.0 client identifier is empty")]
+    EmptyClientId,
+    /// The redirection URI carries a fragment.
+    #[error("OAuth 2.0 redirection URI `{0}` must not have a fragment")]
+    RedirectUriFragment(String),
+    /// A scope token does not match the scope token grammar.
+    #[error("Invalid OAuth 2.0 scope token `{0}`")]
+    InvalidScopeToken(String),
+    /// The PKCE code verifier is not 43 to 128 bytes long.
+    #[error("Invalid PKCE code verifier length {0} (expected 43 to 128)")]
+    InvalidPkceCodeVerifierLength(usize),
+    /// The params carry no PKCE code challenge, which OAuth 2.1
+    /// requires on every authorization request.
+    ///
+    /// Only reported with the `oauth21` feature.
+    #[error("OAuth 2.1 authorization request is missing a PKCE code challenge")]
+    MissingPkceCodeChallenge,
+}
 
 /// The authorization request parameters from the authorization code grant.
 ///
@@ -25,17 +64,22 @@ pub struct Oauth20AuthRequestParams<'a> {
     /// The absolute URI the server redirects the user-agent back to.
     ///
     /// Refs: <https://datatracker.ietf.org/doc/html/rfc6749#section-3.1.2>
-    pub redirect_uri: Option<Cow<'a, str>>,
+    pub redirect_uri: Option<Oauth20RedirectUri>,
     /// The requested access scope, as space-delimited tokens.
     ///
     /// Refs: <https://datatracker.ietf.org/doc/html/rfc6749#section-3.3>
-    // TODO: validate scope tokens?
     pub scope: BTreeSet<Cow<'a, str>>,
+    /// The separator between scope tokens, spaces unless the provider
+    /// expects commas.
+    pub scope_delimiter: Oauth20ScopeDelimiter,
     /// The opaque CSRF value echoed back on the callback.
     ///
     /// Refs: <https://datatracker.ietf.org/doc/html/rfc6749#section-10.12>
     pub state: Option<Cow<'a, Oauth20State>>,
     /// The PKCE code challenge, when the flow uses PKCE (RFC 7636).
+    ///
+    /// Required by OAuth 2.1 servers, which otherwise answer
+    /// `invalid_request`.
     pub pkce_code_challenge: Option<Cow<'a, Oauth20PkceCodeChallenge>>,
     /// Extra query parameters appended to the authorization URL.
     ///
@@ -46,13 +90,273 @@ pub struct Oauth20AuthRequestParams<'a> {
     pub extras: BTreeMap<Cow<'a, str>, Cow<'a, str>>,
 }
 
-impl Oauth20AuthRequestParams<'_> {
+/// The authorization request params, owning every value.
+pub type Oauth20AuthRequestParamsOwned = Oauth20AuthRequestParams<'static>;
+
+impl<'a> Oauth20AuthRequestParams<'a> {
+    /// Converts every borrowed value into an owned one, so the params
+    /// outlive the strings they were built from.
+    pub fn into_owned(self) -> Oauth20AuthRequestParams<'static> {
+        Oauth20AuthRequestParams {
+            client_id: Cow::Owned(self.client_id.into_owned()),
+            redirect_uri: self.redirect_uri,
+            scope: into_owned_scope(self.scope),
+            scope_delimiter: self.scope_delimiter,
+            state: self.state.map(|state| Cow::Owned(state.into_owned())),
+            pkce_code_challenge: self
+                .pkce_code_challenge
+                .map(|challenge| Cow::Owned(challenge.into_owned())),
+            extras: self
+                .extras
+                .into_iter()
+                .map(|(k, v)| (Cow::Owned(k.into_owned()), Cow::Owned(v.into_owned())))
+                .collect(),
+        }
+    }
+
+    /// Requests authentication context class references, most preferred
+    /// first, as OpenID Connect step-up authentication does.
+    ///
+    /// Inserted into `extras` as the space-delimited `acr_values`; no
+    /// values insert nothing.
+    ///
+    /// Refs: <https://openid.net/specs/openid-connect-core-1_0.html#AuthRequest>
+    pub fn with_acr_values<T: AsRef<str>>(
+        mut self,
+        acr_values: impl IntoIterator<Item = T>,
+    ) -> Self {
+        let mut values = String::new();
+
+        for value in acr_values {
+            if !values.is_empty() {
+                values.push(' ');
+            }
+            values.push_str(value.as_ref());
+        }
+
+        if !values.is_empty() {
+            self.extras.insert("acr_values".into(), values.into());
+        }
+
+        self
+    }
+
+    /// Requests individual claims with the OpenID Connect `claims` request
+    /// object.
+    ///
+    /// Inserted into `extras` as compact JSON, URL-encoded with the rest of
+    /// the query.
+    ///
+    /// Refs: <https://openid.net/specs/openid-connect-core-1_0.html#ClaimsParameter>
+    pub fn with_claims(mut self, claims: &Value) -> Self {
+        self.extras
+            .insert("claims".into(), claims.to_string().into());
+        self
+    }
+
+    /// Requests the given scope tokens, replacing any previous ones.
+    ///
+    /// Refs: <https://datatracker.ietf.org/doc/html/rfc6749#section-3.3>
+    pub fn with_scope<S: Into<Cow<'a, str>>>(
+        mut self,
+        tokens: impl IntoIterator<Item = S>,
+    ) -> Self {
+        self.scope = collect_scope(tokens);
+        self
+    }
+
+    /// Hints the account to sign in with, an email address or a user
+    /// name, so the provider pre-fills or skips its account picker.
+    ///
+    /// Born in OpenID Connect, yet accepted on plain OAuth 2.0 by
+    /// Microsoft and Google. Inserted into `extras` as `login_hint`.
+    ///
+    /// Refs: <https://openid.net/specs/openid-connect-core-1_0.html#AuthRequest>
+    pub fn with_login_hint(mut self, login_hint: impl Into<Cow<'a, str>>) -> Self {
+        self.extras.insert("login_hint".into(), login_hint.into());
+        self
+    }
+
+    /// Hints the tenant domain of the account, so Microsoft Entra ID
+    /// sends the user straight to its federated sign-in page.
+    ///
+    /// A Microsoft extension, inserted into `extras` as `domain_hint`.
+    ///
+    /// Refs: <https://learn.microsoft.com/en-us/entra/identity-platform/v2-oauth2-auth-code-flow#request-an-authorization-code>
+    pub fn with_domain_hint(mut self, domain_hint: impl Into<Cow<'a, str>>) -> Self {
+        self.extras.insert("domain_hint".into(), domain_hint.into());
+        self
+    }
+
+    /// Checks the params before the URL is shown to the user, reporting
+    /// every problem found rather than the first one.
+    ///
+    /// Scope tokens are checked once trimmed, empty ones being skipped on
+    /// serialization anyway.
+    ///
+    /// Refs: <https://datatracker.ietf.org/doc/html/rfc6749#section-3.1.2>,
+    ///       <https://datatracker.ietf.org/doc/html/rfc6749#section-3.3>,
+    ///       <https://datatracker.ietf.org/doc/html/rfc7636#section-4.1>
+    pub fn validate(&self) -> Result<(), Vec<Oauth20AuthRequestParamsError>> {
+        let mut errors = Vec::new();
+
+        if self.client_id.is_empty() {
+            errors.push(Oauth20AuthRequestParamsError::EmptyClientId);
+        }
+
+        if let Some(uri) = &self.redirect_uri
+            && uri.as_str().contains('#')
+        {
+            let uri = uri.to_string();
+            errors.push(Oauth20AuthRequestParamsError::RedirectUriFragment(uri));
+        }
+
+        for token in &self.scope {
+            let token = token.trim();
+
+            if !token.is_empty() && !is_valid_scope_token(token) {
+                let token = token.to_string();
+                errors.push(Oauth20AuthRequestParamsError::InvalidScopeToken(token));
+            }
+        }
+
+        if let Some(challenge) = &self.pkce_code_challenge
+            && !challenge.verifier.is_valid_length()
+        {
+            let len = challenge.verifier.len();
+            errors.push(Oauth20AuthRequestParamsError::InvalidPkceCodeVerifierLength(len));
+        }
+
+        #[cfg(feature = "oauth21")]
+        if self.pkce_code_challenge.is_none() {
+            errors.push(Oauth20AuthRequestParamsError::MissingPkceCodeChallenge);
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+
+    /// Validates the params, then builds the authorization URL.
+    ///
+    /// With the `oauth21` feature, params without a PKCE code challenge
+    /// are refused rather than turned into a URL.
+    ///
+    /// Refs: <https://datatracker.ietf.org/doc/html/draft-ietf-oauth-v2-1#section-4.1.1>
+    // SAFETY: exposes the state and the PKCE code verifier
+    pub fn try_build_url(&self, endpoint: &Url) -> Result<Url, Vec<Oauth20AuthRequestParamsError>> {
+        self.validate()?;
+        Ok(self.build_url_unchecked(endpoint))
+    }
+
     /// Builds the authorization URL from the typed fields.
     ///
     /// `extras` override the typed defaults, and query parameters already
     /// present in `endpoint` take final precedence.
+    ///
+    /// Deprecated with the `oauth21` feature, since it builds a URL
+    /// without the PKCE code challenge OAuth 2.1 requires: use
+    /// [`Self::try_build_url`] instead.
     // SAFETY: exposes the state and the PKCE code verifier
+    #[cfg_attr(
+        feature = "oauth21",
+        deprecated(note = "skips the OAuth 2.1 checks, use `try_build_url`")
+    )]
     pub fn build_url(&self, endpoint: &Url) -> Url {
+        self.build_url_unchecked(endpoint)
+    }
+
+    /// Builds the authorization URL without validating the params.
+    // SAFETY: exposes the state and the PKCE code verifier
+    pub(crate) fn build_url_unchecked(&self, endpoint: &Url) -> Url {
+        let mut params = self.params();
+
+        for (k, v) in endpoint.query_pairs() {
+            params.insert(k.into_owned(), v.into_owned());
+        }
+
+        let mut url = endpoint.clone();
+        let mut qm = url.query_pairs_mut();
+        qm.clear();
+        qm.extend_pairs(params.iter().map(|(k, v)| (k.as_str(), v.as_str())));
+        drop(qm);
+        url
+    }
+
+    /// Serializes the params into a form-urlencoded body, to POST to the
+    /// authorization endpoint instead of redirecting to a URL.
+    ///
+    /// For user-agents submitting an auto-posting HTML form, keeping the
+    /// params (a large `claims`, a long scope) out of URLs and their length
+    /// limits. Servers must accept both methods on the authorization
+    /// endpoint under OpenID Connect, RFC 6749 only requires GET: check the
+    /// provider supports POST first. Same params as [`Self::build_url`],
+    /// without the endpoint query.
+    ///
+    /// Refs: <https://datatracker.ietf.org/doc/html/rfc6749#section-3.1>,
+    ///       <https://openid.net/specs/openid-connect-core-1_0.html#AuthRequest>
+    // SAFETY: exposes the state and the PKCE code verifier
+    pub fn to_form_post_body(&self) -> Vec<u8> {
+        let mut serializer = Serializer::new(String::new());
+
+        for (k, v) in self.params() {
+            serializer.append_pair(&k, &v);
+        }
+
+        serializer.finish().into_bytes()
+    }
+
+    /// Returns the claims of a request object (JAR) mirroring the params.
+    ///
+    /// The caller adds `iss` and `aud`, signs the object into a JWT, then
+    /// passes it to [`Self::build_jar_url`]. The `claims` parameter is kept
+    /// as a JSON object, every other one as a string.
+    ///
+    /// Refs: <https://datatracker.ietf.org/doc/html/rfc9101#section-4>
+    // SAFETY: exposes the state and the PKCE code verifier
+    pub fn request_object_claims(&self) -> Map<String, Value> {
+        self.params()
+            .into_iter()
+            .map(|(k, v)| {
+                let v = match k.as_str() {
+                    "claims" => serde_json::from_str(&v).unwrap_or(Value::String(v)),
+                    _ => Value::String(v),
+                };
+                (k, v)
+            })
+            .collect()
+    }
+
+    /// Builds the authorization URL passing the params by value in a signed
+    /// request object (JAR), as FAPI requires.
+    ///
+    /// Only `client_id` and `request` are sent next to the query parameters
+    /// already present in `endpoint`: the server reads every other param
+    /// from the object, built from [`Self::request_object_claims`].
+    ///
+    /// Refs: <https://datatracker.ietf.org/doc/html/rfc9101#section-5>
+    pub fn build_jar_url(&self, endpoint: &Url, request_object: &str) -> Url {
+        let mut params: BTreeMap<String, String> = BTreeMap::new();
+
+        params.insert("client_id".into(), self.client_id.as_ref().into());
+        params.insert("request".into(), request_object.into());
+
+        for (k, v) in endpoint.query_pairs() {
+            params.insert(k.into_owned(), v.into_owned());
+        }
+
+        let mut url = endpoint.clone();
+        let mut qm = url.query_pairs_mut();
+        qm.clear();
+        qm.extend_pairs(params.iter().map(|(k, v)| (k.as_str(), v.as_str())));
+        drop(qm);
+        url
+    }
+
+    /// Collects the typed fields, then `extras` on top.
+    fn params(&self) -> BTreeMap<String, String> {
         let mut params: BTreeMap<String, String> = BTreeMap::new();
 
         params.insert("response_type".into(), "code".into());
@@ -66,43 +370,292 @@ impl Oauth20AuthRequestParams<'_> {
         }
 
         if let Some(uri) = &self.redirect_uri {
-            params.insert("redirect_uri".into(), uri.as_ref().into());
+            params.insert("redirect_uri".into(), uri.as_str().into());
         }
 
-        if !self.scope.is_empty() {
-            let mut scope = String::new();
-            let mut glue = "";
-
-            for token in &self.scope {
-                scope.push_str(glue);
-                scope.push_str(token);
-                glue = " ";
-            }
+        let scope = join_scope(&self.scope, self.scope_delimiter);
 
+        if !scope.is_empty() {
             params.insert("scope".into(), scope);
         }
 
-        if let Some(challenge) = &self.pkce_code_challenge {
-            params.insert("code_challenge".into(), challenge.encode().into_owned());
-            params.insert(
-                "code_challenge_method".into(),
-                challenge.method.as_str().into(),
-            );
+        match &self.pkce_code_challenge {
+            Some(challenge) => {
+                params.insert("code_challenge".into(), challenge.encode().into_owned());
+                params.insert(
+                    "code_challenge_method".into(),
+                    challenge.method.as_str().into(),
+                );
+            }
+            None => {
+                debug!("authorization request without PKCE, servers requiring it reject it");
+            }
         }
 
         for (k, v) in &self.extras {
             params.insert(k.as_ref().into(), v.as_ref().into());
         }
 
-        for (k, v) in endpoint.query_pairs() {
-            params.insert(k.into_owned(), v.into_owned());
+        params
+    }
+}
+
+#[cfg(test)]
+#[cfg_attr(feature = "oauth21", allow(deprecated))]
+mod tests {
+    use alloc::{collections::BTreeMap, string::String};
+
+    use serde_json::json;
+    use url::Url;
+
+    use crate::rfc6749::auth_request::*;
+
+    #[test]
+    fn acr_values_and_claims_are_url_encoded() {
+        let claims = json!({"id_token": {"acr": {"essential": true}}});
+        let params = Oauth20AuthRequestParams {
+            client_id: "client-id".into(),
+            redirect_uri: None,
+            scope: Default::default(),
+            scope_delimiter: Default::default(),
+            state: None,
+            pkce_code_challenge: None,
+            extras: BTreeMap::new(),
         }
+        .with_acr_values([
+            "urn:mace:incommon:iap:silver",
+            "urn:mace:incommon:iap:bronze",
+        ])
+        .with_claims(&claims);
 
-        let mut url = endpoint.clone();
-        let mut qm = url.query_pairs_mut();
-        qm.clear();
-        qm.extend_pairs(params.iter().map(|(k, v)| (k.as_str(), v.as_str())));
-        drop(qm);
-        url
+        let url = params.build_url(&Url::parse("https://example.com/authorize").unwrap());
+        let query = url.query().unwrap();
+        assert!(query.contains(
+            "acr_values=urn%3Amace%3Aincommon%3Aiap%3Asilver+urn%3Amace%3Aincommon%3Aiap%3Abronze"
+        ));
+        assert!(query.contains(
+            "claims=%7B%22id_token%22%3A%7B%22acr%22%3A%7B%22essential%22%3Atrue%7D%7D%7D"
+        ));
+    }
+
+    #[test]
+    fn empty_acr_values_are_omitted() {
+        let params = Oauth20AuthRequestParams {
+            client_id: "client-id".into(),
+            redirect_uri: None,
+            scope: Default::default(),
+            scope_delimiter: Default::default(),
+            state: None,
+            pkce_code_challenge: None,
+            extras: BTreeMap::new(),
+        }
+        .with_acr_values(core::iter::empty::<&str>());
+
+        assert!(!params.extras.contains_key("acr_values"));
+
+        let url = params.build_url(&Url::parse("https://example.com/authorize").unwrap());
+        assert!(!url.query().unwrap().contains("acr_values"));
+    }
+
+    #[test]
+    fn owned_params_outlive_their_source() {
+        let params: Oauth20AuthRequestParamsOwned = {
+            let client_id = String::from("client-id");
+            let scope = String::from("openid");
+
+            Oauth20AuthRequestParams {
+                client_id: client_id.as_str().into(),
+                redirect_uri: None,
+                scope: [scope.as_str().into()].into(),
+                scope_delimiter: Default::default(),
+                state: None,
+                pkce_code_challenge: None,
+                extras: BTreeMap::from([(scope.as_str().into(), client_id.as_str().into())]),
+            }
+            .into_owned()
+        };
+
+        let url = params.build_url(&Url::parse("https://example.com/authorize").unwrap());
+        let query = url.query().unwrap();
+        assert!(query.contains("client_id=client-id"), "{query}");
+        assert!(query.contains("scope=openid"), "{query}");
+        assert!(query.contains("openid=client-id"), "{query}");
+    }
+
+    #[test]
+    fn login_and_domain_hints_are_url_encoded() {
+        let params = Oauth20AuthRequestParams {
+            client_id: "client-id".into(),
+            redirect_uri: None,
+            scope: Default::default(),
+            scope_delimiter: Default::default(),
+            state: None,
+            pkce_code_challenge: None,
+            extras: BTreeMap::new(),
+        }
+        .with_login_hint("user+tag@contoso.com")
+        .with_domain_hint("contoso.com");
+
+        let url = params.build_url(&Url::parse("https://example.com/authorize").unwrap());
+        let query = url.query().unwrap();
+        assert!(
+            query.contains("login_hint=user%2Btag%40contoso.com"),
+            "{query}"
+        );
+        assert!(query.contains("domain_hint=contoso.com"), "{query}");
+    }
+
+    #[test]
+    fn scope_builds_from_any_strings() {
+        let params = Oauth20AuthRequestParams {
+            client_id: "client-id".into(),
+            redirect_uri: None,
+            scope: Default::default(),
+            scope_delimiter: Default::default(),
+            state: None,
+            pkce_code_challenge: None,
+            extras: BTreeMap::new(),
+        }
+        .with_scope(["openid", "email"]);
+
+        let url = params.build_url(&Url::parse("https://example.com/authorize").unwrap());
+        let query = url.query().unwrap();
+        assert!(query.contains("scope=email+openid"), "{query}");
+    }
+
+    #[test]
+    fn jar_url_carries_client_id_and_request_object() {
+        let claims = json!({"userinfo": {"email": null}});
+        let params = Oauth20AuthRequestParams {
+            client_id: "client-id".into(),
+            redirect_uri: Some("https://client.example.com/cb".parse().unwrap()),
+            scope: ["openid".into()].into(),
+            scope_delimiter: Default::default(),
+            state: None,
+            pkce_code_challenge: None,
+            extras: BTreeMap::new(),
+        }
+        .with_claims(&claims);
+
+        let object = params.request_object_claims();
+        assert_eq!(object["response_type"], "code");
+        assert_eq!(object["client_id"], "client-id");
+        assert_eq!(object["redirect_uri"], "https://client.example.com/cb");
+        assert_eq!(object["scope"], "openid");
+        assert_eq!(object["claims"], claims);
+
+        let endpoint = Url::parse("https://example.com/authorize?tenant=x").unwrap();
+        let url = params.build_jar_url(&endpoint, "eyJhbGciOiJSUzI1NiJ9.e30.sig");
+        assert_eq!(
+            url.query(),
+            Some("client_id=client-id&request=eyJhbGciOiJSUzI1NiJ9.e30.sig&tenant=x")
+        );
+    }
+
+    #[test]
+    fn validate_reports_every_problem() {
+        use alloc::vec;
+        use core::str::FromStr;
+
+        use crate::rfc7636::pkce::{Oauth20PkceCodeChallengeMethod, Oauth20PkceCodeVerifier};
+
+        let params = Oauth20AuthRequestParams {
+            client_id: "".into(),
+            redirect_uri: "http://127.0.0.1/cb#frag".parse().ok(),
+            scope: ["read", "", "wr\"ite"]
+                .into_iter()
+                .map(Into::into)
+                .collect(),
+            scope_delimiter: Default::default(),
+            state: None,
+            pkce_code_challenge: Some(Cow::Owned(Oauth20PkceCodeChallenge {
+                method: Oauth20PkceCodeChallengeMethod::Sha256,
+                verifier: Oauth20PkceCodeVerifier::from_str("short").unwrap(),
+            })),
+            extras: BTreeMap::new(),
+        };
+
+        assert_eq!(
+            params.validate(),
+            Err(vec![
+                Oauth20AuthRequestParamsError::EmptyClientId,
+                Oauth20AuthRequestParamsError::RedirectUriFragment(
+                    "http://127.0.0.1/cb#frag".into()
+                ),
+                Oauth20AuthRequestParamsError::InvalidScopeToken("wr\"ite".into()),
+                Oauth20AuthRequestParamsError::InvalidPkceCodeVerifierLength(5),
+            ])
+        );
+
+        let params = Oauth20AuthRequestParams {
+            client_id: "client-id".into(),
+            redirect_uri: "http://127.0.0.1/cb".parse().ok(),
+            scope: ["read"].into_iter().map(Into::into).collect(),
+            scope_delimiter: Default::default(),
+            state: None,
+            pkce_code_challenge: None,
+            extras: BTreeMap::new(),
+        };
+
+        #[cfg(not(feature = "oauth21"))]
+        assert_eq!(params.validate(), Ok(()));
+        #[cfg(feature = "oauth21")]
+        assert_eq!(
+            params.validate(),
+            Err(vec![
+                Oauth20AuthRequestParamsError::MissingPkceCodeChallenge
+            ])
+        );
+    }
+
+    #[cfg(all(feature = "oauth21", feature = "client"))]
+    #[test]
+    fn try_build_url_requires_pkce_challenge() {
+        use alloc::vec;
+
+        let endpoint = Url::parse("https://example.com/authorize").unwrap();
+        let mut params = Oauth20AuthRequestParams {
+            client_id: "client-id".into(),
+            redirect_uri: "http://127.0.0.1/cb".parse().ok(),
+            scope: BTreeSet::new(),
+            scope_delimiter: Default::default(),
+            state: None,
+            pkce_code_challenge: None,
+            extras: BTreeMap::new(),
+        };
+
+        assert_eq!(
+            params.try_build_url(&endpoint),
+            Err(vec![
+                Oauth20AuthRequestParamsError::MissingPkceCodeChallenge
+            ])
+        );
+
+        params.pkce_code_challenge = Some(Cow::Owned(Oauth20PkceCodeChallenge::default()));
+        let url = params.try_build_url(&endpoint).unwrap();
+        assert!(url.query_pairs().any(|(k, _)| k == "code_challenge"));
+    }
+
+    #[test]
+    fn form_post_body_matches_url_query() {
+        let params = Oauth20AuthRequestParams {
+            client_id: "client id".into(),
+            redirect_uri: "http://127.0.0.1/cb".parse().ok(),
+            scope: ["openid", "email"].into_iter().map(Into::into).collect(),
+            scope_delimiter: Default::default(),
+            state: None,
+            pkce_code_challenge: None,
+            extras: BTreeMap::new(),
+        };
+
+        let body = params.to_form_post_body();
+        assert_eq!(
+            body,
+            b"client_id=client+id&redirect_uri=http%3A%2F%2F127.0.0.1%2Fcb\
+              &response_type=code&scope=email+openid"
+        );
+
+        let url = params.build_url(&Url::parse("https://example.com/authorize").unwrap());
+        assert_eq!(url.query().unwrap().as_bytes(), body);
     }
 }
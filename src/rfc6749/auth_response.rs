@@ -1,11 +1,18 @@
 //! Authorization response (RFC 6749 section 4.1.2).
 //!
 //! Parses the redirected URL back into an authorization code or an
-//! error, and validates the echoed CSRF state.
+//! error, and validates the echoed CSRF state and, against mix-up
+//! attacks, the issuer identifier (RFC 9207).
+//!
+//! The response params travel in the query, or in the fragment when
+//! the request asked for `response_mode=fragment` (OAuth 2.0 Multiple
+//! Response Type Encoding Practices), as some servers also do for
+//! errors: the fragment is read when the query carries neither a
+//! `code` nor an `error`. The implicit grant itself stays omitted.
 
-use core::fmt;
+use core::{fmt, time::Duration};
 
-use alloc::borrow::Cow;
+use alloc::{borrow::Cow, boxed::Box};
 
 use log::debug;
 use serde::{
@@ -13,9 +20,15 @@ use serde::{
     de::value::{CowStrDeserializer, Error},
 };
 use thiserror::Error as ThisError;
-use url::Url;
+use url::{Url, form_urlencoded::parse};
 
-use crate::rfc6749::state::Oauth20State;
+use crate::rfc6749::{issuer::Oauth20Issuer, state::Oauth20State};
+
+/// The maximum lifetime of an authorization code the specification
+/// recommends.
+///
+/// Refs: <https://datatracker.ietf.org/doc/html/rfc6749#section-4.1.2>
+pub const OAUTH20_AUTH_CODE_MAX_AGE: Duration = Duration::from_secs(600);
 
 /// The parsed authorization response: an authorization code, or an error.
 pub enum Oauth20AuthParams<'a> {
@@ -36,7 +49,7 @@ impl<'a> Oauth20AuthParams<'a> {
         expected_state: Option<&Oauth20State>,
     ) -> Result<Cow<'a, str>, Oauth20AuthParamsValidationError<'a>> {
         match self {
-            Self::Error(err) => Err(Oauth20AuthParamsValidationError::Server(err)),
+            Self::Error(err) => Err(Oauth20AuthParamsValidationError::Server(Box::new(err))),
             Self::Success(success) => {
                 if let Some(expected) = expected_state {
                     match &success.state {
@@ -51,6 +64,39 @@ impl<'a> Oauth20AuthParams<'a> {
             }
         }
     }
+
+    /// Returns the issuer identifier the server sent, if any.
+    ///
+    /// Refs: <https://datatracker.ietf.org/doc/html/rfc9207#section-2>
+    pub fn iss(&self) -> Option<&str> {
+        match self {
+            Self::Success(success) => success.iss.as_deref(),
+            Self::Error(err) => err.iss.as_deref(),
+        }
+    }
+
+    /// Verifies the issuer identifier against the expected issuer.
+    ///
+    /// A present `iss` must match `expected` exactly (no normalization,
+    /// per RFC 9207 §2.4), hence the [`Oauth20Issuer`]. An absent one is
+    /// only accepted when `required` is `false`: pass the
+    /// `authorization_response_iss_parameter_supported` value of the
+    /// server metadata, since a server advertising it must always send
+    /// `iss`.
+    ///
+    /// Refs: <https://datatracker.ietf.org/doc/html/rfc9207#section-2.4>
+    pub fn verify_issuer(
+        &self,
+        expected: &Oauth20Issuer,
+        required: bool,
+    ) -> Result<(), Oauth20AuthParamsValidationError<'a>> {
+        match self.iss() {
+            Some(iss) if expected == iss => Ok(()),
+            Some(_) => Err(Oauth20AuthParamsValidationError::IssuerMismatch),
+            None if required => Err(Oauth20AuthParamsValidationError::IssuerMissing),
+            None => Ok(()),
+        }
+    }
 }
 
 /// Errors returned by [`Oauth20AuthParams::validate`].
@@ -58,7 +104,7 @@ impl<'a> Oauth20AuthParams<'a> {
 pub enum Oauth20AuthParamsValidationError<'a> {
     /// The authorization server returned an error response.
     #[error("Authorization error: {_0}")]
-    Server(Oauth20AuthErrorParams<'a>),
+    Server(Box<Oauth20AuthErrorParams<'a>>),
     /// A state was expected in the response but none was returned.
     #[error("Authorization state missing from response")]
     StateMissing,
@@ -66,18 +112,36 @@ pub enum Oauth20AuthParamsValidationError<'a> {
     /// one (CSRF mismatch).
     #[error("Authorization state mismatch")]
     StateMismatch,
+    /// An issuer identifier was required in the response but none was
+    /// returned.
+    #[error("Authorization issuer missing from response")]
+    IssuerMissing,
+    /// The issuer identifier returned by the server does not match the
+    /// expected one (mix-up attack).
+    #[error("Authorization issuer mismatch")]
+    IssuerMismatch,
 }
 
 impl<'a> From<&'a Url> for Oauth20AuthParams<'a> {
     fn from(url: &'a Url) -> Self {
         let mut code = None;
         let mut state = None;
+        let mut iss = None;
 
         let mut error = None;
         let mut error_description = None;
         let mut error_uri = None;
 
-        for (key, val) in url.query_pairs() {
+        let is_response = |(key, _): (Cow<str>, Cow<str>)| {
+            key.eq_ignore_ascii_case("code") || key.eq_ignore_ascii_case("error")
+        };
+
+        let pairs = match url.fragment() {
+            Some(fragment) if !url.query_pairs().any(is_response) => parse(fragment.as_bytes()),
+            _ => url.query_pairs(),
+        };
+
+        for (key, val) in pairs {
             match &key {
                 key if key.eq_ignore_ascii_case("code") => {
                     code = Some(val);
@@ -89,6 +153,9 @@ impl<'a> From<&'a Url> for Oauth20AuthParams<'a> {
                         Err(err) => debug!("skip invalid state: {err}"),
                     }
                 }
+                key if key.eq_ignore_ascii_case("iss") => {
+                    iss = Some(val);
+                }
                 key if key.eq_ignore_ascii_case("error") => {
                     let deserializer = CowStrDeserializer::<Error>::new(val);
                     match Oauth20AuthErrorCode::deserialize(deserializer) {
@@ -111,7 +178,13 @@ impl<'a> From<&'a Url> for Oauth20AuthParams<'a> {
         }
 
         if let Some(code) = code {
-            let params = Oauth20AuthSuccessParams { code, state };
+            let received_at = unix_now();
+            let params = Oauth20AuthSuccessParams {
+                code,
+                state,
+                iss,
+                received_at,
+            };
             return Oauth20AuthParams::Success(params);
         }
 
@@ -119,6 +192,7 @@ impl<'a> From<&'a Url> for Oauth20AuthParams<'a> {
             error: error.unwrap_or(Oauth20AuthErrorCode::InvalidRequest),
             error_description,
             error_uri,
+            iss,
         };
 
         Oauth20AuthParams::Error(params)
@@ -128,7 +202,7 @@ impl<'a> From<&'a Url> for Oauth20AuthParams<'a> {
 /// The successful authorization response, carrying the code (and state).
 ///
 /// Refs: <https://datatracker.ietf.org/doc/html/rfc6749#section-4.1.2>
-#[derive(Clone, Debug, Deserialize)]
+#[derive(Clone, Deserialize)]
 pub struct Oauth20AuthSuccessParams<'a> {
     /// The authorization code to exchange for an access token.
     ///
@@ -138,6 +212,59 @@ pub struct Oauth20AuthSuccessParams<'a> {
     ///
     /// Refs: <https://datatracker.ietf.org/doc/html/rfc6749#section-10.12>
     pub state: Option<Cow<'a, Oauth20State>>,
+    /// The issuer identifier of the server that sent the response.
+    ///
+    /// Refs: <https://datatracker.ietf.org/doc/html/rfc9207#section-2>
+    #[serde(default)]
+    pub iss: Option<Cow<'a, str>>,
+    /// Unix epoch seconds when the response was parsed.
+    ///
+    /// Outside the OAuth specs; stamped from the system clock with the
+    /// `client` feature, `None` without it (set it yourself).
+    #[serde(skip)]
+    pub received_at: Option<u64>,
+}
+
+impl Oauth20AuthSuccessParams<'_> {
+    /// Returns whether the code was received more than `max_age` before
+    /// `now` (Unix epoch seconds), [`OAUTH20_AUTH_CODE_MAX_AGE`] being the
+    /// recommended bound.
+    ///
+    /// Exchanging a stale code only earns an `invalid_grant`, so check it
+    /// first and fail with a clearer message. `false` when the reception
+    /// time is unknown.
+    ///
+    /// Refs: <https://datatracker.ietf.org/doc/html/rfc6749#section-4.1.2>
+    pub fn is_code_stale(&self, now: u64, max_age: Duration) -> bool {
+        match self.received_at {
+            Some(received_at) => now.saturating_sub(received_at) > max_age.as_secs(),
+            None => false,
+        }
+    }
+}
+
+impl fmt::Debug for Oauth20AuthSuccessParams<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Oauth20AuthSuccessParams")
+            .field("code", &format_args!("[REDACTED]"))
+            .field("state", &self.state)
+            .field("iss", &self.iss)
+            .field("received_at", &self.received_at)
+            .finish()
+    }
+}
+
+#[cfg(feature = "client")]
+fn unix_now() -> Option<u64> {
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).ok()?;
+    Some(now.as_secs())
+}
+
+#[cfg(not(feature = "client"))]
+fn unix_now() -> Option<u64> {
+    None
 }
 
 /// The error authorization response, when the request is denied or fails.
@@ -149,6 +276,11 @@ pub struct Oauth20AuthErrorParams<'a> {
     pub error_description: Option<Cow<'a, str>>,
     /// A URI to a human-readable page about the error.
     pub error_uri: Option<Cow<'a, Url>>,
+    /// The issuer identifier of the server that sent the response.
+    ///
+    /// Refs: <https://datatracker.ietf.org/doc/html/rfc9207#section-2>
+    #[serde(default)]
+    pub iss: Option<Cow<'a, str>>,
 }
 
 impl fmt::Display for Oauth20AuthErrorParams<'_> {
@@ -190,7 +322,136 @@ pub enum Oauth20AuthErrorCode {
     ///
     /// Refs: <https://datatracker.ietf.org/doc/html/rfc8707#section-3>
     InvalidTarget,
+    /// The end user must interact with the server (OpenID Connect).
+    ///
+    /// Refs: <https://openid.net/specs/openid-connect-core-1_0.html#AuthError>
+    InteractionRequired,
+    /// The end user must authenticate (OpenID Connect).
+    ///
+    /// Refs: <https://openid.net/specs/openid-connect-core-1_0.html#AuthError>
+    LoginRequired,
+    /// The end user must select one of their accounts (OpenID Connect).
+    ///
+    /// Refs: <https://openid.net/specs/openid-connect-core-1_0.html#AuthError>
+    AccountSelectionRequired,
+    /// The end user must consent (OpenID Connect).
+    ///
+    /// Refs: <https://openid.net/specs/openid-connect-core-1_0.html#AuthError>
+    ConsentRequired,
     /// Any unregistered code, kept for provider-specific extensions.
     #[serde(other)]
     Unknown,
 }
+
+impl Oauth20AuthErrorCode {
+    /// Returns whether the error only asks for the end user to interact.
+    ///
+    /// A silent authorization request (`prompt=none`, as hidden-iframe
+    /// renewal sends) failing this way recovers with an interactive request;
+    /// any other code fails the interactive request as well.
+    ///
+    /// Refs: <https://openid.net/specs/openid-connect-core-1_0.html#AuthError>
+    pub fn requires_interaction(&self) -> bool {
+        matches!(
+            self,
+            Self::InteractionRequired
+                | Self::LoginRequired
+                | Self::AccountSelectionRequired
+                | Self::ConsentRequired
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::format;
+
+    use url::Url;
+
+    use crate::rfc6749::auth_response::*;
+
+    #[test]
+    fn success_params_debug_redacts_secrets() {
+        let url = Url::parse("http://127.0.0.1/cb?code=s3cr3t-c0de&state=s3cr3t-st4te").unwrap();
+        let Oauth20AuthParams::Success(params) = Oauth20AuthParams::from(&url) else {
+            panic!("expected success params");
+        };
+
+        let debug = format!("{params:?}");
+        assert!(!debug.contains("s3cr3t"), "{debug}");
+    }
+
+    #[test]
+    fn stale_codes_are_detected() {
+        let url = Url::parse("http://127.0.0.1/cb?code=abc").unwrap();
+        let Oauth20AuthParams::Success(mut params) = Oauth20AuthParams::from(&url) else {
+            panic!("expected success params");
+        };
+
+        params.received_at = Some(1000);
+        assert!(!params.is_code_stale(1600, OAUTH20_AUTH_CODE_MAX_AGE));
+        assert!(params.is_code_stale(1601, OAUTH20_AUTH_CODE_MAX_AGE));
+
+        params.received_at = None;
+        assert!(!params.is_code_stale(u64::MAX, OAUTH20_AUTH_CODE_MAX_AGE));
+    }
+
+    #[test]
+    fn silent_request_errors_require_interaction() {
+        let url = Url::parse("http://127.0.0.1/cb?error=login_required").unwrap();
+        let Oauth20AuthParams::Error(params) = Oauth20AuthParams::from(&url) else {
+            panic!("expected error params");
+        };
+        assert!(matches!(params.error, Oauth20AuthErrorCode::LoginRequired));
+        assert!(params.error.requires_interaction());
+
+        let url = Url::parse("http://127.0.0.1/cb?error=access_denied").unwrap();
+        let Oauth20AuthParams::Error(params) = Oauth20AuthParams::from(&url) else {
+            panic!("expected error params");
+        };
+        assert!(!params.error.requires_interaction());
+    }
+
+    #[test]
+    fn issuer_verified_on_both_outcomes() {
+        let url = Url::parse("http://127.0.0.1/cb?code=abc&iss=https%3A%2F%2Fas.example").unwrap();
+        let params = Oauth20AuthParams::from(&url);
+        let issuer = Oauth20Issuer::parse("https://as.example").unwrap();
+        let other = Oauth20Issuer::parse("https://as.example/").unwrap();
+        assert_eq!(params.iss(), Some("https://as.example"));
+        assert!(params.verify_issuer(&issuer, true).is_ok());
+        assert!(matches!(
+            params.verify_issuer(&other, true),
+            Err(Oauth20AuthParamsValidationError::IssuerMismatch)
+        ));
+
+        let url = Url::parse("http://127.0.0.1/cb?error=access_denied").unwrap();
+        let params = Oauth20AuthParams::from(&url);
+        assert!(params.verify_issuer(&issuer, false).is_ok());
+        assert!(matches!(
+            params.verify_issuer(&issuer, true),
+            Err(Oauth20AuthParamsValidationError::IssuerMissing)
+        ));
+    }
+
+    #[test]
+    fn fragment_is_read_without_query_response() {
+        let url = Url::parse("http://127.0.0.1/cb#error=access_denied&state=abc").unwrap();
+        let Oauth20AuthParams::Error(params) = Oauth20AuthParams::from(&url) else {
+            panic!("expected error params");
+        };
+        assert!(matches!(params.error, Oauth20AuthErrorCode::AccessDenied));
+
+        let url = Url::parse("http://127.0.0.1/cb?foo=bar#code=abc").unwrap();
+        let Oauth20AuthParams::Success(params) = Oauth20AuthParams::from(&url) else {
+            panic!("expected success params");
+        };
+        assert_eq!(params.code, "abc");
+
+        let url = Url::parse("http://127.0.0.1/cb?code=query#code=fragment").unwrap();
+        let Oauth20AuthParams::Success(params) = Oauth20AuthParams::from(&url) else {
+            panic!("expected success params");
+        };
+        assert_eq!(params.code, "query");
+    }
+}
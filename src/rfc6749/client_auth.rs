@@ -0,0 +1,309 @@
+//! Client authentication (RFC 6749 section 2.3).
+//!
+//! How a client proves its identity to the token endpoint. The wire
+//! names are the `token_endpoint_auth_method` values of RFC 7591,
+//! shared with the server metadata of RFC 8414.
+//!
+//! The method also decides where the `client_id` goes: in the Basic
+//! `Authorization` header alongside the secret, or in the body, so
+//! request builders place both through
+//! [`Oauth20ClientAuthMethod::authenticate`].
+//!
+//! The JWT methods of RFC 7523 (`private_key_jwt`,
+//! `client_secret_jwt`) are not implemented: the assertion is a JWS
+//! whose signing algorithm (picked among the server's
+//! `token_endpoint_auth_signing_alg_values_supported`) and key belong
+//! to the caller's JOSE library, as for the request objects of the
+//! authorization request. Such a caller sets `client_assertion` and
+//! `client_assertion_type` on the body of a `dry_run` request itself.
+
+use alloc::string::String;
+
+use io_http::{
+    rfc7617::basic::HttpAuthBasic,
+    rfc9110::{challenge::HttpChallenge, request::HttpRequest},
+};
+use secrecy::{ExposeSecret, SecretString};
+use serde::{Deserialize, Serialize};
+use url::form_urlencoded::{Serializer, parse};
+
+/// The method a client authenticates with on the token endpoint.
+///
+/// Refs: <https://datatracker.ietf.org/doc/html/rfc6749#section-2.3.1>,
+///       <https://datatracker.ietf.org/doc/html/rfc7591#section-2>
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Oauth20ClientAuthMethod {
+    /// A public client: no secret, only the `client_id` in the body.
+    None,
+    /// The secret in the HTTP Basic `Authorization` header, the default
+    /// the specification mandates servers support.
+    #[default]
+    ClientSecretBasic,
+    /// The secret in the request body, as `client_secret`.
+    ClientSecretPost,
+}
+
+impl Oauth20ClientAuthMethod {
+    /// Returns the method's wire value.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::None => "none",
+            Self::ClientSecretBasic => "client_secret_basic",
+            Self::ClientSecretPost => "client_secret_post",
+        }
+    }
+
+    /// Returns the method a token endpoint challenged the client with.
+    ///
+    /// A server rejecting the client authentication answers 401 with a
+    /// `WWW-Authenticate` challenge for the scheme it expects: `Basic`
+    /// calls for [`Self::ClientSecretBasic`], so a client sending its
+    /// secret in the body can switch on the retry. `None` when no
+    /// challenge names a client authentication scheme.
+    ///
+    /// Refs: <https://datatracker.ietf.org/doc/html/rfc6749#section-5.2>
+    pub fn from_challenges(challenges: &[HttpChallenge]) -> Option<Self> {
+        challenges
+            .iter()
+            .any(|challenge| challenge.scheme.eq_ignore_ascii_case("basic"))
+            .then_some(Self::ClientSecretBasic)
+    }
+
+    /// Places the client credentials of a form-urlencoded `request`
+    /// according to the method.
+    ///
+    /// Any `client_id` the params serialized into the body is dropped,
+    /// then the credentials are set again where the method puts them:
+    /// in the body, they take the place of the serialized `client_id`,
+    /// so the field order of the params holds. The Basic `Authorization`
+    /// header replaces any the request already carries.
+    ///
+    /// The `client_secret` of the params is dropped as well, unless
+    /// `client_secret` is `None` and the method is not [`Self::None`]:
+    /// a client without a secret of its own then keeps the one of the
+    /// params, in the body.
+    // SAFETY: this function exposes the client secret
+    pub fn authenticate(
+        &self,
+        request: HttpRequest,
+        client_id: &str,
+        client_secret: Option<&SecretString>,
+    ) -> HttpRequest {
+        let mut request = request;
+
+        let mut body_credentials = match (self, client_secret) {
+            (Self::ClientSecretBasic, Some(secret)) => {
+                let creds = HttpAuthBasic::new(client_id, secret.expose_secret());
+                request
+                    .headers
+                    .retain(|(k, _)| !k.eq_ignore_ascii_case("Authorization"));
+                request = request.header("Authorization", creds.to_authorization());
+                None
+            }
+            (Self::ClientSecretPost, Some(secret)) => Some((client_id, Some(secret))),
+            _ => Some((client_id, None)),
+        };
+
+        let mut serializer = Serializer::new(String::new());
+        let mut append_credentials = |serializer: &mut Serializer<String>| {
+            if let Some((client_id, secret)) = body_credentials.take() {
+                serializer.append_pair("client_id", client_id);
+
+                if let Some(secret) = secret {
+                    serializer.append_pair("client_secret", secret.expose_secret());
+                }
+            }
+        };
+
+        for (key, val) in parse(&request.body) {
+            match key.as_ref() {
+                "client_id" => append_credentials(&mut serializer),
+                "client_secret" if client_secret.is_some() || *self == Self::None => (),
+                _ => {
+                    serializer.append_pair(&key, &val);
+                }
+            }
+        }
+
+        append_credentials(&mut serializer);
+
+        request.body(serializer.finish().into_bytes())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::{borrow::Cow, string::String, vec::Vec};
+    use core::str::FromStr;
+
+    use io_http::rfc9110::request::HttpRequest;
+    use secrecy::SecretString;
+    use url::Url;
+
+    use crate::{
+        rfc6749::{
+            access_token_request::*, client_auth::*, client_credentials::*, endpoint::post_request,
+            scope::collect_scope,
+        },
+        rfc7636::pkce::Oauth20PkceCodeVerifier,
+    };
+
+    #[test]
+    fn credentials_are_placed_per_method() {
+        let request = HttpRequest {
+            method: "POST".into(),
+            url: Url::parse("https://as.example/token").unwrap(),
+            headers: Vec::new(),
+            body: b"grant_type=refresh_token&client_id=stale&refresh_token=r".to_vec(),
+        };
+        let secret = SecretString::from("s3cr3t");
+        let authorization = |request: &HttpRequest| {
+            request
+                .headers
+                .iter()
+                .any(|(k, _)| k.eq_ignore_ascii_case("Authorization"))
+        };
+
+        let basic = Oauth20ClientAuthMethod::ClientSecretBasic;
+        let req = basic.authenticate(request.clone(), "client-id", Some(&secret));
+        assert!(authorization(&req));
+        assert_eq!(req.body, b"grant_type=refresh_token&refresh_token=r");
+
+        let req = basic.authenticate(request.clone(), "client-id", None);
+        assert!(!authorization(&req));
+        assert_eq!(
+            req.body,
+            b"grant_type=refresh_token&client_id=client-id&refresh_token=r"
+        );
+
+        let post = Oauth20ClientAuthMethod::ClientSecretPost;
+        let req = post.authenticate(request.clone(), "client-id", Some(&secret));
+        assert!(!authorization(&req));
+        assert_eq!(
+            req.body,
+            b"grant_type=refresh_token&client_id=client-id&client_secret=s3cr3t&refresh_token=r"
+        );
+
+        let none = Oauth20ClientAuthMethod::None;
+        let req = none.authenticate(request, "client-id", Some(&secret));
+        assert!(!authorization(&req));
+        assert_eq!(
+            req.body,
+            b"grant_type=refresh_token&client_id=client-id&refresh_token=r"
+        );
+    }
+
+    #[test]
+    fn params_secret_is_kept_without_a_client_secret() {
+        let request = HttpRequest {
+            method: "POST".into(),
+            url: Url::parse("https://as.example/token").unwrap(),
+            headers: Vec::new(),
+            body: b"grant_type=refresh_token&client_id=c&client_secret=p&refresh_token=r".to_vec(),
+        };
+        let secret = SecretString::from("s3cr3t");
+
+        for method in [
+            Oauth20ClientAuthMethod::ClientSecretBasic,
+            Oauth20ClientAuthMethod::ClientSecretPost,
+        ] {
+            let req = method.authenticate(request.clone(), "client-id", None);
+            assert_eq!(
+                req.body,
+                b"grant_type=refresh_token&client_id=client-id&client_secret=p&refresh_token=r"
+            );
+        }
+
+        let post = Oauth20ClientAuthMethod::ClientSecretPost;
+        let req = post.authenticate(request.clone(), "client-id", Some(&secret));
+        assert_eq!(
+            req.body,
+            b"grant_type=refresh_token&client_id=client-id&client_secret=s3cr3t&refresh_token=r"
+        );
+
+        let none = Oauth20ClientAuthMethod::None;
+        let req = none.authenticate(request, "client-id", None);
+        assert_eq!(
+            req.body,
+            b"grant_type=refresh_token&client_id=client-id&refresh_token=r"
+        );
+    }
+
+    #[test]
+    fn basic_auth_replaces_the_authorization_header() {
+        let request = post_request(&Url::parse("https://as.example/token").unwrap())
+            .header("authorization", "Basic c3RhbGU=");
+        let secret = SecretString::from("s3cr3t");
+
+        let basic = Oauth20ClientAuthMethod::ClientSecretBasic;
+        let req = basic.authenticate(request, "client-id", Some(&secret));
+        let authorizations: Vec<_> = req
+            .headers
+            .iter()
+            .filter(|(k, _)| k.eq_ignore_ascii_case("Authorization"))
+            .collect();
+        assert_eq!(authorizations.len(), 1);
+        assert_ne!(authorizations[0].1, "Basic c3RhbGU=");
+    }
+
+    #[test]
+    fn basic_challenge_suggests_basic_auth() {
+        let challenges = HttpChallenge::parse_all(r#"Basic realm="token""#);
+        assert_eq!(
+            Oauth20ClientAuthMethod::from_challenges(&challenges),
+            Some(Oauth20ClientAuthMethod::ClientSecretBasic)
+        );
+
+        let challenges = HttpChallenge::parse_all(r#"Bearer error="invalid_token""#);
+        assert_eq!(Oauth20ClientAuthMethod::from_challenges(&challenges), None);
+        assert_eq!(Oauth20ClientAuthMethod::from_challenges(&[]), None);
+    }
+
+    #[test]
+    fn authenticated_body_keeps_the_field_order() {
+        let verifier = "dBjftJeZ4CVP-mB92K27uhbUJU1p1r_wW1gFWFOEjXk";
+        let params = Oauth20AccessTokenRequestParams {
+            code: "code".into(),
+            redirect_uri: Some("https://client.example.com/cb".parse().unwrap()),
+            client_id: "client-id".into(),
+            client_secret: None,
+            pkce_code_verifier: Some(Cow::Owned(
+                Oauth20PkceCodeVerifier::from_str(verifier).unwrap(),
+            )),
+        };
+
+        let request = post_request(&Url::parse("https://as.example/token").unwrap());
+        let request = Oauth20AccessTokenRequest::dry_run(request, params);
+        let secret = SecretString::from("s3cr3t");
+        let req = Oauth20ClientAuthMethod::ClientSecretPost.authenticate(
+            request,
+            "client-id",
+            Some(&secret),
+        );
+
+        assert_eq!(
+            String::from_utf8(req.body).unwrap(),
+            "grant_type=authorization_code\
+             &code=code\
+             &redirect_uri=https%3A%2F%2Fclient.example.com%2Fcb\
+             &client_id=client-id\
+             &client_secret=s3cr3t\
+             &code_verifier=dBjftJeZ4CVP-mB92K27uhbUJU1p1r_wW1gFWFOEjXk",
+        );
+
+        // NOTE: bodies without a client_id get the credentials last
+        let params = Oauth20ClientCredentialsRequestParams {
+            scope: collect_scope(["write", "read"]),
+            scope_delimiter: Default::default(),
+        };
+        let request = post_request(&Url::parse("https://as.example/token").unwrap());
+        let request = Oauth20ClientCredentialsRequest::dry_run(request, params);
+        let req = Oauth20ClientAuthMethod::None.authenticate(request, "client-id", None);
+
+        assert_eq!(
+            String::from_utf8(req.body).unwrap(),
+            "grant_type=client_credentials&scope=read+write&client_id=client-id",
+        );
+    }
+}
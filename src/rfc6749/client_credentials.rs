@@ -12,18 +12,11 @@
 //!     net::TcpStream,
 //! };
 //!
-//! use io_http::rfc9110::request::HttpRequest;
-//! use io_oauth::rfc6749::client_credentials::*;
+//! use io_oauth::rfc6749::{client_credentials::*, endpoint::post_request};
 //! use url::Url;
 //!
 //! let token_url = Url::parse("https://example.com/token").unwrap();
-//! let request = HttpRequest {
-//!     method: "POST".into(),
-//!     url: token_url.clone(),
-//!     headers: Vec::new(),
-//!     body: Vec::new(),
-//! }
-//! .header("Host", token_url.host_str().unwrap());
+//! let request = post_request(&token_url);
 //!
 //! let params = Oauth20ClientCredentialsRequestParams::default();
 //!
@@ -50,28 +43,19 @@
 
 use core::fmt;
 
-use alloc::{
-    borrow::Cow,
-    collections::BTreeSet,
-    string::{String, ToString},
-    vec::Vec,
-};
+use alloc::{borrow::Cow, collections::BTreeSet, string::String, vec::Vec};
 
-use io_http::{
-    coroutine::*,
-    rfc9110::{
-        request::HttpRequest,
-        send::{HttpSendOutput, HttpSendYield},
-    },
-    rfc9112::send::{Http11Send, Http11SendError},
-};
-use log::{debug, trace};
+use io_http::{rfc9110::request::HttpRequest, rfc9112::send::Http11SendError};
 use thiserror::Error;
 use url::{Url, form_urlencoded::Serializer};
 
-use crate::rfc6749::issue_access_token::{
-    Oauth20AccessTokenErrorParams, Oauth20AccessTokenResponse, Oauth20AccessTokenSuccessParams,
-    parse_http_date,
+use crate::rfc6749::{
+    issue_access_token::{
+        Oauth20AccessTokenResponse, Oauth20AccessTokenResponseError, Oauth20CoroutineState,
+        Oauth20RequestError, Oauth20TokenCoroutine, Oauth20TokenCoroutineError,
+        Oauth20TokenCoroutineResult, accept_json,
+    },
+    scope::{Oauth20ScopeDelimiter, into_owned_scope, join_scope},
 };
 
 /// The client credentials grant request parameters.
@@ -86,25 +70,34 @@ pub struct Oauth20ClientCredentialsRequestParams<'a> {
     ///
     /// Refs: <https://datatracker.ietf.org/doc/html/rfc6749#section-3.3>
     pub scope: BTreeSet<Cow<'a, str>>,
+    /// The separator between scope tokens, spaces unless the provider
+    /// expects commas.
+    pub scope_delimiter: Oauth20ScopeDelimiter,
 }
 
+/// The client credentials request params, owning every value.
+pub type Oauth20ClientCredentialsRequestParamsOwned =
+    Oauth20ClientCredentialsRequestParams<'static>;
+
 impl<'a> Oauth20ClientCredentialsRequestParams<'a> {
+    /// Converts every borrowed value into an owned one, so the params
+    /// outlive the strings they were built from.
+    pub fn into_owned(self) -> Oauth20ClientCredentialsRequestParams<'static> {
+        Oauth20ClientCredentialsRequestParams {
+            scope: into_owned_scope(self.scope),
+            scope_delimiter: self.scope_delimiter,
+        }
+    }
+
     /// Serializes the params into the form-urlencoded request body.
     pub fn to_serializer(&self) -> Serializer<'a, String> {
         let mut serializer = Serializer::new(String::new());
 
         serializer.append_pair("grant_type", "client_credentials");
 
-        if !self.scope.is_empty() {
-            let mut scope = String::new();
-            let mut glue = "";
-
-            for token in &self.scope {
-                scope.push_str(glue);
-                scope.push_str(token);
-                glue = " ";
-            }
+        let scope = join_scope(&self.scope, self.scope_delimiter);
 
+        if !scope.is_empty() {
             serializer.append_pair("scope", &scope);
         }
 
@@ -126,7 +119,7 @@ pub enum Oauth20ClientCredentialsRequestError {
     SendHttpRequest(#[from] Http11SendError),
     /// The HTTP response could not be parsed.
     #[error(transparent)]
-    ParseHttpResponse(#[from] serde_json::Error),
+    ParseHttpResponse(#[from] Oauth20AccessTokenResponseError),
     /// The server answered with an unexpected redirection.
     #[error("Unexpected redirection {code} to {url}")]
     Redirect {
@@ -135,6 +128,9 @@ pub enum Oauth20ClientCredentialsRequestError {
         /// The redirection HTTP status code.
         code: u16,
     },
+    /// The coroutine was cancelled by the caller.
+    #[error("OAuth 2.0 client credentials request cancelled")]
+    Cancelled,
 }
 
 /// Result returned by the coroutine's resume function.
@@ -146,8 +142,9 @@ pub enum Oauth20ClientCredentialsRequestResult {
     WantsRead,
     /// The coroutine wants the given bytes to be written to the socket.
     WantsWrite(Vec<u8>),
-    /// The coroutine encountered an error.
-    Err(Oauth20ClientCredentialsRequestError),
+    /// The coroutine encountered an error, along the identifier of
+    /// the request.
+    Err(Oauth20RequestError<Oauth20ClientCredentialsRequestError>),
 }
 
 /// The I/O-free coroutine to request an access token with the client
@@ -158,67 +155,168 @@ pub enum Oauth20ClientCredentialsRequestResult {
 ///
 /// Refs: <https://datatracker.ietf.org/doc/html/rfc6749#section-4.4>
 pub struct Oauth20ClientCredentialsRequest {
-    send: Http11Send,
+    inner: Oauth20TokenCoroutine,
 }
 
 impl Oauth20ClientCredentialsRequest {
     /// Creates the coroutine to request a client credentials access token.
     pub fn new(request: HttpRequest, body: Oauth20ClientCredentialsRequestParams<'_>) -> Self {
-        debug!("prepare client credentials request");
-        trace!("url: {}", request.url);
+        Self::from_request(Self::dry_run(request, body))
+    }
 
-        let request = request
-            .header("Content-Type", "application/x-www-form-urlencoded")
-            .body(body.to_string().into_bytes());
+    /// Creates the coroutine sending `request` as-is.
+    ///
+    /// Meant for a request built by [`Self::dry_run`] then adjusted, for
+    /// instance by [`Oauth20ClientAuthMethod::authenticate`].
+    ///
+    /// [`Oauth20ClientAuthMethod::authenticate`]: crate::rfc6749::client_auth::Oauth20ClientAuthMethod::authenticate
+    pub fn from_request(request: HttpRequest) -> Self {
+        Self {
+            inner: Oauth20TokenCoroutine::new("client credentials", request),
+        }
+    }
 
+    /// Creates the coroutine sending `request` as-is, like
+    /// [`Self::from_request`], keeping a copy of it for
+    /// [`Self::into_request`].
+    ///
+    /// The copy holds the body a second time, secrets included, for the
+    /// whole life of the coroutine; hence opt-in.
+    // SAFETY: the retained body keeps the params' secrets in memory
+    pub fn from_request_retained(request: HttpRequest) -> Self {
         Self {
-            send: Http11Send::new(request),
+            inner: Oauth20TokenCoroutine::retained("client credentials", request),
         }
     }
 
+    /// Returns the HTTP request the coroutine would send, without sending it.
+    ///
+    /// Meant for inspecting or replaying a request (with `curl`, say) when a
+    /// provider misbehaves. The `Debug` output of the request redacts its
+    /// body, but the body itself carries the form params in clear.
+    ///
+    /// Asks for a JSON response with `Accept: application/json`, unless
+    /// `request` already carries an `Accept` header.
+    // SAFETY: the returned body exposes the params' secrets
+    pub fn dry_run(
+        request: HttpRequest,
+        body: Oauth20ClientCredentialsRequestParams<'_>,
+    ) -> HttpRequest {
+        accept_json(request)
+            .header("Content-Type", "application/x-www-form-urlencoded")
+            .body(body.to_serializer().finish().into_bytes())
+    }
+
+    /// Returns the identifier of the request, when the caller set one.
+    ///
+    /// Read from the [`OAUTH20_REQUEST_ID_HEADER`] of the request, it tags
+    /// the errors returned by [`Self::resume`].
+    ///
+    /// [`OAUTH20_REQUEST_ID_HEADER`]: crate::rfc6749::issue_access_token::OAUTH20_REQUEST_ID_HEADER
+    pub fn request_id(&self) -> Option<&str> {
+        self.inner.request_id()
+    }
+
+    /// Returns whether the server kept the connection alive for another
+    /// token request, `false` until [`Self::resume`] returned the response.
+    pub fn keep_alive(&self) -> bool {
+        self.inner.keep_alive()
+    }
+
+    /// Returns the progress of the coroutine, for diagnostics.
+    pub fn state(&self) -> Oauth20CoroutineState {
+        self.inner.state()
+    }
+
+    /// Returns the request the coroutine was created with, to retry
+    /// through a fresh [`Self::from_request`] once it failed.
+    ///
+    /// `None` unless the coroutine was created by
+    /// [`Self::from_request_retained`], the only one keeping it.
+    // SAFETY: the returned body exposes the params' secrets
+    pub fn into_request(self) -> Option<HttpRequest> {
+        self.inner.into_request()
+    }
+
+    /// Cancels the coroutine.
+    ///
+    /// Every later [`Self::resume`] returns a `Cancelled` error without
+    /// yielding any more I/O. The request may have been partially written, or
+    /// its response partially read: drop the stream rather than reusing it
+    /// for another request.
+    pub fn cancel(&mut self) {
+        self.inner.cancel()
+    }
+
     /// Makes the coroutine progress.
     pub fn resume(&mut self, arg: Option<&[u8]>) -> Oauth20ClientCredentialsRequestResult {
-        match self.send.resume(arg) {
-            HttpCoroutineState::Complete(Ok(HttpSendOutput { response, .. }))
-                if response.status.is_success() =>
-            {
-                debug!("received client credentials response");
-                trace!("status: {}", *response.status);
-
-                match Oauth20AccessTokenSuccessParams::try_from(response.body.as_slice()) {
-                    Ok(mut res) => {
-                        res.issued_at = response.header("date").and_then(parse_http_date);
-                        Oauth20ClientCredentialsRequestResult::Ok(Ok(res))
-                    }
-                    Err(err) => Oauth20ClientCredentialsRequestResult::Err(err.into()),
-                }
-            }
-            HttpCoroutineState::Complete(Ok(HttpSendOutput { response, .. })) => {
-                debug!("received client credentials error response");
-                trace!("status: {}", *response.status);
-
-                match Oauth20AccessTokenErrorParams::try_from(response.body.as_slice()) {
-                    Ok(res) => Oauth20ClientCredentialsRequestResult::Ok(Err(res)),
-                    Err(err) => Oauth20ClientCredentialsRequestResult::Err(err.into()),
-                }
-            }
-            HttpCoroutineState::Yielded(HttpSendYield::WantsRead) => {
+        match self.inner.resume(arg) {
+            Oauth20TokenCoroutineResult::Ok(res) => Oauth20ClientCredentialsRequestResult::Ok(res),
+            Oauth20TokenCoroutineResult::WantsRead => {
                 Oauth20ClientCredentialsRequestResult::WantsRead
             }
-            HttpCoroutineState::Yielded(HttpSendYield::WantsWrite(bytes)) => {
+            Oauth20TokenCoroutineResult::WantsWrite(bytes) => {
                 Oauth20ClientCredentialsRequestResult::WantsWrite(bytes)
             }
-            HttpCoroutineState::Yielded(HttpSendYield::WantsRedirect { url, response, .. }) => {
-                Oauth20ClientCredentialsRequestResult::Err(
-                    Oauth20ClientCredentialsRequestError::Redirect {
-                        url,
-                        code: *response.status,
-                    },
-                )
-            }
-            HttpCoroutineState::Complete(Err(err)) => {
-                Oauth20ClientCredentialsRequestResult::Err(err.into())
+            Oauth20TokenCoroutineResult::Err(err) => {
+                Oauth20ClientCredentialsRequestResult::Err(err)
             }
         }
     }
 }
+
+impl Oauth20TokenCoroutineError for Oauth20ClientCredentialsRequestError {
+    fn redirect(url: Url, code: u16) -> Self {
+        Self::Redirect { url, code }
+    }
+
+    fn cancelled() -> Self {
+        Self::Cancelled
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::{collections::BTreeSet, vec::Vec};
+
+    use io_http::rfc9110::request::HttpRequest;
+    use url::Url;
+
+    use crate::rfc6749::client_credentials::*;
+
+    fn request_and_params() -> (HttpRequest, Oauth20ClientCredentialsRequestParams<'static>) {
+        let request = HttpRequest {
+            method: "POST".into(),
+            url: Url::parse("https://example.com/token").unwrap(),
+            headers: Vec::new(),
+            body: Vec::new(),
+        };
+        let params = Oauth20ClientCredentialsRequestParams {
+            scope: BTreeSet::from(["read".into()]),
+            ..Default::default()
+        };
+
+        (request, params)
+    }
+
+    #[test]
+    fn dry_run_returns_the_request_to_send() {
+        let (request, params) = request_and_params();
+
+        let request = Oauth20ClientCredentialsRequest::dry_run(request, params);
+        assert_eq!(request.method, "POST");
+        assert!(
+            request
+                .headers
+                .iter()
+                .any(|(k, v)| { k == "Content-Type" && v == "application/x-www-form-urlencoded" })
+        );
+        assert!(
+            request
+                .headers
+                .iter()
+                .any(|(k, v)| { k == "Accept" && v == "application/json" })
+        );
+        assert_eq!(request.body, b"grant_type=client_credentials&scope=read");
+    }
+}
@@ -0,0 +1,134 @@
+//! Authorization and token endpoints (RFC 6749 sections 3.1 and 3.2).
+//!
+//! Parses and checks the endpoint URLs the grants talk to. Both
+//! endpoints carry credentials (client secret, authorization code,
+//! tokens), so the specification requires TLS: only a loopback host
+//! may be reached over plain `http`, for local development.
+//!
+//! [`post_request`] builds the bare `POST` the grant coroutines send
+//! to those endpoints, `Host` header included.
+
+use alloc::{format, string::String, vec::Vec};
+
+use io_http::rfc9110::request::HttpRequest;
+use thiserror::Error;
+use url::{Host, Url};
+
+/// Errors that can occur while parsing or checking an endpoint URL.
+#[derive(Debug, Error)]
+pub enum Oauth20EndpointError {
+    /// The endpoint is not a valid URL.
+    #[error("Parse OAuth 2.0 endpoint URL: {0}")]
+    ParseUrl(#[from] url::ParseError),
+    /// The endpoint does not use TLS and is not a loopback address.
+    #[error("OAuth 2.0 endpoint `{0}` must use https")]
+    InsecureEndpoint(Url),
+}
+
+/// Parses an endpoint URL, then checks it with [`check_endpoint`].
+pub fn parse_endpoint(endpoint: &str) -> Result<Url, Oauth20EndpointError> {
+    let url = Url::parse(endpoint)?;
+    check_endpoint(&url)?;
+    Ok(url)
+}
+
+/// Checks that an endpoint URL uses `https`, or `http` on a loopback host.
+///
+/// Refs: <https://datatracker.ietf.org/doc/html/rfc6749#section-3.1>,
+///       <https://datatracker.ietf.org/doc/html/rfc6749#section-3.2>
+pub fn check_endpoint(endpoint: &Url) -> Result<(), Oauth20EndpointError> {
+    let scheme = endpoint.scheme();
+
+    if scheme.eq_ignore_ascii_case("https") {
+        return Ok(());
+    }
+
+    if scheme.eq_ignore_ascii_case("http") && is_loopback(endpoint) {
+        return Ok(());
+    }
+
+    Err(Oauth20EndpointError::InsecureEndpoint(endpoint.clone()))
+}
+
+/// Builds a `POST` request to the given endpoint, with an empty body.
+///
+/// The `Host` header carries the endpoint host, followed by its port
+/// only when it is not the scheme default: `https://a.com:443` gives
+/// `a.com`, `https://a.com:8443` gives `a.com:8443`.
+///
+/// Refs: <https://datatracker.ietf.org/doc/html/rfc9110#section-7.2>
+pub fn post_request(endpoint: &Url) -> HttpRequest {
+    HttpRequest {
+        method: "POST".into(),
+        url: endpoint.clone(),
+        headers: Vec::new(),
+        body: Vec::new(),
+    }
+    .header("Host", host_header(endpoint))
+}
+
+fn host_header(url: &Url) -> String {
+    let host = url.host_str().unwrap_or("");
+
+    // NOTE: the URL parser already drops the port when it is the
+    // scheme default, so any remaining port is a non-standard one
+    match url.port() {
+        Some(port) => format!("{host}:{port}"),
+        None => host.into(),
+    }
+}
+
+fn is_loopback(url: &Url) -> bool {
+    match url.host() {
+        Some(Host::Ipv4(ip)) => ip.is_loopback(),
+        Some(Host::Ipv6(ip)) => ip.is_loopback(),
+        Some(Host::Domain(domain)) => domain.eq_ignore_ascii_case("localhost"),
+        None => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::rfc6749::endpoint::*;
+
+    #[test]
+    fn endpoints_require_tls_except_loopback() {
+        assert!(parse_endpoint("https://example.com/token").is_ok());
+        assert!(parse_endpoint("http://127.0.0.1:8080/token").is_ok());
+        assert!(parse_endpoint("http://[::1]/token").is_ok());
+        assert!(parse_endpoint("http://localhost/token").is_ok());
+
+        assert!(matches!(
+            parse_endpoint("http://example.com/token"),
+            Err(Oauth20EndpointError::InsecureEndpoint(_))
+        ));
+        assert!(matches!(
+            parse_endpoint("ftp://127.0.0.1/token"),
+            Err(Oauth20EndpointError::InsecureEndpoint(_))
+        ));
+        assert!(matches!(
+            parse_endpoint("not a url"),
+            Err(Oauth20EndpointError::ParseUrl(_))
+        ));
+    }
+
+    #[test]
+    fn post_request_host_omits_default_port_only() {
+        let hosts = [
+            ("https://a.com", "a.com"),
+            ("https://example.com:443/token", "example.com"),
+            ("http://127.0.0.1:80/token", "127.0.0.1"),
+            ("http://127.0.0.1:8080/token", "127.0.0.1:8080"),
+            ("https://a.com:8443", "a.com:8443"),
+            ("https://[::1]:8443/token", "[::1]:8443"),
+            ("http://a.com:443", "a.com:443"),
+        ];
+
+        for (url, host) in hosts {
+            let request = post_request(&Url::parse(url).unwrap());
+            assert_eq!(request.method, "POST");
+            assert!(request.body.is_empty());
+            assert_eq!(request.headers, [("Host".into(), host.into())], "{url}");
+        }
+    }
+}
@@ -3,19 +3,146 @@
 //! The token endpoint response shared by every grant: the issued
 //! token params on success, the error params otherwise. Consumed by
 //! the access token request, refresh, client credentials and device
-//! grant coroutines.
+//! grant coroutines. Bodies compressed by a CDN in front of the token
+//! endpoint (`gzip`, `deflate`) are decoded with the `compression`
+//! feature.
 
-use alloc::string::String;
+use core::{fmt, str::FromStr, time::Duration};
 
+use alloc::{
+    borrow::Cow,
+    collections::BTreeSet,
+    string::{String, ToString},
+    vec::Vec,
+};
+
+use io_http::{
+    coroutine::*,
+    rfc6750::bearer::HttpAuthBearer,
+    rfc9110::{
+        request::HttpRequest,
+        response::HttpResponse,
+        send::{HttpSendOutput, HttpSendYield},
+    },
+    rfc9112::send::{Http11Send, Http11SendError},
+};
+use log::{debug, trace};
 use secrecy::{ExposeSecret, SecretString};
-use serde::{Deserialize, Serialize, Serializer};
+use serde::{Deserialize, Deserializer, Serialize, Serializer, de::Error};
+use thiserror::Error as ThisError;
+use url::Url;
+
+use crate::rfc6749::{
+    client_auth::Oauth20ClientAuthMethod,
+    refresh_access_token::{Oauth20AccessTokenRefresh, Oauth20AccessTokenRefreshParams},
+    scope::split_scope,
+};
+
+/// The header carrying the caller's request identifier.
+///
+/// Outside the OAuth specs; the token coroutines read it back from the request
+/// they are created with, so a failure can be correlated with the caller's own
+/// logs.
+pub const OAUTH20_REQUEST_ID_HEADER: &str = "X-Request-ID";
+
+/// Sets the [`OAUTH20_REQUEST_ID_HEADER`] of `request` to `id`,
+/// replacing any previous one.
+pub fn with_request_id(mut request: HttpRequest, id: impl ToString) -> HttpRequest {
+    request
+        .headers
+        .retain(|(k, _)| !k.eq_ignore_ascii_case(OAUTH20_REQUEST_ID_HEADER));
+    request.header(OAUTH20_REQUEST_ID_HEADER, id)
+}
+
+/// An error of a token endpoint coroutine, along the identifier of
+/// the request that failed.
+///
+/// The identifier is the [`OAUTH20_REQUEST_ID_HEADER`] of the request
+/// the coroutine was created with, `None` when the caller set none.
+#[derive(Debug)]
+pub struct Oauth20RequestError<E> {
+    /// The identifier of the request that failed.
+    pub request_id: Option<String>,
+    /// The error itself.
+    pub error: E,
+}
+
+impl<E> Oauth20RequestError<E> {
+    /// Tags `error` with the identifier of the request that failed.
+    pub fn new(request_id: Option<String>, error: impl Into<E>) -> Self {
+        Self {
+            request_id,
+            error: error.into(),
+        }
+    }
+}
+
+impl<E: fmt::Display> fmt::Display for Oauth20RequestError<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.error.fmt(f)?;
+
+        if let Some(id) = &self.request_id {
+            write!(f, " (request {id})")?;
+        }
+
+        Ok(())
+    }
+}
+
+impl<E: core::error::Error> core::error::Error for Oauth20RequestError<E> {
+    // NOTE: the message already embeds the error's own, so skip it
+    // in the chain rather than printing it twice
+    fn source(&self) -> Option<&(dyn core::error::Error + 'static)> {
+        self.error.source()
+    }
+}
 
 /// The access token response: success params, or error params.
 ///
+/// The error params implement [`core::error::Error`], so the response
+/// can be propagated with `?` to the caller's own error type.
+///
 /// Refs: <https://datatracker.ietf.org/doc/html/rfc6749#section-5>
 pub type Oauth20AccessTokenResponse =
     Result<Oauth20AccessTokenSuccessParams, Oauth20AccessTokenErrorParams>;
 
+/// Errors that can occur while parsing a token endpoint response.
+#[derive(Debug, ThisError)]
+pub enum Oauth20AccessTokenResponseError {
+    /// The body is not valid JSON params.
+    #[error(transparent)]
+    ParseJson(#[from] serde_json::Error),
+    /// The body is encoded with a content coding that cannot be decoded.
+    ///
+    /// `gzip` and `deflate` are decoded with the `compression` feature.
+    #[error("Unsupported token endpoint response content encoding `{0}`")]
+    UnsupportedContentEncoding(String),
+    /// The body could not be decompressed.
+    ///
+    /// Only reported with the `compression` feature.
+    #[error("Decompress `{0}` token endpoint response body")]
+    Decompress(String),
+    /// The body is empty, carrying the status code of the response.
+    ///
+    /// Seen with misconfigured gateways in front of the token endpoint,
+    /// on success and error statuses alike.
+    #[error("Empty token endpoint response body (status {0})")]
+    EmptyBody(u16),
+}
+
+/// Errors that can occur while using an issued access token.
+#[derive(Debug, ThisError)]
+pub enum Oauth20TokenTypeError {
+    /// The token is of another type than the one the caller assumed.
+    #[error("Unsupported OAuth 2.0 token type `{actual}` (expected `{expected}`)")]
+    Unsupported {
+        /// The token type the caller assumed.
+        expected: &'static str,
+        /// The token type the server issued.
+        actual: String,
+    },
+}
+
 /// The successful access token response.
 ///
 /// Refs: <https://datatracker.ietf.org/doc/html/rfc6749#section-5.1>
@@ -29,6 +156,10 @@ pub struct Oauth20AccessTokenSuccessParams {
     /// Refs: <https://datatracker.ietf.org/doc/html/rfc6749#section-7.1>
     pub token_type: String,
     /// The lifetime of the access token, in seconds.
+    ///
+    /// Also accepted as a numeric string, as some providers send it. A
+    /// negative lifetime is clamped to 0: the token is already expired.
+    #[serde(default, deserialize_with = "deserialize_expires_in")]
     pub expires_in: Option<usize>,
     /// The refresh token, to obtain new access tokens from the same grant.
     ///
@@ -37,6 +168,10 @@ pub struct Oauth20AccessTokenSuccessParams {
     pub refresh_token: Option<SecretString>,
     /// The granted scope, when it differs from the requested one.
     ///
+    /// `None` means the server granted exactly the requested scope,
+    /// not that no scope was granted: an empty string does. Resolve it
+    /// with [`Self::effective_scopes`].
+    ///
     /// Refs: <https://datatracker.ietf.org/doc/html/rfc6749#section-3.3>
     pub scope: Option<String>,
     /// Unix epoch seconds when the token was issued.
@@ -46,13 +181,323 @@ pub struct Oauth20AccessTokenSuccessParams {
     /// expires_in` against their own clock.
     #[serde(default)]
     pub issued_at: Option<u64>,
+    /// Unix epoch seconds when the access token expires.
+    ///
+    /// Outside the OAuth specs; some providers send an RFC 3339
+    /// `expires_at` instead of, or next to, `expires_in`. Parsing the
+    /// response derives `expires_in` from it against `issued_at`, so
+    /// the expiry helpers work the same; unparsable, it is ignored.
+    #[serde(
+        default,
+        deserialize_with = "deserialize_expires_at",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub expires_at: Option<u64>,
+    /// The type identifier of the issued token, on token exchange
+    /// responses only.
+    ///
+    /// Refs: <https://datatracker.ietf.org/doc/html/rfc8693#section-2.2.1>
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub issued_token_type: Option<String>,
+}
+
+impl Oauth20AccessTokenSuccessParams {
+    /// The fields of a success response, as RFC 6749 defines them,
+    /// plus the ones these params read: `issued_token_type` (RFC 8693)
+    /// and the non-standard `expires_at`.
+    ///
+    /// Refs: <https://datatracker.ietf.org/doc/html/rfc6749#section-5.1>
+    pub const FIELDS: &'static [&'static str] = &[
+        "access_token",
+        "token_type",
+        "expires_in",
+        "refresh_token",
+        "scope",
+        "issued_token_type",
+        "expires_at",
+    ];
+
+    /// Deserializes success params from a JSON body, rejecting any
+    /// field outside [`Self::FIELDS`].
+    ///
+    /// Meant for conformance tests catching provider drift: production
+    /// code parses leniently (with [`TryFrom`]), since providers add
+    /// their own fields (`id_token`, `ext_expires_in`) freely.
+    pub fn try_from_strict(bytes: &[u8]) -> Result<Self, serde_json::Error> {
+        let params: serde_json::Map<String, serde_json::Value> = serde_json::from_slice(bytes)?;
+
+        if let Some(field) = params.keys().find(|k| !Self::FIELDS.contains(&k.as_str())) {
+            return Err(serde_json::Error::unknown_field(field, Self::FIELDS));
+        }
+
+        serde_json::from_value(serde_json::Value::Object(params))
+    }
+
+    /// Returns the granted scope tokens, split by [`split_scope`].
+    ///
+    /// `None` when the server sent no scope, meaning the granted scope is
+    /// the requested one.
+    pub fn scope_tokens(&self) -> Option<BTreeSet<String>> {
+        self.scope.as_deref().map(split_scope)
+    }
+
+    /// Returns the granted scope tokens, falling back to the
+    /// `requested` ones when the server sent no scope.
+    ///
+    /// Refs: <https://datatracker.ietf.org/doc/html/rfc6749#section-3.3>
+    pub fn effective_scopes<'a>(
+        &self,
+        requested: impl IntoIterator<Item = &'a str>,
+    ) -> BTreeSet<String> {
+        match self.scope_tokens() {
+            Some(granted) => granted,
+            None => requested.into_iter().map(String::from).collect(),
+        }
+    }
+
+    /// Returns the requested scope tokens the server did not grant.
+    ///
+    /// `None` when the server sent no scope, meaning it granted the
+    /// requested one; an empty set when it granted every token, and
+    /// every requested token when it sent an empty scope. A narrowed
+    /// scope is logged, for the caller to warn the user.
+    ///
+    /// Refs: <https://datatracker.ietf.org/doc/html/rfc6749#section-5.1>
+    pub fn denied_scopes<'a>(
+        &self,
+        requested: impl IntoIterator<Item = &'a str>,
+    ) -> Option<BTreeSet<String>> {
+        let granted = self.scope_tokens()?;
+
+        let denied: BTreeSet<String> = requested
+            .into_iter()
+            .filter(|token| !granted.contains(*token))
+            .map(String::from)
+            .collect();
+
+        if !denied.is_empty() {
+            debug!("server narrowed the requested scope, denying {denied:?}");
+        }
+
+        Some(denied)
+    }
+
+    /// Returns the access token as a bearer token, to authorize requests to
+    /// protected resources with io-http.
+    ///
+    /// Fails loudly on any other token type rather than mis-formatting it as
+    /// a `Bearer` header: the MAC scheme and its kin never left draft
+    /// status, and are not implemented. The type is case-insensitive.
+    ///
+    /// Refs: <https://datatracker.ietf.org/doc/html/rfc6749#section-7.1>,
+    ///       <https://datatracker.ietf.org/doc/html/rfc6750#section-2.1>
+    pub fn bearer(&self) -> Result<HttpAuthBearer, Oauth20TokenTypeError> {
+        if !self.token_type.eq_ignore_ascii_case("bearer") {
+            return Err(Oauth20TokenTypeError::Unsupported {
+                expected: "Bearer",
+                actual: self.token_type.clone(),
+            });
+        }
+
+        Ok(HttpAuthBearer::new(self.access_token.expose_secret()))
+    }
+
+    /// Appends the access token to `url` as the `access_token` query
+    /// parameter, for legacy resource servers reading it nowhere else.
+    ///
+    /// Discouraged by the specification, hence behind the
+    /// `legacy-bearer-query` feature: the token ends up wherever the URL
+    /// goes (server and proxy logs, browser history, `Referer`
+    /// headers). Prefer [`Self::bearer`] whenever the server accepts the
+    /// header, and send the request with `Cache-Control: no-store`.
+    /// Fails like [`Self::bearer`] on any other token type.
+    ///
+    /// Refs: <https://datatracker.ietf.org/doc/html/rfc6750#section-2.3>
+    // SAFETY: this function exposes the access token into the URL
+    #[cfg(feature = "legacy-bearer-query")]
+    pub fn append_access_token_query(&self, url: &mut Url) -> Result<(), Oauth20TokenTypeError> {
+        self.bearer()?;
+
+        let token = self.access_token.expose_secret();
+        url.query_pairs_mut().append_pair("access_token", token);
+
+        Ok(())
+    }
+
+    /// Returns the lifetime of the access token as a [`Duration`].
+    ///
+    /// Refs: <https://datatracker.ietf.org/doc/html/rfc6749#section-5.1>
+    pub fn expires_in_duration(&self) -> Option<Duration> {
+        self.expires_in
+            .map(|secs| Duration::from_secs(u64::try_from(secs).unwrap_or(u64::MAX)))
+    }
+
+    /// Wipes the access and refresh tokens now, rather than on drop.
+    ///
+    /// Both secrets are zeroized as they are dropped: the access token
+    /// is left empty, the refresh token `None` and the lifetime zero,
+    /// so [`Self::is_expired`] holds whatever the clock. Meant for
+    /// logout, after revocation: the tokens are gone for good, and
+    /// [`Self::bearer`] no longer yields a usable header.
+    pub fn clear_sensitive(&mut self) {
+        self.access_token = SecretString::default();
+        self.refresh_token = None;
+        self.expires_in = Some(0);
+        self.expires_at = None;
+    }
+
+    /// Returns whether the access token is expired at `now` (Unix epoch
+    /// seconds).
+    ///
+    /// A zero lifetime is expired whatever the clock. Otherwise the token
+    /// expires at `issued_at + expires_in`; without either, the expiry is
+    /// unknown and the token is considered valid until the resource server
+    /// says otherwise.
+    pub fn is_expired(&self, now: u64) -> bool {
+        match (self.expires_in, self.issued_at) {
+            (Some(0), _) => true,
+            (Some(expires_in), Some(issued_at)) => {
+                now >= issued_at.saturating_add(expires_in as u64)
+            }
+            _ => false,
+        }
+    }
+
+    /// Returns whether the access token needs a refresh at `now` (Unix
+    /// epoch seconds), per the refresh `policy`.
+    ///
+    /// Follows [`Self::is_expired`]: a zero lifetime always needs a refresh,
+    /// an unknown expiry never does. A bare [`Duration`] is a leeway.
+    pub fn needs_refresh(&self, policy: impl Into<Oauth20RefreshPolicy>, now: u64) -> bool {
+        if self.expires_in == Some(0) {
+            return true;
+        }
+
+        self.refresh_at(policy).is_some_and(|at| now >= at)
+    }
+
+    /// Returns when to refresh the access token per the refresh `policy`,
+    /// in Unix epoch seconds.
+    ///
+    /// `None` when the expiry is unknown (no `expires_in`, or no
+    /// `issued_at`): the token cannot be refreshed proactively.
+    pub fn refresh_at(&self, policy: impl Into<Oauth20RefreshPolicy>) -> Option<u64> {
+        let issued_at = self.issued_at?;
+        let expires_in = self.expires_in? as u64;
+        let expires_at = issued_at.saturating_add(expires_in);
+
+        match policy.into() {
+            Oauth20RefreshPolicy::Leeway(leeway) => {
+                Some(expires_at.saturating_sub(leeway.as_secs()))
+            }
+            Oauth20RefreshPolicy::Fraction(fraction) => {
+                // NOTE: rounds to the nearest second, without std
+                let fraction = fraction.clamp(0.0, 1.0) as f64;
+                let elapsed = (expires_in as f64 * fraction + 0.5) as u64;
+                Some(issued_at.saturating_add(elapsed.min(expires_in)))
+            }
+            Oauth20RefreshPolicy::Never => Some(expires_at),
+        }
+    }
+
+    /// Returns when to refresh the access token per the refresh `policy`,
+    /// shifted by up to `jitter` either way, in Unix epoch seconds.
+    ///
+    /// Staggers the refreshes of a fleet sharing a token lifetime, so
+    /// the replicas do not hit the authorization server at once. The
+    /// shift derives from `seed`: a random value (`rand::random()`)
+    /// spreads every refresh anew, a per-instance one (a hash of the
+    /// host name) keeps each replica on a stable slot. The result stays
+    /// within the token lifetime.
+    pub fn refresh_at_jittered(
+        &self,
+        policy: impl Into<Oauth20RefreshPolicy>,
+        jitter: Duration,
+        seed: u64,
+    ) -> Option<u64> {
+        let refresh_at = self.refresh_at(policy)?;
+        let issued_at = self.issued_at?;
+        let expires_at = issued_at.saturating_add(self.expires_in? as u64);
+
+        let jitter = jitter.as_secs();
+        let span = jitter.saturating_mul(2).saturating_add(1);
+        let shift = seed % span;
+
+        // NOTE: shift in 0..=2*jitter, centered back on refresh_at
+        let refresh_at = refresh_at.saturating_add(shift).saturating_sub(jitter);
+        Some(refresh_at.clamp(issued_at, expires_at))
+    }
+
+    /// Returns when to refresh the access token as a monotonic instant,
+    /// ready for a scheduler, following [`Self::refresh_at`].
+    ///
+    /// A refresh time already past maps to now; one too far ahead for
+    /// the monotonic clock maps to `None`, as an unknown expiry.
+    #[cfg(feature = "client")]
+    pub fn refresh_instant(
+        &self,
+        policy: impl Into<Oauth20RefreshPolicy>,
+    ) -> Option<std::time::Instant> {
+        use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+        let refresh_at = self.refresh_at(policy)?;
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).ok()?;
+        let delay = Duration::from_secs(refresh_at.saturating_sub(now.as_secs()));
+        Instant::now().checked_add(delay)
+    }
+
+    /// Returns the coroutine refreshing the access token, only when it
+    /// [needs a refresh](Self::needs_refresh) and a refresh token was issued.
+    ///
+    /// The refresh asks for the same scope, without client secret:
+    /// confidential clients build their [`Oauth20AccessTokenRefreshParams`]
+    /// after checking [`Self::needs_refresh`] themselves.
+    pub fn refresh_if_needed(
+        &self,
+        request: HttpRequest,
+        client_id: impl ToString,
+        policy: impl Into<Oauth20RefreshPolicy>,
+        now: u64,
+    ) -> Option<Oauth20AccessTokenRefresh> {
+        if !self.needs_refresh(policy, now) {
+            return None;
+        }
+
+        let refresh_token = self.refresh_token.clone()?;
+        let params = Oauth20AccessTokenRefreshParams::new(client_id, refresh_token);
+        Some(Oauth20AccessTokenRefresh::new(request, params))
+    }
+}
+
+/// When to refresh an access token, ahead of its expiry.
+///
+/// A fixed leeway suits long-lived tokens, a fraction of the lifetime
+/// short-lived ones: a 60s leeway on a 90s token refreshes it after
+/// only 30s. A [`Duration`] converts into a leeway.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Oauth20RefreshPolicy {
+    /// Refresh the given duration before the token expires.
+    Leeway(Duration),
+    /// Refresh once the given fraction of the lifetime elapsed, `0.9`
+    /// refreshing a 3600s token after 3240s. Clamped to `0.0..=1.0`.
+    Fraction(f32),
+    /// Never refresh ahead: only once the token expired.
+    Never,
+}
+
+impl From<Duration> for Oauth20RefreshPolicy {
+    fn from(leeway: Duration) -> Self {
+        Self::Leeway(leeway)
+    }
 }
 
 /// Parses an HTTP IMF-fixdate into Unix epoch seconds (UTC).
 ///
 /// Format: `Sun, 06 Nov 1994 08:49:37 GMT` (29 ASCII bytes); returns `None` on
-/// any structural deviation. Does not check that the day-of-month is legal for
-/// the month/year, relying on origin servers to send well-formed dates.
+/// any structural deviation, an out-of-range field (day 0 or past 31, hour
+/// past 23, minute past 59, second past 60), or a date before the epoch. Does
+/// not check that the day-of-month is legal for the month/year (`31 Feb`
+/// rolls over into March).
 pub fn parse_http_date(s: &str) -> Option<u64> {
     let b = s.as_bytes();
 
@@ -81,10 +526,87 @@ pub fn parse_http_date(s: &str) -> Option<u64> {
     let min = parse_2_digits(&b[20..22])? as u64;
     let sec = parse_2_digits(&b[23..25])? as u64;
 
+    if !(1..=31).contains(&day) || hour > 23 || min > 59 || sec > 60 {
+        return None;
+    }
+
+    epoch_seconds(year, month, day, hour * 3600 + min * 60 + sec)
+}
+
+/// Parses an RFC 3339 timestamp into Unix epoch seconds (UTC).
+///
+/// Format: `2024-05-01T12:30:00Z`, with optional fractional seconds
+/// (truncated) and a `Z` or `±HH:MM` offset; returns `None` on any
+/// structural deviation, or a date before the epoch.
+///
+/// Refs: <https://datatracker.ietf.org/doc/html/rfc3339#section-5.6>
+pub fn parse_rfc3339(s: &str) -> Option<u64> {
+    let b = s.as_bytes();
+
+    if b.len() < 20
+        || b[4] != b'-'
+        || b[7] != b'-'
+        || !matches!(b[10], b'T' | b't' | b' ')
+        || b[13] != b':'
+        || b[16] != b':'
+    {
+        return None;
+    }
+
+    let year = parse_4_digits(&b[0..4])? as u64;
+    let month = parse_2_digits(&b[5..7])? as u64;
+    let day = parse_2_digits(&b[8..10])? as u64;
+    let hour = parse_2_digits(&b[11..13])? as u64;
+    let min = parse_2_digits(&b[14..16])? as u64;
+    let sec = parse_2_digits(&b[17..19])? as u64;
+
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) || hour > 23 || min > 59 || sec > 60 {
+        return None;
+    }
+
+    let mut rest = &b[19..];
+
+    if let [b'.', frac @ ..] = rest {
+        let digits = frac.iter().take_while(|b| b.is_ascii_digit()).count();
+
+        if digits == 0 {
+            return None;
+        }
+
+        rest = &frac[digits..];
+    }
+
+    let secs = epoch_seconds(year, month, day, hour * 3600 + min * 60 + sec)?;
+
+    match rest {
+        [b'Z' | b'z'] => Some(secs),
+        [sign @ (b'+' | b'-'), h1, h2, b':', m1, m2] => {
+            let offset_hour = parse_2_digits(&[*h1, *h2])? as u64;
+            let offset_min = parse_2_digits(&[*m1, *m2])? as u64;
+
+            if offset_hour > 23 || offset_min > 59 {
+                return None;
+            }
+
+            let offset = offset_hour * 3600 + offset_min * 60;
+
+            // NOTE: the local time is ahead of UTC by a positive offset
+            match sign {
+                b'+' => secs.checked_sub(offset),
+                _ => secs.checked_add(offset),
+            }
+        }
+        _ => None,
+    }
+}
+
+/// Returns the Unix epoch seconds of a UTC civil date, `None` before
+/// the epoch.
+fn epoch_seconds(year: u64, month: u64, day: u64, secs_of_day: u64) -> Option<u64> {
     // NOTE: Howard Hinnant's days_from_civil algorithm; treats March as the
     // first month so the leap day lands at the end of the year.
     let (y, m) = if month <= 2 {
-        (year - 1, month + 9)
+        (year.checked_sub(1)?, month + 9)
     } else {
         (year, month - 3)
     };
@@ -92,9 +614,393 @@ pub fn parse_http_date(s: &str) -> Option<u64> {
     let yoe = y - era * 400;
     let doy = (153 * m + 2) / 5 + day - 1;
     let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
-    let days_from_epoch = era * 146097 + doe - 719468;
+    let days_from_epoch = (era * 146097 + doe).checked_sub(719468)?;
+
+    Some(days_from_epoch * 86400 + secs_of_day)
+}
+
+/// Parses a token endpoint response into success or error params.
+///
+/// Decodes the body per its `Content-Encoding` first, then branches on
+/// the status: success params stamped with the `Date` of the response,
+/// or error params carrying its `Retry-After`. An empty body is an
+/// [`Oauth20AccessTokenResponseError::EmptyBody`], whatever the status.
+/// The token coroutines parse their response with it; a consumer owning
+/// its transport (an HTTP/2 stack, an `http::Response`) converts the
+/// response into an [`HttpResponse`] and gets the very same parsing.
+///
+/// Refs: <https://datatracker.ietf.org/doc/html/rfc6749#section-5>
+pub fn parse_access_token_response(
+    response: &HttpResponse,
+) -> Result<Oauth20AccessTokenResponse, Oauth20AccessTokenResponseError> {
+    let body = decode_body(response)?;
+
+    if body.trim_ascii().is_empty() {
+        return Err(Oauth20AccessTokenResponseError::EmptyBody(*response.status));
+    }
+
+    let parse_error = || {
+        let mut res = Oauth20AccessTokenErrorParams::try_from(body.as_ref())?;
+        res.retry_after = response
+            .header("retry-after")
+            .and_then(|after| parse_retry_after(after, response.header("date")));
+
+        if *response.status == 401 {
+            res.client_auth_challenge =
+                Oauth20ClientAuthMethod::from_challenges(&response.challenges());
+        }
+
+        Ok(Err(res))
+    };
+
+    if !response.status.is_success() {
+        return parse_error();
+    }
+
+    match Oauth20AccessTokenSuccessParams::try_from(body.as_ref()) {
+        Ok(mut res) => {
+            res.issued_at = response.header("date").and_then(parse_http_date);
+
+            if res.expires_in.is_none()
+                && let (Some(expires_at), Some(issued_at)) = (res.expires_at, res.issued_at)
+            {
+                let expires_in = expires_at.saturating_sub(issued_at);
+                res.expires_in = Some(usize::try_from(expires_in).unwrap_or(usize::MAX));
+            }
+
+            Ok(Ok(res))
+        }
+        // NOTE: some providers (GitHub among them) answer errors with
+        // a 200 status, so fall back to the error params
+        Err(err) => parse_error().or(Err(err.into())),
+    }
+}
+
+/// Parses a `Retry-After` header value, in delta-seconds or HTTP-date form.
+///
+/// The HTTP-date form is resolved against `date`, the `Date` header of the
+/// same response, so both instants come from the server's clock; `None`
+/// without it.
+///
+/// Refs: <https://datatracker.ietf.org/doc/html/rfc9110#section-10.2.3>
+pub fn parse_retry_after(value: &str, date: Option<&str>) -> Option<Duration> {
+    let value = value.trim();
+
+    if let Ok(secs) = value.parse::<u64>() {
+        return Some(Duration::from_secs(secs));
+    }
+
+    let after = parse_http_date(value)?;
+    let date = parse_http_date(date?)?;
+    Some(Duration::from_secs(after.saturating_sub(date)))
+}
+
+/// Decodes the response body per its `Content-Encoding`, codings applied
+/// last being removed first.
+///
+/// Refs: <https://datatracker.ietf.org/doc/html/rfc9110#section-8.4>
+fn decode_body(response: &HttpResponse) -> Result<Cow<'_, [u8]>, Oauth20AccessTokenResponseError> {
+    let mut body = Cow::Borrowed(response.body.as_slice());
+
+    let Some(encodings) = response.header("content-encoding") else {
+        return Ok(body);
+    };
+
+    for encoding in encodings.rsplit(',').map(str::trim) {
+        if encoding.is_empty() || encoding.eq_ignore_ascii_case("identity") {
+            continue;
+        }
+
+        body = Cow::Owned(decode(encoding, &body)?);
+    }
+
+    Ok(body)
+}
+
+/// The size a compressed token endpoint response body may decode to.
+///
+/// Token responses are a few kilobytes at most; the cap keeps a
+/// hostile or broken server from exhausting memory with a small
+/// compressed body. Going over it fails as
+/// [`Oauth20AccessTokenResponseError::Decompress`].
+#[cfg(feature = "compression")]
+pub const OAUTH20_DECOMPRESSED_BODY_LIMIT: usize = 1024 * 1024;
+
+/// Removes a single content coding.
+#[cfg(feature = "compression")]
+fn decode(encoding: &str, bytes: &[u8]) -> Result<Vec<u8>, Oauth20AccessTokenResponseError> {
+    let decoded =
+        if encoding.eq_ignore_ascii_case("gzip") || encoding.eq_ignore_ascii_case("x-gzip") {
+            gunzip(bytes)
+        } else if encoding.eq_ignore_ascii_case("deflate") {
+            // NOTE: deflate is zlib-wrapped (RFC 9110 §8.4.1.2), though some
+            // servers send it raw
+            let limit = OAUTH20_DECOMPRESSED_BODY_LIMIT;
+            miniz_oxide::inflate::decompress_to_vec_zlib_with_limit(bytes, limit)
+                .or_else(|_| miniz_oxide::inflate::decompress_to_vec_with_limit(bytes, limit))
+                .ok()
+        } else {
+            let encoding = encoding.into();
+            return Err(Oauth20AccessTokenResponseError::UnsupportedContentEncoding(
+                encoding,
+            ));
+        };
+
+    decoded.ok_or_else(|| Oauth20AccessTokenResponseError::Decompress(encoding.into()))
+}
+
+/// Removes a single content coding, none being supported without the
+/// `compression` feature.
+#[cfg(not(feature = "compression"))]
+fn decode(encoding: &str, _bytes: &[u8]) -> Result<Vec<u8>, Oauth20AccessTokenResponseError> {
+    let encoding = encoding.into();
+    Err(Oauth20AccessTokenResponseError::UnsupportedContentEncoding(
+        encoding,
+    ))
+}
+
+/// Decompresses a gzip member.
+///
+/// Refs: <https://datatracker.ietf.org/doc/html/rfc1952#section-2.3>
+#[cfg(feature = "compression")]
+fn gunzip(bytes: &[u8]) -> Option<Vec<u8>> {
+    const FHCRC: u8 = 0x02;
+    const FEXTRA: u8 = 0x04;
+    const FNAME: u8 = 0x08;
+    const FCOMMENT: u8 = 0x10;
+
+    // NOTE: ID1 ID2 CM FLG MTIME(4) XFL OS, CM 8 being deflate
+    if bytes.len() < 18 || bytes[..3] != [0x1f, 0x8b, 0x08] {
+        return None;
+    }
+
+    let flags = bytes[3];
+    let mut pos = 10;
+
+    if flags & FEXTRA != 0 {
+        let len = u16::from_le_bytes([*bytes.get(pos)?, *bytes.get(pos + 1)?]);
+        pos += 2 + len as usize;
+    }
+
+    for flag in [FNAME, FCOMMENT] {
+        if flags & flag != 0 {
+            pos += bytes.get(pos..)?.iter().position(|b| *b == 0)? + 1;
+        }
+    }
+
+    if flags & FHCRC != 0 {
+        pos += 2;
+    }
+
+    // NOTE: the member ends with CRC32 and ISIZE, the input size mod 2^32
+    let end = bytes.len().checked_sub(8)?;
+    let (deflated, trailer) = (bytes.get(pos..end)?, &bytes[end..]);
+    let body = miniz_oxide::inflate::decompress_to_vec_with_limit(
+        deflated,
+        OAUTH20_DECOMPRESSED_BODY_LIMIT,
+    )
+    .ok()?;
+    let size = u32::from_le_bytes(trailer[4..].try_into().ok()?);
+
+    (body.len() as u32 == size).then_some(body)
+}
+
+/// The progress of a token endpoint coroutine, between two `resume`.
+///
+/// Meant for diagnostics: logging it between calls tells what I/O a
+/// stuck exchange waits on. Connecting the stream happens before the
+/// coroutine starts, and parsing the response within the last
+/// `resume`, so neither shows as a state.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum Oauth20CoroutineState {
+    /// Not resumed yet.
+    #[default]
+    Ready,
+    /// Wants the yielded bytes to be written to the stream.
+    Writing,
+    /// Wants the stream to be read into.
+    Reading,
+    /// Returned its output or an error, or was cancelled.
+    Done,
+}
+
+impl Oauth20CoroutineState {
+    /// Moves to the next state, tracing the transition.
+    pub(crate) fn transition(&mut self, next: Self) {
+        if *self != next {
+            trace!("coroutine state: {self:?} -> {next:?}");
+            *self = next;
+        }
+    }
+}
+
+/// The errors of a token coroutine, built by [`Oauth20TokenCoroutine`].
+pub(crate) trait Oauth20TokenCoroutineError:
+    From<Http11SendError> + From<Oauth20AccessTokenResponseError>
+{
+    /// The server answered with an unexpected redirection.
+    fn redirect(url: Url, code: u16) -> Self;
+
+    /// The coroutine was cancelled by the caller.
+    fn cancelled() -> Self;
+}
+
+/// Result returned by [`Oauth20TokenCoroutine::resume`], mapped by
+/// every token coroutine onto its own result.
+#[derive(Debug)]
+pub(crate) enum Oauth20TokenCoroutineResult<E> {
+    Ok(Oauth20AccessTokenResponse),
+    WantsRead,
+    WantsWrite(Vec<u8>),
+    Err(Oauth20RequestError<E>),
+}
+
+/// The token endpoint exchange every token coroutine delegates to:
+/// sends the request, parses the response, and keeps what the
+/// coroutines expose on top (request identifier, keep-alive, state,
+/// retained request, cancellation).
+#[derive(Debug)]
+pub(crate) struct Oauth20TokenCoroutine {
+    name: &'static str,
+    send: Http11Send,
+    request: Option<HttpRequest>,
+    request_id: Option<String>,
+    cancelled: bool,
+    keep_alive: bool,
+    state: Oauth20CoroutineState,
+}
+
+impl Oauth20TokenCoroutine {
+    /// Creates the exchange sending `request` as-is, `name` labelling
+    /// its logs.
+    pub(crate) fn new(name: &'static str, request: HttpRequest) -> Self {
+        debug!("prepare {name} request");
+        trace!("url: {}", request.url);
+
+        let request_id = find_request_id(&request);
+
+        Self {
+            name,
+            send: Http11Send::new(request),
+            request: None,
+            request_id,
+            cancelled: false,
+            keep_alive: false,
+            state: Oauth20CoroutineState::Ready,
+        }
+    }
+
+    /// Creates the exchange like [`Self::new`], keeping a copy of
+    /// `request` for [`Self::into_request`].
+    // SAFETY: the retained body keeps the params' secrets in memory
+    pub(crate) fn retained(name: &'static str, request: HttpRequest) -> Self {
+        Self {
+            request: Some(request.clone()),
+            ..Self::new(name, request)
+        }
+    }
+
+    pub(crate) fn request_id(&self) -> Option<&str> {
+        self.request_id.as_deref()
+    }
+
+    pub(crate) fn keep_alive(&self) -> bool {
+        self.keep_alive
+    }
+
+    pub(crate) fn state(&self) -> Oauth20CoroutineState {
+        self.state
+    }
+
+    // SAFETY: the returned body exposes the params' secrets
+    pub(crate) fn into_request(self) -> Option<HttpRequest> {
+        self.request
+    }
+
+    pub(crate) fn cancel(&mut self) {
+        self.cancelled = true;
+        self.state.transition(Oauth20CoroutineState::Done);
+    }
+
+    pub(crate) fn resume<E: Oauth20TokenCoroutineError>(
+        &mut self,
+        arg: Option<&[u8]>,
+    ) -> Oauth20TokenCoroutineResult<E> {
+        if self.cancelled {
+            return self.error(E::cancelled());
+        }
+
+        let result = match self.send.resume(arg) {
+            HttpCoroutineState::Complete(Ok(HttpSendOutput {
+                response,
+                keep_alive,
+                ..
+            })) => {
+                self.keep_alive = keep_alive;
+
+                debug!("received {} response", self.name);
+                trace!("status: {}", *response.status);
+
+                match parse_access_token_response(&response) {
+                    Ok(res) => Oauth20TokenCoroutineResult::Ok(res),
+                    Err(err) => self.error(err),
+                }
+            }
+            HttpCoroutineState::Yielded(HttpSendYield::WantsRead) => {
+                Oauth20TokenCoroutineResult::WantsRead
+            }
+            HttpCoroutineState::Yielded(HttpSendYield::WantsWrite(bytes)) => {
+                Oauth20TokenCoroutineResult::WantsWrite(bytes)
+            }
+            HttpCoroutineState::Yielded(HttpSendYield::WantsRedirect { url, response, .. }) => {
+                self.error(E::redirect(url, *response.status))
+            }
+            HttpCoroutineState::Complete(Err(err)) => self.error(err),
+        };
+
+        self.state.transition(match &result {
+            Oauth20TokenCoroutineResult::WantsRead => Oauth20CoroutineState::Reading,
+            Oauth20TokenCoroutineResult::WantsWrite(_) => Oauth20CoroutineState::Writing,
+            Oauth20TokenCoroutineResult::Ok(_) | Oauth20TokenCoroutineResult::Err(_) => {
+                Oauth20CoroutineState::Done
+            }
+        });
+
+        result
+    }
+
+    fn error<E>(&self, err: impl Into<E>) -> Oauth20TokenCoroutineResult<E> {
+        Oauth20TokenCoroutineResult::Err(Oauth20RequestError::new(self.request_id.clone(), err))
+    }
+}
 
-    Some(days_from_epoch * 86400 + hour * 3600 + min * 60 + sec)
+/// Returns the value of the [`OAUTH20_REQUEST_ID_HEADER`] of `request`.
+pub(crate) fn find_request_id(request: &HttpRequest) -> Option<String> {
+    request
+        .headers
+        .iter()
+        .find(|(k, _)| k.eq_ignore_ascii_case(OAUTH20_REQUEST_ID_HEADER))
+        .map(|(_, v)| v.clone())
+}
+
+/// Adds `Accept: application/json` to `request`, unless the caller
+/// already set an `Accept` header.
+///
+/// Token responses are JSON by the specification, yet a few providers
+/// (GitHub among them) answer form-urlencoded bodies unless asked.
+///
+/// Refs: <https://datatracker.ietf.org/doc/html/rfc6749#section-5.1>
+pub(crate) fn accept_json(request: HttpRequest) -> HttpRequest {
+    let accept = request
+        .headers
+        .iter()
+        .any(|(k, _)| k.eq_ignore_ascii_case("Accept"));
+
+    if accept {
+        request
+    } else {
+        request.header("Accept", "application/json")
+    }
 }
 
 fn parse_2_digits(b: &[u8]) -> Option<u32> {
@@ -129,19 +1035,106 @@ impl TryFrom<&[u8]> for Oauth20AccessTokenSuccessParams {
     }
 }
 
+/// Deserializes success params from a JSON string.
+impl TryFrom<&str> for Oauth20AccessTokenSuccessParams {
+    type Error = serde_json::Error;
+
+    fn try_from(json: &str) -> Result<Self, Self::Error> {
+        Self::try_from(json.as_bytes())
+    }
+}
+
+/// Deserializes success params from a JSON string.
+impl FromStr for Oauth20AccessTokenSuccessParams {
+    type Err = serde_json::Error;
+
+    fn from_str(json: &str) -> Result<Self, Self::Err> {
+        Self::try_from(json)
+    }
+}
+
 /// The error access token response.
 ///
+/// Serializes back into the JSON error response, minus the header-borne
+/// fields (`retry_after`, `client_auth_challenge`).
+///
 /// Refs: <https://datatracker.ietf.org/doc/html/rfc6749#section-5.2>
-#[derive(Clone, Debug, Deserialize)]
+#[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct Oauth20AccessTokenErrorParams {
     /// A single ASCII error code.
     pub error: Oauth20AccessTokenErrorCode,
     /// Human-readable text explaining the error.
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub error_description: Option<String>,
     /// A URI to a human-readable page about the error.
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub error_uri: Option<String>,
+    /// How long to wait before retrying, from the `Retry-After` header.
+    ///
+    /// Outside the OAuth specs; sent along `slow_down` or a 429 or 503
+    /// status, and honored in place of a guessed interval when present.
+    #[serde(skip)]
+    pub retry_after: Option<Duration>,
+    /// The client authentication method the server challenged with,
+    /// from the `WWW-Authenticate` header of a 401 response.
+    ///
+    /// See [`Oauth20ClientAuthMethod::from_challenges`].
+    #[serde(skip)]
+    pub client_auth_challenge: Option<Oauth20ClientAuthMethod>,
 }
 
+impl Oauth20AccessTokenErrorParams {
+    /// Returns whether repeating the same request later may succeed.
+    ///
+    /// True for a pending device authorization (`authorization_pending`,
+    /// `slow_down`), for a server failure (`server_error`,
+    /// `temporarily_unavailable`), and for any error the server sent a
+    /// `Retry-After` along, as overloaded servers do with a 429 or 503
+    /// status. Wait [`Self::retry_after`] first when present.
+    pub fn is_retryable(&self) -> bool {
+        use Oauth20AccessTokenErrorCode::*;
+
+        self.retry_after.is_some()
+            || matches!(
+                self.error,
+                AuthorizationPending | SlowDown | ServerError | TemporarilyUnavailable
+            )
+    }
+
+    /// Returns whether the grant is over and the end user must authorize
+    /// the client again.
+    ///
+    /// True when the authorization code, the refresh token or the device
+    /// code is dead: `invalid_grant` (expired, revoked or already used),
+    /// `expired_token` and `bad_verification_code`. Retrying the request
+    /// as-is cannot succeed; restart the grant instead.
+    ///
+    /// Refs: <https://datatracker.ietf.org/doc/html/rfc6749#section-5.2>,
+    ///       <https://datatracker.ietf.org/doc/html/rfc8628#section-3.5>
+    pub fn requires_reauthorization(&self) -> bool {
+        use Oauth20AccessTokenErrorCode::*;
+
+        matches!(
+            self.error,
+            InvalidGrant | ExpiredToken | BadVerificationCode
+        )
+    }
+}
+
+impl fmt::Display for Oauth20AccessTokenErrorParams {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.error.as_str())?;
+
+        if let Some(description) = &self.error_description {
+            write!(f, ": {description}")?;
+        }
+
+        Ok(())
+    }
+}
+
+impl core::error::Error for Oauth20AccessTokenErrorParams {}
+
 /// Parses error params from JSON bytes.
 impl TryFrom<&[u8]> for Oauth20AccessTokenErrorParams {
     type Error = serde_json::Error;
@@ -152,7 +1145,13 @@ impl TryFrom<&[u8]> for Oauth20AccessTokenErrorParams {
 }
 
 /// The error code of the [`Oauth20AccessTokenErrorParams`].
-#[derive(Clone, Debug, Eq, PartialEq, Deserialize)]
+///
+/// Codes match their wire value exactly, case included: a code sent in
+/// another casing (`Invalid_Grant`) is not registered, hence `Unknown`,
+/// which serializes as `unknown`.
+///
+/// Refs: <https://datatracker.ietf.org/doc/html/rfc6749#section-5.2>
+#[derive(Clone, Debug, Eq, PartialEq, Deserialize, Serialize)]
 #[serde(rename_all = "snake_case")]
 pub enum Oauth20AccessTokenErrorCode {
     /// Client authentication failed.
@@ -195,11 +1194,51 @@ pub enum Oauth20AccessTokenErrorCode {
     ///
     /// Refs: <https://datatracker.ietf.org/doc/html/rfc8707#section-3>
     InvalidTarget,
+    /// The server met an unexpected condition.
+    ///
+    /// Registered for the authorization endpoint, though servers send it
+    /// from the token endpoint too, along a 500 status.
+    ///
+    /// Refs: <https://datatracker.ietf.org/doc/html/rfc6749#section-4.1.2.1>
+    ServerError,
+    /// The server is temporarily overloaded or under maintenance.
+    ///
+    /// Registered for the authorization endpoint, though servers send it
+    /// from the token endpoint too, along a 503 status.
+    ///
+    /// Refs: <https://datatracker.ietf.org/doc/html/rfc6749#section-4.1.2.1>
+    TemporarilyUnavailable,
     /// Any unregistered code, kept for provider-specific extensions.
     #[serde(other)]
     Unknown,
 }
 
+impl Oauth20AccessTokenErrorCode {
+    /// Returns the code's wire value, `unknown` for unregistered codes.
+    ///
+    /// Stable and low-cardinality, fit for metric labels.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::InvalidClient => "invalid_client",
+            Self::InvalidGrant => "invalid_grant",
+            Self::InvalidRequest => "invalid_request",
+            Self::InvalidScope => "invalid_scope",
+            Self::UnauthorizedClient => "unauthorized_client",
+            Self::UnsupportedGrantType => "unsupported_grant_type",
+            Self::AuthorizationPending => "authorization_pending",
+            Self::SlowDown => "slow_down",
+            Self::AccessDenied => "access_denied",
+            Self::ExpiredToken => "expired_token",
+            Self::AuthorizationDeclined => "authorization_declined",
+            Self::BadVerificationCode => "bad_verification_code",
+            Self::InvalidTarget => "invalid_target",
+            Self::ServerError => "server_error",
+            Self::TemporarilyUnavailable => "temporarily_unavailable",
+            Self::Unknown => "unknown",
+        }
+    }
+}
+
 fn serialize_secret_string<S: Serializer>(secret: &SecretString, s: S) -> Result<S::Ok, S::Error> {
     s.serialize_str(secret.expose_secret())
 }
@@ -213,3 +1252,831 @@ fn serialize_opt_secret_string<S: Serializer>(
         None => s.serialize_none(),
     }
 }
+
+fn deserialize_expires_in<'de, D: Deserializer<'de>>(d: D) -> Result<Option<usize>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Lifetime {
+        Signed(i64),
+        Unsigned(u64),
+        Text(String),
+    }
+
+    let secs = match Option::<Lifetime>::deserialize(d)? {
+        None => return Ok(None),
+        Some(Lifetime::Signed(secs)) => secs,
+        Some(Lifetime::Unsigned(secs)) => i64::try_from(secs).unwrap_or(i64::MAX),
+        Some(Lifetime::Text(secs)) => secs.trim().parse().map_err(D::Error::custom)?,
+    };
+
+    // NOTE: a negative lifetime means the token is already expired
+    Ok(Some(usize::try_from(secs.max(0)).unwrap_or(usize::MAX)))
+}
+
+fn deserialize_expires_at<'de, D: Deserializer<'de>>(d: D) -> Result<Option<u64>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Instant {
+        Epoch(u64),
+        Text(String),
+        Other(serde::de::IgnoredAny),
+    }
+
+    // NOTE: the field is a vendor extension, so a malformed value is
+    // ignored rather than failing the whole response
+    Ok(match Option::<Instant>::deserialize(d)? {
+        Some(Instant::Epoch(secs)) => Some(secs),
+        Some(Instant::Text(date)) => parse_rfc3339(date.trim()),
+        Some(Instant::Other(_)) | None => None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::{
+        boxed::Box,
+        format,
+        string::{String, ToString},
+        vec,
+        vec::Vec,
+    };
+
+    use io_http::rfc9110::{request::HttpRequest, status::HttpStatusCode};
+
+    use crate::rfc6749::{
+        access_token_request::Oauth20AccessTokenRequestError, issue_access_token::*,
+    };
+
+    type Oauth20TestCoroutineResult = Oauth20TokenCoroutineResult<Oauth20AccessTokenRequestError>;
+
+    fn token_request() -> HttpRequest {
+        HttpRequest {
+            method: "POST".into(),
+            url: Url::parse("https://example.com/token").unwrap(),
+            headers: Vec::new(),
+            body: b"grant_type=client_credentials".to_vec(),
+        }
+    }
+
+    fn token_response(connection: &str) -> String {
+        let body = r#"{"access_token":"a","token_type":"Bearer"}"#;
+        format!(
+            "HTTP/1.1 200 OK\r\nConnection: {connection}\r\nContent-Length: {}\r\n\r\n{body}",
+            body.len()
+        )
+    }
+
+    fn resume(
+        coroutine: &mut Oauth20TokenCoroutine,
+        arg: Option<&[u8]>,
+    ) -> Oauth20TestCoroutineResult {
+        coroutine.resume(arg)
+    }
+
+    fn parse(json: &str) -> Oauth20AccessTokenSuccessParams {
+        json.parse().unwrap()
+    }
+
+    fn response(encoding: &str, body: &[u8]) -> HttpResponse {
+        HttpResponse {
+            status: HttpStatusCode(200),
+            version: "HTTP/1.1".into(),
+            headers: vec![("content-encoding".into(), encoding.into())],
+            body: body.to_vec(),
+        }
+    }
+
+    #[test]
+    fn error_body_with_success_status_is_an_error() {
+        let body = br#"{"error":"invalid_grant","error_description":"bad code"}"#;
+        let res = parse_access_token_response(&response("identity", body)).unwrap();
+        let err = res.unwrap_err();
+        assert_eq!(err.error, Oauth20AccessTokenErrorCode::InvalidGrant);
+        assert_eq!(err.error_description.as_deref(), Some("bad code"));
+
+        let res = parse_access_token_response(&response("identity", b"{}"));
+        assert!(matches!(
+            res,
+            Err(Oauth20AccessTokenResponseError::ParseJson(_))
+        ));
+    }
+
+    #[test]
+    fn empty_body_is_reported_with_its_status() {
+        let mut res = response("identity", b"");
+        assert!(matches!(
+            parse_access_token_response(&res),
+            Err(Oauth20AccessTokenResponseError::EmptyBody(200))
+        ));
+
+        res.status = HttpStatusCode(401);
+        res.body = b" \r\n".to_vec();
+        assert!(matches!(
+            parse_access_token_response(&res),
+            Err(Oauth20AccessTokenResponseError::EmptyBody(401))
+        ));
+    }
+
+    #[test]
+    fn unsupported_content_encoding_is_reported() {
+        let res = parse_access_token_response(&response("br", b"\x1b\x00"));
+        assert!(matches!(
+            res,
+            Err(Oauth20AccessTokenResponseError::UnsupportedContentEncoding(e)) if e == "br"
+        ));
+
+        let res = parse_access_token_response(&response(
+            "identity",
+            br#"{"access_token":"a","token_type":"Bearer"}"#,
+        ));
+        assert!(matches!(res, Ok(Ok(_))));
+    }
+
+    #[cfg(feature = "compression")]
+    #[test]
+    fn gzip_body_is_decoded() {
+        // NOTE: {"access_token":"a","token_type":"Bearer"}, gzipped
+        const GZIP: [u8; 57] = [
+            0x1f, 0x8b, 0x08, 0x00, 0x00, 0x00, 0x00, 0x00, 0x02, 0x03, 0xab, 0x56, 0x4a, 0x4c,
+            0x4e, 0x4e, 0x2d, 0x2e, 0x8e, 0x2f, 0xc9, 0xcf, 0x4e, 0xcd, 0x53, 0xb2, 0x52, 0x4a,
+            0x54, 0xd2, 0x51, 0x02, 0xb3, 0xe3, 0x4b, 0x2a, 0x0b, 0x52, 0x81, 0x02, 0x4e, 0xa9,
+            0x89, 0x45, 0xa9, 0x45, 0x4a, 0xb5, 0x00, 0x95, 0x61, 0x73, 0x2b, 0x2a, 0x00, 0x00,
+            0x00,
+        ];
+
+        let Ok(Ok(params)) = parse_access_token_response(&response("gzip", &GZIP)) else {
+            panic!("expected success params");
+        };
+        assert_eq!(params.access_token.expose_secret(), "a");
+
+        let res = parse_access_token_response(&response("gzip", &GZIP[..40]));
+        assert!(matches!(
+            res,
+            Err(Oauth20AccessTokenResponseError::Decompress(_))
+        ));
+
+        // NOTE: a file name running into the trailer
+        let mut header = vec![0x1f, 0x8b, 0x08, 0x08, 0, 0, 0, 0, 0, 0];
+        header.extend_from_slice(b"trailer\0");
+        let res = parse_access_token_response(&response("gzip", &header));
+        assert!(matches!(
+            res,
+            Err(Oauth20AccessTokenResponseError::Decompress(_))
+        ));
+    }
+
+    #[cfg(feature = "compression")]
+    #[test]
+    fn decompressed_body_over_the_limit_is_rejected() {
+        use miniz_oxide::deflate::{compress_to_vec, compress_to_vec_zlib};
+
+        let json = br#"{"access_token":"a","token_type":"Bearer"}"#;
+        let mut body = json.to_vec();
+        body.resize(OAUTH20_DECOMPRESSED_BODY_LIMIT + 1, b' ');
+
+        let res = parse_access_token_response(&response("deflate", &compress_to_vec_zlib(json, 6)));
+        assert!(matches!(res, Ok(Ok(_))));
+
+        for deflated in [compress_to_vec_zlib(&body, 6), compress_to_vec(&body, 6)] {
+            let res = parse_access_token_response(&response("deflate", &deflated));
+            assert!(matches!(
+                res,
+                Err(Oauth20AccessTokenResponseError::Decompress(_))
+            ));
+        }
+
+        // NOTE: a gzip member around the same raw deflate stream
+        let mut gzip = vec![0x1f, 0x8b, 0x08, 0, 0, 0, 0, 0, 0, 0xff];
+        gzip.extend(compress_to_vec(&body, 6));
+        gzip.extend([0; 4]);
+        gzip.extend((body.len() as u32).to_le_bytes());
+        let res = parse_access_token_response(&response("gzip", &gzip));
+        assert!(matches!(
+            res,
+            Err(Oauth20AccessTokenResponseError::Decompress(_))
+        ));
+    }
+
+    #[test]
+    fn error_code_wire_value_round_trips() {
+        let params = Oauth20AccessTokenErrorParams::try_from(
+            br#"{"error":"authorization_pending"}"#.as_slice(),
+        )
+        .unwrap();
+        assert_eq!(params.error.as_str(), "authorization_pending");
+
+        let params =
+            Oauth20AccessTokenErrorParams::try_from(br#"{"error":"vendor_quirk"}"#.as_slice())
+                .unwrap();
+        assert_eq!(params.error.as_str(), "unknown");
+    }
+
+    #[test]
+    fn retry_after_in_both_forms() {
+        assert_eq!(
+            parse_retry_after(" 120 ", None),
+            Some(Duration::from_secs(120))
+        );
+
+        let date = Some("Sun, 06 Nov 1994 08:49:37 GMT");
+        let after = "Sun, 06 Nov 1994 08:50:07 GMT";
+        assert_eq!(
+            parse_retry_after(after, date),
+            Some(Duration::from_secs(30))
+        );
+        assert_eq!(parse_retry_after(after, None), None);
+        assert_eq!(parse_retry_after("soon", date), None);
+
+        let mut response = response("identity", br#"{"error":"slow_down"}"#);
+        response.status = HttpStatusCode(400);
+        response.headers.push(("retry-after".into(), "10".into()));
+
+        let Ok(Err(params)) = parse_access_token_response(&response) else {
+            panic!("expected error params");
+        };
+        assert_eq!(params.error, Oauth20AccessTokenErrorCode::SlowDown);
+        assert_eq!(params.retry_after, Some(Duration::from_secs(10)));
+    }
+
+    #[test]
+    fn http_date_before_the_epoch_is_rejected() {
+        assert_eq!(parse_http_date("Thu, 01 Jan 1970 00:00:00 GMT"), Some(0));
+        assert_eq!(parse_http_date("Wed, 31 Dec 1969 23:59:59 GMT"), None);
+        assert_eq!(parse_http_date("Sat, 01 Jan 0000 00:00:00 GMT"), None);
+        assert_eq!(parse_http_date("Sun, 06 Nov 1994 08:49:37 GMTé"), None);
+        assert_eq!(parse_http_date("Sun, 00 Mar 1994 08:49:37 GMT"), None);
+        assert_eq!(parse_http_date("Sun, 32 Mar 1994 08:49:37 GMT"), None);
+        assert_eq!(parse_http_date("Sun, 06 Nov 1994 24:49:37 GMT"), None);
+        assert_eq!(parse_http_date("Sun, 06 Nov 1994 08:60:37 GMT"), None);
+        assert_eq!(parse_http_date("Sun, 06 Nov 1994 08:49:61 GMT"), None);
+    }
+
+    #[test]
+    fn rfc3339_timestamps_resolve_to_utc() {
+        assert_eq!(parse_rfc3339("1994-11-06T08:49:37Z"), Some(784111777));
+        assert_eq!(
+            parse_rfc3339("1994-11-06T08:49:37.123456z"),
+            Some(784111777)
+        );
+        assert_eq!(parse_rfc3339("1994-11-06T10:49:37+02:00"), Some(784111777));
+        assert_eq!(parse_rfc3339("1994-11-06 03:49:37-05:00"), Some(784111777));
+        assert_eq!(parse_rfc3339("1970-01-01T00:00:00+00:01"), None);
+        assert_eq!(parse_rfc3339("1994-13-06T08:49:37Z"), None);
+        assert_eq!(parse_rfc3339("1994-11-06T08:49:37"), None);
+        assert_eq!(parse_rfc3339("1994-11-06T08:49:37.Z"), None);
+    }
+
+    #[test]
+    fn expires_at_derives_expires_in() {
+        let body =
+            br#"{"access_token":"a","token_type":"Bearer","expires_at":"1994-11-06T09:49:37Z"}"#;
+        let mut response = response("identity", body);
+        response
+            .headers
+            .push(("date".into(), "Sun, 06 Nov 1994 08:49:37 GMT".into()));
+
+        let params = parse_access_token_response(&response).unwrap().unwrap();
+        assert_eq!(params.expires_at, Some(784115377));
+        assert_eq!(params.expires_in, Some(3600));
+        assert!(!params.is_expired(784115376));
+        assert!(params.is_expired(784115377));
+
+        let params = parse(r#"{"access_token":"a","token_type":"Bearer","expires_at":"soon"}"#);
+        assert_eq!(params.expires_at, None);
+    }
+
+    #[test]
+    fn expires_in_as_duration() {
+        let params = parse(r#"{"access_token":"a","token_type":"Bearer","expires_in":3600}"#);
+        assert_eq!(
+            params.expires_in_duration(),
+            Some(Duration::from_secs(3600))
+        );
+
+        let params = parse(r#"{"access_token":"a","token_type":"Bearer"}"#);
+        assert_eq!(params.expires_in_duration(), None);
+    }
+
+    #[test]
+    fn cleared_params_hold_no_token() {
+        let mut params = parse(
+            r#"{"access_token":"s3cr3t-a","token_type":"Bearer","refresh_token":"s3cr3t-r"}"#,
+        );
+        params.clear_sensitive();
+
+        assert_eq!(params.access_token.expose_secret(), "");
+        assert!(params.refresh_token.is_none());
+        assert!(params.is_expired(0));
+        assert!(!String::try_from(&params).unwrap().contains("s3cr3t"));
+    }
+
+    #[test]
+    fn expires_in_zero_or_negative_is_expired() {
+        let mut params = parse(r#"{"access_token":"a","token_type":"Bearer","expires_in":0}"#);
+        assert_eq!(params.expires_in, Some(0));
+        assert!(params.is_expired(0));
+
+        params.issued_at = Some(1000);
+        assert!(params.is_expired(1000));
+
+        let params = parse(r#"{"access_token":"a","token_type":"Bearer","expires_in":-60}"#);
+        assert_eq!(params.expires_in, Some(0));
+        assert!(params.is_expired(0));
+
+        let params = parse(r#"{"access_token":"a","token_type":"Bearer","expires_in":"-60"}"#);
+        assert_eq!(params.expires_in, Some(0));
+    }
+
+    #[test]
+    fn only_bearer_tokens_become_bearer_headers() {
+        let params = parse(r#"{"access_token":"a","token_type":"bearer"}"#);
+        assert_eq!(params.bearer().unwrap().to_authorization(), "Bearer a");
+
+        let params = parse(r#"{"access_token":"a","token_type":"mac"}"#);
+        let err = params.bearer().unwrap_err();
+        assert!(matches!(
+            &err,
+            Oauth20TokenTypeError::Unsupported { expected: "Bearer", actual } if actual == "mac"
+        ));
+        assert_eq!(
+            err.to_string(),
+            "Unsupported OAuth 2.0 token type `mac` (expected `Bearer`)"
+        );
+    }
+
+    #[cfg(feature = "legacy-bearer-query")]
+    #[test]
+    fn access_token_query_is_appended_to_bearer_tokens_only() {
+        let params = parse(r#"{"access_token":"a b","token_type":"Bearer"}"#);
+        let mut url = url::Url::parse("https://api.example.com/items?page=2").unwrap();
+        params.append_access_token_query(&mut url).unwrap();
+        assert_eq!(url.query(), Some("page=2&access_token=a+b"));
+
+        let params = parse(r#"{"access_token":"a","token_type":"mac"}"#);
+        let mut url = url::Url::parse("https://api.example.com/items").unwrap();
+        assert!(params.append_access_token_query(&mut url).is_err());
+        assert_eq!(url.query(), None);
+    }
+
+    #[test]
+    fn success_params_debug_redacts_secrets() {
+        let params = parse(
+            r#"{"access_token":"s3cr3t-a","token_type":"Bearer","refresh_token":"s3cr3t-r"}"#,
+        );
+
+        let debug = format!("{params:?}");
+        assert!(debug.contains("Bearer"), "{debug}");
+        assert!(!debug.contains("s3cr3t"), "{debug}");
+    }
+
+    #[test]
+    fn strict_parsing_rejects_unknown_fields() {
+        let body = br#"{"access_token":"a","token_type":"Bearer","expires_in":"60"}"#;
+        let params = Oauth20AccessTokenSuccessParams::try_from_strict(body).unwrap();
+        assert_eq!(params.expires_in, Some(60));
+
+        let body = br#"{
+            "access_token":"a",
+            "token_type":"N_A",
+            "issued_token_type":"urn:ietf:params:oauth:token-type:access_token",
+            "expires_at":"2025-01-01T00:00:00Z"
+        }"#;
+        let params = Oauth20AccessTokenSuccessParams::try_from_strict(body).unwrap();
+        assert!(params.issued_token_type.is_some());
+        assert!(params.expires_at.is_some());
+
+        let body = br#"{"access_token":"a","token_type":"Bearer","id_token":"x"}"#;
+        assert!(Oauth20AccessTokenSuccessParams::try_from(body.as_slice()).is_ok());
+        let err = Oauth20AccessTokenSuccessParams::try_from_strict(body).unwrap_err();
+        assert!(err.to_string().contains("`id_token`"), "{err}");
+    }
+
+    #[test]
+    fn unauthorized_client_carries_the_challenged_method() {
+        let body = br#"{"error":"invalid_client"}"#;
+        let mut response = response("identity", body);
+        response.status = HttpStatusCode(401);
+        response
+            .headers
+            .push(("WWW-Authenticate".into(), r#"Basic realm="token""#.into()));
+
+        let err = parse_access_token_response(&response).unwrap().unwrap_err();
+        assert_eq!(err.error, Oauth20AccessTokenErrorCode::InvalidClient);
+        assert_eq!(
+            err.client_auth_challenge,
+            Some(Oauth20ClientAuthMethod::ClientSecretBasic)
+        );
+    }
+
+    #[test]
+    fn error_params_are_classified() {
+        let params = |json: &str| Oauth20AccessTokenErrorParams::try_from(json.as_bytes());
+
+        let mut err = params(r#"{"error":"invalid_grant"}"#).unwrap();
+        assert!(err.requires_reauthorization());
+        assert!(!err.is_retryable());
+
+        err.retry_after = Some(Duration::from_secs(1));
+        assert!(err.is_retryable());
+
+        let err = params(r#"{"error":"slow_down"}"#).unwrap();
+        assert!(err.is_retryable());
+        assert!(!err.requires_reauthorization());
+
+        let err = params(r#"{"error":"invalid_client"}"#).unwrap();
+        assert!(!err.is_retryable());
+        assert!(!err.requires_reauthorization());
+
+        for (status, json) in [
+            (500, r#"{"error":"server_error"}"#),
+            (503, r#"{"error":"temporarily_unavailable"}"#),
+        ] {
+            let mut response = response("identity", json.as_bytes());
+            response.status = HttpStatusCode(status);
+
+            let Ok(Err(err)) = parse_access_token_response(&response) else {
+                panic!("expected error params");
+            };
+            assert_eq!(err.retry_after, None);
+            assert!(err.is_retryable(), "{status}");
+            assert!(!err.requires_reauthorization());
+        }
+    }
+
+    #[test]
+    fn effective_scopes_fall_back_to_requested() {
+        let requested = ["email", "openid"];
+
+        let params = parse(r#"{"access_token":"a","token_type":"Bearer"}"#);
+        assert_eq!(
+            params.effective_scopes(requested),
+            BTreeSet::from(["email".into(), "openid".into()])
+        );
+
+        let params = parse(r#"{"access_token":"a","token_type":"Bearer","scope":"openid"}"#);
+        assert_eq!(
+            params.effective_scopes(requested),
+            BTreeSet::from(["openid".into()])
+        );
+
+        let params = parse(r#"{"access_token":"a","token_type":"Bearer","scope":""}"#);
+        assert!(params.effective_scopes(requested).is_empty());
+    }
+
+    #[test]
+    fn denied_scopes_tell_omitted_from_narrowed() {
+        let requested = ["email", "openid", "calendar"];
+
+        let params = parse(r#"{"access_token":"a","token_type":"Bearer"}"#);
+        assert_eq!(params.denied_scopes(requested), None);
+
+        let params = parse(r#"{"access_token":"a","token_type":"Bearer","scope":"openid,email"}"#);
+        assert_eq!(
+            params.denied_scopes(requested),
+            Some(BTreeSet::from(["calendar".into()]))
+        );
+
+        let params = parse(r#"{"access_token":"a","token_type":"Bearer","scope":""}"#);
+        assert_eq!(params.denied_scopes(requested).map(|d| d.len()), Some(3));
+
+        let params = parse(r#"{"access_token":"a","token_type":"Bearer","scope":"openid"}"#);
+        assert_eq!(params.denied_scopes(["openid"]), Some(BTreeSet::new()));
+    }
+
+    #[test]
+    fn success_params_round_trip_through_string() {
+        let params = parse(r#"{"access_token":"a","token_type":"Bearer","refresh_token":"r"}"#);
+        let json = String::try_from(&params).unwrap();
+
+        let params = Oauth20AccessTokenSuccessParams::try_from(json.as_str()).unwrap();
+        assert_eq!(params.access_token.expose_secret(), "a");
+        assert_eq!(
+            params.refresh_token.as_ref().map(|t| t.expose_secret()),
+            Some("r")
+        );
+    }
+
+    #[test]
+    fn error_params_serialize_into_the_error_response() {
+        let json = br#"{"error":"invalid_grant","error_description":"code expired","error_uri":"https://a.com/e"}"#;
+        let mut params = Oauth20AccessTokenErrorParams::try_from(json.as_slice()).unwrap();
+        params.retry_after = Some(Duration::from_secs(5));
+        assert_eq!(serde_json::to_vec(&params).unwrap(), json);
+
+        let params =
+            Oauth20AccessTokenErrorParams::try_from(br#"{"error":"vendor_quirk"}"#.as_slice())
+                .unwrap();
+        assert_eq!(
+            serde_json::to_string(&params).unwrap(),
+            r#"{"error":"unknown"}"#
+        );
+    }
+
+    #[test]
+    fn error_params_display_code_and_description() {
+        let params = Oauth20AccessTokenErrorParams::try_from(
+            br#"{"error":"invalid_grant","error_description":"code expired"}"#.as_slice(),
+        )
+        .unwrap();
+        assert_eq!(params.to_string(), "invalid_grant: code expired");
+        assert_eq!(
+            format!("token request failed: {params}"),
+            "token request failed: invalid_grant: code expired"
+        );
+
+        let bare =
+            Oauth20AccessTokenErrorParams::try_from(br#"{"error":"invalid_client"}"#.as_slice())
+                .unwrap();
+        assert_eq!(format!("{bare}"), "invalid_client");
+
+        fn token_type(
+            response: Oauth20AccessTokenResponse,
+        ) -> Result<String, Box<dyn core::error::Error>> {
+            Ok(response?.token_type)
+        }
+
+        let err = token_type(Err(params)).unwrap_err();
+        assert_eq!(err.to_string(), "invalid_grant: code expired");
+    }
+
+    #[test]
+    fn refresh_only_when_needed_and_possible() {
+        let request = || HttpRequest {
+            method: "POST".into(),
+            url: url::Url::parse("https://example.com/token").unwrap(),
+            headers: Vec::new(),
+            body: Vec::new(),
+        };
+        let leeway = Duration::from_secs(60);
+
+        let mut params = parse(
+            r#"{"access_token":"a","token_type":"Bearer","expires_in":3600,"refresh_token":"r"}"#,
+        );
+        params.issued_at = Some(1000);
+
+        assert!(!params.needs_refresh(leeway, 4539));
+        assert!(params.needs_refresh(leeway, 4540));
+        assert!(
+            params
+                .refresh_if_needed(request(), "client-id", leeway, 4539)
+                .is_none()
+        );
+        assert!(
+            params
+                .refresh_if_needed(request(), "client-id", leeway, 4540)
+                .is_some()
+        );
+
+        params.refresh_token = None;
+        assert!(
+            params
+                .refresh_if_needed(request(), "client-id", leeway, 4540)
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn refresh_policy_fraction_of_the_lifetime() {
+        let mut params = parse(r#"{"access_token":"a","token_type":"Bearer","expires_in":3600}"#);
+        params.issued_at = Some(0);
+
+        let policy = Oauth20RefreshPolicy::Fraction(0.9);
+        assert_eq!(params.refresh_at(policy), Some(3240));
+        assert!(!params.needs_refresh(policy, 3239));
+        assert!(params.needs_refresh(policy, 3240));
+
+        assert_eq!(
+            params.refresh_at(Oauth20RefreshPolicy::Fraction(2.0)),
+            Some(3600)
+        );
+        assert_eq!(
+            params.refresh_at(Oauth20RefreshPolicy::Fraction(-1.0)),
+            Some(0)
+        );
+
+        let policy = Oauth20RefreshPolicy::Never;
+        assert!(!params.needs_refresh(policy, 3599));
+        assert!(params.needs_refresh(policy, 3600));
+    }
+
+    #[test]
+    fn refresh_jitter_stays_within_range_and_lifetime() {
+        let mut params = parse(r#"{"access_token":"a","token_type":"Bearer","expires_in":3600}"#);
+        params.issued_at = Some(0);
+
+        let policy = Oauth20RefreshPolicy::Fraction(0.9);
+        let jitter = Duration::from_secs(60);
+        assert_eq!(params.refresh_at_jittered(policy, jitter, 0), Some(3180));
+        assert_eq!(params.refresh_at_jittered(policy, jitter, 60), Some(3240));
+        assert_eq!(params.refresh_at_jittered(policy, jitter, 120), Some(3300));
+        assert_eq!(params.refresh_at_jittered(policy, jitter, 121), Some(3180));
+
+        for seed in 0..1000 {
+            let at = params.refresh_at_jittered(policy, jitter, seed).unwrap();
+            assert!((3180..=3300).contains(&at));
+        }
+
+        let policy = Oauth20RefreshPolicy::Never;
+        assert_eq!(params.refresh_at_jittered(policy, jitter, 120), Some(3600));
+        assert_eq!(
+            params.refresh_at_jittered(policy, Duration::ZERO, u64::MAX),
+            Some(3600)
+        );
+
+        params.issued_at = None;
+        assert_eq!(params.refresh_at_jittered(policy, jitter, 0), None);
+    }
+
+    #[test]
+    fn error_codes_match_wire_values_exactly() {
+        use Oauth20AccessTokenErrorCode::*;
+
+        let code = |value: &str| -> Oauth20AccessTokenErrorCode {
+            serde_json::from_value(serde_json::Value::from(value)).unwrap()
+        };
+
+        let codes = [
+            ("invalid_client", InvalidClient),
+            ("invalid_grant", InvalidGrant),
+            ("invalid_request", InvalidRequest),
+            ("invalid_scope", InvalidScope),
+            ("unauthorized_client", UnauthorizedClient),
+            ("unsupported_grant_type", UnsupportedGrantType),
+            ("authorization_pending", AuthorizationPending),
+            ("slow_down", SlowDown),
+            ("access_denied", AccessDenied),
+            ("expired_token", ExpiredToken),
+            ("authorization_declined", AuthorizationDeclined),
+            ("bad_verification_code", BadVerificationCode),
+            ("invalid_target", InvalidTarget),
+            ("server_error", ServerError),
+            ("temporarily_unavailable", TemporarilyUnavailable),
+        ];
+
+        for (value, expected) in codes {
+            assert_eq!(code(value), expected);
+            assert_eq!(expected.as_str(), value);
+        }
+
+        assert_eq!(code("Invalid_Grant"), Unknown);
+        assert_eq!(code("INVALID_GRANT"), Unknown);
+        assert_eq!(code("interaction_required"), Unknown);
+        assert_eq!(Unknown.as_str(), "unknown");
+    }
+
+    #[test]
+    fn expires_in_counts_from_issued_at() {
+        let mut params = parse(r#"{"access_token":"a","token_type":"Bearer","expires_in":"3600"}"#);
+        assert_eq!(params.expires_in, Some(3600));
+        assert!(!params.is_expired(u64::MAX));
+
+        assert_eq!(params.refresh_at(Duration::ZERO), None);
+
+        params.issued_at = Some(1000);
+        assert!(!params.is_expired(4599));
+        assert!(params.is_expired(4600));
+        assert_eq!(params.refresh_at(Duration::from_secs(60)), Some(4540));
+        assert_eq!(params.refresh_at(Duration::from_secs(9999)), Some(0));
+
+        let params = parse(r#"{"access_token":"a","token_type":"Bearer"}"#);
+        assert_eq!(params.expires_in, None);
+        assert!(!params.is_expired(u64::MAX));
+        assert_eq!(params.refresh_at(Duration::ZERO), None);
+
+        let err = Oauth20AccessTokenSuccessParams::try_from(
+            br#"{"access_token":"a","token_type":"Bearer","expires_in":"soon"}"#.as_slice(),
+        );
+        assert!(err.is_err());
+    }
+
+    #[test]
+    fn with_request_id_replaces_the_header() {
+        let request = HttpRequest {
+            method: "POST".into(),
+            url: "https://example.com/token".parse().unwrap(),
+            headers: vec![("x-request-id".into(), "old".into())],
+            body: Vec::new(),
+        };
+
+        let request = with_request_id(request, 42);
+        assert_eq!(find_request_id(&request).as_deref(), Some("42"));
+        assert_eq!(request.headers.len(), 1);
+    }
+
+    #[test]
+    fn request_error_display_ends_with_the_request_id() {
+        let err = Oauth20RequestError::<&str>::new(Some("42".into()), "boom");
+        assert_eq!(err.to_string(), "boom (request 42)");
+
+        let err = Oauth20RequestError::<&str>::new(None, "boom");
+        assert_eq!(err.to_string(), "boom");
+    }
+
+    #[test]
+    fn cancelled_coroutine_stops_yielding() {
+        let mut coroutine = Oauth20TokenCoroutine::new("test", token_request());
+        assert!(matches!(
+            resume(&mut coroutine, None),
+            Oauth20TokenCoroutineResult::WantsWrite(_)
+        ));
+
+        coroutine.cancel();
+
+        for _ in 0..2 {
+            assert!(matches!(
+                resume(&mut coroutine, Some(b"HTTP/1.1 200 OK\r\n")),
+                Oauth20TokenCoroutineResult::Err(Oauth20RequestError {
+                    error: Oauth20AccessTokenRequestError::Cancelled,
+                    ..
+                })
+            ));
+        }
+    }
+
+    #[test]
+    fn failed_coroutine_reports_its_request_id() {
+        let coroutine = Oauth20TokenCoroutine::new("test", token_request());
+        assert_eq!(coroutine.request_id(), None);
+
+        let request = with_request_id(token_request(), "req-1");
+        let mut coroutine = Oauth20TokenCoroutine::new("test", request);
+        assert_eq!(coroutine.request_id(), Some("req-1"));
+
+        coroutine.cancel();
+
+        let Oauth20TokenCoroutineResult::Err(err) = resume(&mut coroutine, None) else {
+            panic!("expected the cancelled error");
+        };
+        assert_eq!(err.request_id.as_deref(), Some("req-1"));
+        assert!(matches!(
+            err.error,
+            Oauth20AccessTokenRequestError::Cancelled
+        ));
+        assert!(err.to_string().ends_with(" (request req-1)"));
+    }
+
+    #[test]
+    fn keep_alive_follows_the_response() {
+        for (connection, keep_alive) in [("keep-alive", true), ("close", false)] {
+            let response = token_response(connection);
+
+            let mut coroutine = Oauth20TokenCoroutine::new("test", token_request());
+            assert!(!coroutine.keep_alive());
+
+            let mut arg: Option<&[u8]> = None;
+            loop {
+                match resume(&mut coroutine, arg.take()) {
+                    Oauth20TokenCoroutineResult::Ok(res) => break assert!(res.is_ok()),
+                    Oauth20TokenCoroutineResult::WantsRead => arg = Some(response.as_bytes()),
+                    Oauth20TokenCoroutineResult::WantsWrite(_) => (),
+                    Oauth20TokenCoroutineResult::Err(err) => panic!("{err}"),
+                }
+            }
+
+            assert_eq!(coroutine.keep_alive(), keep_alive);
+        }
+    }
+
+    #[test]
+    fn state_follows_the_yielded_io() {
+        let response = token_response("close");
+
+        let mut coroutine = Oauth20TokenCoroutine::new("test", token_request());
+        assert_eq!(coroutine.state(), Oauth20CoroutineState::Ready);
+
+        resume(&mut coroutine, None);
+        assert_eq!(coroutine.state(), Oauth20CoroutineState::Writing);
+
+        resume(&mut coroutine, None);
+        assert_eq!(coroutine.state(), Oauth20CoroutineState::Reading);
+
+        resume(&mut coroutine, Some(response.as_bytes()));
+        assert_eq!(coroutine.state(), Oauth20CoroutineState::Done);
+    }
+
+    #[test]
+    fn failed_coroutine_gives_its_request_back() {
+        let unretained = Oauth20TokenCoroutine::new("test", token_request());
+        assert!(unretained.into_request().is_none());
+
+        let mut coroutine = Oauth20TokenCoroutine::retained("test", token_request());
+        let Oauth20TokenCoroutineResult::WantsWrite(sent) = resume(&mut coroutine, None) else {
+            panic!("expected WantsWrite");
+        };
+        assert!(matches!(
+            resume(&mut coroutine, None),
+            Oauth20TokenCoroutineResult::WantsRead
+        ));
+        assert!(matches!(
+            resume(&mut coroutine, Some(b"garbage\r\n\r\n")),
+            Oauth20TokenCoroutineResult::Err(_)
+        ));
+
+        let request = coroutine.into_request().unwrap();
+        let mut retry = Oauth20TokenCoroutine::new("test", request);
+        let Oauth20TokenCoroutineResult::WantsWrite(resent) = resume(&mut retry, None) else {
+            panic!("expected WantsWrite");
+        };
+        assert_eq!(resent, sent);
+    }
+}
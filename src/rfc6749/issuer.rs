@@ -0,0 +1,114 @@
+//! Authorization server issuer identifier (RFC 8414 section 2).
+//!
+//! The issuer names an authorization server, and the mix-up defense
+//! of RFC 9207 compares the `iss` of the authorization response
+//! against it. The comparison must be exact: no case folding, no
+//! trailing slash added or dropped, since `https://as.example` and
+//! `https://as.example/` are distinct issuers. [`Oauth20Issuer`]
+//! therefore keeps the identifier verbatim and only validates its
+//! shape.
+
+use core::{fmt, str::FromStr};
+
+use alloc::string::{String, ToString};
+
+use thiserror::Error;
+use url::Url;
+
+/// Errors that can occur while parsing an issuer identifier.
+#[derive(Debug, Error)]
+pub enum Oauth20IssuerError {
+    /// The issuer is not a valid URL.
+    #[error("Parse OAuth 2.0 issuer URL: {0}")]
+    ParseUrl(#[from] url::ParseError),
+    /// The issuer does not use the `https` scheme.
+    #[error("OAuth 2.0 issuer `{0}` must use https")]
+    InsecureIssuer(String),
+    /// The issuer carries a query or a fragment.
+    #[error("OAuth 2.0 issuer `{0}` must not have a query or a fragment")]
+    QueryOrFragment(String),
+}
+
+/// An authorization server issuer identifier, compared exactly.
+///
+/// Refs: <https://datatracker.ietf.org/doc/html/rfc8414#section-2>,
+///       <https://datatracker.ietf.org/doc/html/rfc9207#section-2.4>
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct Oauth20Issuer(String);
+
+impl Oauth20Issuer {
+    /// Parses an issuer identifier: an `https` URL without query nor
+    /// fragment, kept verbatim.
+    pub fn parse(issuer: &str) -> Result<Self, Oauth20IssuerError> {
+        let url = Url::parse(issuer)?;
+
+        if url.scheme() != "https" {
+            return Err(Oauth20IssuerError::InsecureIssuer(issuer.to_string()));
+        }
+
+        if url.query().is_some() || url.fragment().is_some() {
+            return Err(Oauth20IssuerError::QueryOrFragment(issuer.to_string()));
+        }
+
+        Ok(Self(issuer.to_string()))
+    }
+
+    /// Returns the issuer identifier, as given.
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl PartialEq<str> for Oauth20Issuer {
+    fn eq(&self, other: &str) -> bool {
+        self.0 == other
+    }
+}
+
+impl FromStr for Oauth20Issuer {
+    type Err = Oauth20IssuerError;
+
+    fn from_str(issuer: &str) -> Result<Self, Self::Err> {
+        Self::parse(issuer)
+    }
+}
+
+impl AsRef<str> for Oauth20Issuer {
+    fn as_ref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl fmt::Display for Oauth20Issuer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::rfc6749::issuer::*;
+
+    #[test]
+    fn issuer_is_validated_and_compared_exactly() {
+        let issuer = Oauth20Issuer::parse("https://as.example").unwrap();
+        assert_eq!(issuer.as_str(), "https://as.example");
+        assert!(issuer == *"https://as.example");
+        assert!(issuer != *"https://as.example/");
+        assert_ne!(issuer, Oauth20Issuer::parse("https://as.example/").unwrap());
+        assert_ne!(issuer, Oauth20Issuer::parse("HTTPS://as.example").unwrap());
+
+        assert!(matches!(
+            Oauth20Issuer::parse("http://as.example"),
+            Err(Oauth20IssuerError::InsecureIssuer(_))
+        ));
+        assert!(matches!(
+            Oauth20Issuer::parse("https://as.example?tenant=x"),
+            Err(Oauth20IssuerError::QueryOrFragment(_))
+        ));
+        assert!(matches!(
+            Oauth20Issuer::parse("https://as.example#x"),
+            Err(Oauth20IssuerError::QueryOrFragment(_))
+        ));
+    }
+}
@@ -1,12 +1,19 @@
 //! OAuth 2.0 Authorization Framework (RFC 6749).
 //!
-//! The authorization code grant, the client credentials grant, and
-//! the token issuance and refresh exchanges shared by every grant.
+//! The authorization code grant, the client credentials grant, the
+//! token issuance and refresh exchanges shared by every grant, and
+//! the endpoints they talk to.
 
 pub mod access_token_request;
+pub mod auth_code_grant;
 pub mod auth_request;
 pub mod auth_response;
+pub mod client_auth;
 pub mod client_credentials;
+pub mod endpoint;
 pub mod issue_access_token;
+pub mod issuer;
+pub mod redirect_uri;
 pub mod refresh_access_token;
+pub mod scope;
 pub mod state;
@@ -0,0 +1,172 @@
+//! Redirection URI (RFC 6749 section 3.1.2).
+//!
+//! The authorization code grant sends the redirection URI twice: on
+//! the authorization request, then on the access token request, where
+//! the server requires it to be identical to the first one (section
+//! 4.1.3). Servers compare the two by plain string comparison, so
+//! `https://Client.example.com` then `https://client.example.com/`
+//! end in `invalid_grant`. Holding the URI once in an
+//! [`Oauth20RedirectUri`] and handing the same value to both requests
+//! guarantees the bytes match.
+
+use core::{fmt, str::FromStr};
+
+use alloc::string::String;
+
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+/// A validated redirection URI.
+///
+/// Sent exactly as given: the WHATWG URL parser only validates it. Its
+/// normalization (lowercase scheme and host, default port dropped, `/`
+/// for an empty path) would change the bytes, and a server comparing
+/// them with the registered URI would reject `http://localhost:8080`
+/// sent as `http://localhost:8080/`.
+///
+/// Deserialized from a URI string through the same validation.
+///
+/// Refs: <https://datatracker.ietf.org/doc/html/rfc6749#section-3.1.2>
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Oauth20RedirectUri {
+    uri: String,
+    url: Url,
+}
+
+impl Oauth20RedirectUri {
+    /// Validates a redirection URI, keeping it as given.
+    pub fn parse(uri: &str) -> Result<Self, url::ParseError> {
+        let url = Url::parse(uri)?;
+        let uri = uri.into();
+        Ok(Self { uri, url })
+    }
+
+    /// Returns the URI as given, as sent on both requests.
+    pub fn as_str(&self) -> &str {
+        &self.uri
+    }
+
+    /// Returns the URI as parsed, normalized by the URL parser.
+    ///
+    /// Meant for comparing the URI with a parsed URL, like the
+    /// redirection the server sends back.
+    pub fn as_url(&self) -> &Url {
+        &self.url
+    }
+}
+
+impl From<Url> for Oauth20RedirectUri {
+    fn from(url: Url) -> Self {
+        let uri = url.as_str().into();
+        Self { uri, url }
+    }
+}
+
+impl FromStr for Oauth20RedirectUri {
+    type Err = url::ParseError;
+
+    fn from_str(uri: &str) -> Result<Self, Self::Err> {
+        Self::parse(uri)
+    }
+}
+
+impl TryFrom<String> for Oauth20RedirectUri {
+    type Error = url::ParseError;
+
+    fn try_from(uri: String) -> Result<Self, Self::Error> {
+        let url = Url::parse(&uri)?;
+        Ok(Self { uri, url })
+    }
+}
+
+impl From<Oauth20RedirectUri> for String {
+    fn from(uri: Oauth20RedirectUri) -> Self {
+        uri.uri
+    }
+}
+
+impl AsRef<str> for Oauth20RedirectUri {
+    fn as_ref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl fmt::Display for Oauth20RedirectUri {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.uri)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::{borrow::Cow, collections::BTreeMap, string::ToString};
+
+    use url::Url;
+
+    use crate::rfc6749::{
+        access_token_request::Oauth20AccessTokenRequestParams,
+        auth_request::Oauth20AuthRequestParams, redirect_uri::*,
+    };
+
+    #[test]
+    #[cfg_attr(feature = "oauth21", allow(deprecated))]
+    fn redirect_uri_is_identical_on_both_requests() {
+        let uri = Oauth20RedirectUri::parse("https://client.example.com/cb?x=a").unwrap();
+
+        let auth_url = Oauth20AuthRequestParams {
+            client_id: "client-id".into(),
+            redirect_uri: Some(uri.clone()),
+            scope: Default::default(),
+            scope_delimiter: Default::default(),
+            state: None,
+            pkce_code_challenge: None,
+            extras: BTreeMap::new(),
+        }
+        .build_url(&Url::parse("https://example.com/authorize").unwrap());
+
+        let body = Oauth20AccessTokenRequestParams {
+            code: "code".into(),
+            redirect_uri: Some(uri.clone()),
+            client_id: "client-id".into(),
+            client_secret: None,
+            pkce_code_verifier: None,
+        }
+        .to_string();
+
+        let sent_on_auth = auth_url
+            .query_pairs()
+            .find(|(k, _)| k == "redirect_uri")
+            .map(|(_, v)| v);
+
+        let sent_on_token = url::form_urlencoded::parse(body.as_bytes())
+            .find(|(k, _)| k == "redirect_uri")
+            .map(|(_, v)| v);
+
+        assert_eq!(sent_on_auth, Some(Cow::Borrowed(uri.as_str())));
+        assert_eq!(sent_on_auth, sent_on_token);
+    }
+
+    #[test]
+    fn redirect_uri_is_sent_as_given() {
+        let uri = Oauth20RedirectUri::parse("http://localhost:8080").unwrap();
+        assert_eq!(uri.as_str(), "http://localhost:8080");
+        assert_eq!(uri.as_url().as_str(), "http://localhost:8080/");
+
+        let body = Oauth20AccessTokenRequestParams {
+            code: "code".into(),
+            redirect_uri: Some(uri),
+            client_id: "client-id".into(),
+            client_secret: None,
+            pkce_code_verifier: None,
+        }
+        .to_string();
+
+        let sent = url::form_urlencoded::parse(body.as_bytes())
+            .find(|(k, _)| k == "redirect_uri")
+            .map(|(_, v)| v);
+        assert_eq!(sent.as_deref(), Some("http://localhost:8080"));
+
+        assert!(Oauth20RedirectUri::parse("/cb").is_err());
+    }
+}
@@ -11,18 +11,11 @@
 //!     net::TcpStream,
 //! };
 //!
-//! use io_http::rfc9110::request::HttpRequest;
-//! use io_oauth::rfc6749::refresh_access_token::*;
+//! use io_oauth::rfc6749::{endpoint::post_request, refresh_access_token::*};
 //! use url::Url;
 //!
 //! let token_url = Url::parse("https://example.com/token").unwrap();
-//! let request = HttpRequest {
-//!     method: "POST".into(),
-//!     url: token_url.clone(),
-//!     headers: Vec::new(),
-//!     body: Vec::new(),
-//! }
-//! .header("Host", token_url.host_str().unwrap());
+//! let request = post_request(&token_url);
 //!
 //! let params = Oauth20AccessTokenRefreshParams::new("client-id", "the-refresh-token");
 //!
@@ -56,22 +49,19 @@ use alloc::{
     vec::Vec,
 };
 
-use io_http::{
-    coroutine::*,
-    rfc9110::{
-        request::HttpRequest,
-        send::{HttpSendOutput, HttpSendYield},
-    },
-    rfc9112::send::{Http11Send, Http11SendError},
-};
-use log::{debug, trace};
+use io_http::{rfc9110::request::HttpRequest, rfc9112::send::Http11SendError};
 use secrecy::{ExposeSecret, SecretString};
 use thiserror::Error;
 use url::{Url, form_urlencoded::Serializer};
 
-use crate::rfc6749::issue_access_token::{
-    Oauth20AccessTokenErrorParams, Oauth20AccessTokenResponse, Oauth20AccessTokenSuccessParams,
-    parse_http_date,
+use crate::rfc6749::{
+    issue_access_token::{
+        Oauth20AccessTokenErrorParams, Oauth20AccessTokenResponse, Oauth20AccessTokenResponseError,
+        Oauth20AccessTokenSuccessParams, Oauth20CoroutineState, Oauth20RequestError,
+        Oauth20TokenCoroutine, Oauth20TokenCoroutineError, Oauth20TokenCoroutineResult,
+        accept_json,
+    },
+    scope::{Oauth20ScopeDelimiter, collect_scope, into_owned_scope, join_scope},
 };
 
 /// Errors that can occur during the coroutine progression.
@@ -82,7 +72,7 @@ pub enum Oauth20AccessTokenRefreshError {
     SendHttpRefresh(#[from] Http11SendError),
     /// The HTTP response could not be parsed.
     #[error(transparent)]
-    ParseHttpResponse(#[from] serde_json::Error),
+    ParseHttpResponse(#[from] Oauth20AccessTokenResponseError),
     /// The server answered with an unexpected redirection.
     #[error("Unexpected redirection {code} to {url}")]
     Redirect {
@@ -91,6 +81,9 @@ pub enum Oauth20AccessTokenRefreshError {
         /// The redirection HTTP status code.
         code: u16,
     },
+    /// The coroutine was cancelled by the caller.
+    #[error("OAuth 2.0 access token refresh cancelled")]
+    Cancelled,
 }
 
 /// Result returned by the coroutine's resume function.
@@ -102,77 +95,161 @@ pub enum Oauth20AccessTokenRefreshResult {
     WantsRead,
     /// The coroutine wants the given bytes to be written to the socket.
     WantsWrite(Vec<u8>),
-    /// The coroutine encountered an error.
-    Err(Oauth20AccessTokenRefreshError),
+    /// The coroutine encountered an error, along the identifier of
+    /// the request.
+    Err(Oauth20RequestError<Oauth20AccessTokenRefreshError>),
+}
+
+/// A refresh response, classified by what the caller does next.
+///
+/// Built from the response the coroutine returns, see
+/// [`Oauth20AccessTokenErrorParams::requires_reauthorization`] and
+/// [`Oauth20AccessTokenErrorParams::is_retryable`]. A coroutine error
+/// (I/O, unparsable response) is left to the caller: the server never
+/// judged the refresh token then.
+#[derive(Debug)]
+pub enum Oauth20RefreshOutcome {
+    /// The token was refreshed.
+    Refreshed(Oauth20AccessTokenSuccessParams),
+    /// The refresh token is dead (expired, revoked, already rotated):
+    /// send the end user back to the authorization.
+    ReauthorizationRequired(Oauth20AccessTokenErrorParams),
+    /// The server is overloaded or asked to wait: retry the same
+    /// refresh later, after `retry_after` when set.
+    Transient(Oauth20AccessTokenErrorParams),
+    /// Any other error, a client misconfiguration (`invalid_client`,
+    /// `invalid_scope`) that neither retrying nor reauthorizing fixes.
+    Failed(Oauth20AccessTokenErrorParams),
+}
+
+impl From<Oauth20AccessTokenResponse> for Oauth20RefreshOutcome {
+    fn from(response: Oauth20AccessTokenResponse) -> Self {
+        match response {
+            Ok(params) => Self::Refreshed(params),
+            Err(err) if err.requires_reauthorization() => Self::ReauthorizationRequired(err),
+            Err(err) if err.is_retryable() => Self::Transient(err),
+            Err(err) => Self::Failed(err),
+        }
+    }
 }
 
 /// The I/O-free coroutine to refresh an access token.
 ///
 /// Refs: <https://datatracker.ietf.org/doc/html/rfc6749#section-6>
 pub struct Oauth20AccessTokenRefresh {
-    send: Http11Send,
+    inner: Oauth20TokenCoroutine,
 }
 
 impl Oauth20AccessTokenRefresh {
     /// Creates a new I/O-free coroutine to refresh an access token.
     pub fn new(request: HttpRequest, body: Oauth20AccessTokenRefreshParams<'_>) -> Self {
-        debug!("prepare access token refresh request");
-        trace!("url: {}", request.url);
+        Self::from_request(Self::dry_run(request, body))
+    }
 
-        let request = request
-            .header("Content-Type", "application/x-www-form-urlencoded")
-            .body(body.to_string().into_bytes());
+    /// Creates the coroutine sending `request` as-is.
+    ///
+    /// Meant for a request built by [`Self::dry_run`] then adjusted, for
+    /// instance by [`Oauth20ClientAuthMethod::authenticate`].
+    ///
+    /// [`Oauth20ClientAuthMethod::authenticate`]: crate::rfc6749::client_auth::Oauth20ClientAuthMethod::authenticate
+    pub fn from_request(request: HttpRequest) -> Self {
+        Self {
+            inner: Oauth20TokenCoroutine::new("access token refresh", request),
+        }
+    }
 
+    /// Creates the coroutine sending `request` as-is, like
+    /// [`Self::from_request`], keeping a copy of it for
+    /// [`Self::into_request`].
+    ///
+    /// The copy holds the body a second time, secrets included, for the
+    /// whole life of the coroutine; hence opt-in.
+    // SAFETY: the retained body keeps the params' secrets in memory
+    pub fn from_request_retained(request: HttpRequest) -> Self {
         Self {
-            send: Http11Send::new(request),
+            inner: Oauth20TokenCoroutine::retained("access token refresh", request),
         }
     }
 
+    /// Returns the HTTP request the coroutine would send, without sending it.
+    ///
+    /// Meant for inspecting or replaying a request (with `curl`, say) when a
+    /// provider misbehaves. The `Debug` output of the request redacts its
+    /// body, but the body itself carries the form params in clear.
+    ///
+    /// Asks for a JSON response with `Accept: application/json`, unless
+    /// `request` already carries an `Accept` header.
+    // SAFETY: the returned body exposes the params' secrets
+    pub fn dry_run(request: HttpRequest, body: Oauth20AccessTokenRefreshParams<'_>) -> HttpRequest {
+        accept_json(request)
+            .header("Content-Type", "application/x-www-form-urlencoded")
+            .body(body.to_serializer().finish().into_bytes())
+    }
+
+    /// Returns the identifier of the request, when the caller set one.
+    ///
+    /// Read from the [`OAUTH20_REQUEST_ID_HEADER`] of the request, it tags
+    /// the errors returned by [`Self::resume`].
+    ///
+    /// [`OAUTH20_REQUEST_ID_HEADER`]: crate::rfc6749::issue_access_token::OAUTH20_REQUEST_ID_HEADER
+    pub fn request_id(&self) -> Option<&str> {
+        self.inner.request_id()
+    }
+
+    /// Returns whether the server kept the connection alive for another
+    /// token request, `false` until [`Self::resume`] returned the response.
+    pub fn keep_alive(&self) -> bool {
+        self.inner.keep_alive()
+    }
+
+    /// Returns the progress of the coroutine, for diagnostics.
+    pub fn state(&self) -> Oauth20CoroutineState {
+        self.inner.state()
+    }
+
+    /// Returns the request the coroutine was created with, to retry
+    /// through a fresh [`Self::from_request`] once it failed.
+    ///
+    /// `None` unless the coroutine was created by
+    /// [`Self::from_request_retained`], the only one keeping it.
+    // SAFETY: the returned body exposes the params' secrets
+    pub fn into_request(self) -> Option<HttpRequest> {
+        self.inner.into_request()
+    }
+
+    /// Cancels the coroutine.
+    ///
+    /// Every later [`Self::resume`] returns a `Cancelled` error without
+    /// yielding any more I/O. The request may have been partially written, or
+    /// its response partially read: drop the stream rather than reusing it
+    /// for another request.
+    pub fn cancel(&mut self) {
+        self.inner.cancel()
+    }
+
     /// Makes the coroutine progress.
     pub fn resume(&mut self, arg: Option<&[u8]>) -> Oauth20AccessTokenRefreshResult {
-        match self.send.resume(arg) {
-            HttpCoroutineState::Complete(Ok(HttpSendOutput { response, .. }))
-                if response.status.is_success() =>
-            {
-                debug!("received access token refresh response");
-                trace!("status: {}", *response.status);
-
-                match Oauth20AccessTokenSuccessParams::try_from(response.body.as_slice()) {
-                    Ok(mut res) => {
-                        res.issued_at = response.header("date").and_then(parse_http_date);
-                        Oauth20AccessTokenRefreshResult::Ok(Ok(res))
-                    }
-                    Err(err) => Oauth20AccessTokenRefreshResult::Err(err.into()),
-                }
-            }
-            HttpCoroutineState::Complete(Ok(HttpSendOutput { response, .. })) => {
-                debug!("received access token refresh error response");
-                trace!("status: {}", *response.status);
-
-                match Oauth20AccessTokenErrorParams::try_from(response.body.as_slice()) {
-                    Ok(res) => Oauth20AccessTokenRefreshResult::Ok(Err(res)),
-                    Err(err) => Oauth20AccessTokenRefreshResult::Err(err.into()),
-                }
-            }
-            HttpCoroutineState::Yielded(HttpSendYield::WantsRead) => {
-                Oauth20AccessTokenRefreshResult::WantsRead
-            }
-            HttpCoroutineState::Yielded(HttpSendYield::WantsWrite(bytes)) => {
+        match self.inner.resume(arg) {
+            Oauth20TokenCoroutineResult::Ok(res) => Oauth20AccessTokenRefreshResult::Ok(res),
+            Oauth20TokenCoroutineResult::WantsRead => Oauth20AccessTokenRefreshResult::WantsRead,
+            Oauth20TokenCoroutineResult::WantsWrite(bytes) => {
                 Oauth20AccessTokenRefreshResult::WantsWrite(bytes)
             }
-            HttpCoroutineState::Yielded(HttpSendYield::WantsRedirect { url, response, .. }) => {
-                Oauth20AccessTokenRefreshResult::Err(Oauth20AccessTokenRefreshError::Redirect {
-                    url,
-                    code: *response.status,
-                })
-            }
-            HttpCoroutineState::Complete(Err(err)) => {
-                Oauth20AccessTokenRefreshResult::Err(err.into())
-            }
+            Oauth20TokenCoroutineResult::Err(err) => Oauth20AccessTokenRefreshResult::Err(err),
         }
     }
 }
 
+impl Oauth20TokenCoroutineError for Oauth20AccessTokenRefreshError {
+    fn redirect(url: Url, code: u16) -> Self {
+        Self::Redirect { url, code }
+    }
+
+    fn cancelled() -> Self {
+        Self::Cancelled
+    }
+}
+
 /// The refresh access token request parameters.
 ///
 /// Refs: <https://datatracker.ietf.org/doc/html/rfc6749#section-6>
@@ -190,11 +267,47 @@ pub struct Oauth20AccessTokenRefreshParams<'a> {
     pub refresh_token: SecretString,
     /// The requested scope, narrowing the original one at most.
     ///
+    /// Serialized in lexicographic order, so the body is deterministic.
+    ///
     /// Refs: <https://datatracker.ietf.org/doc/html/rfc6749#section-3.3>
     pub scopes: BTreeSet<Cow<'a, str>>,
+    /// The separator between scope tokens, spaces unless the provider
+    /// expects commas.
+    pub scope_delimiter: Oauth20ScopeDelimiter,
+    /// The resource indicators the token is meant for, each sent as a
+    /// `resource` parameter (RFC 8707).
+    ///
+    /// Refs: <https://datatracker.ietf.org/doc/html/rfc8707#section-2.2>
+    pub resource: Vec<Cow<'a, str>>,
+    /// The API the token is meant for, a vendor parameter (Auth0) that
+    /// predates resource indicators.
+    pub audience: Option<Cow<'a, str>>,
 }
 
+/// The access token refresh params, owning every value.
+pub type Oauth20AccessTokenRefreshParamsOwned = Oauth20AccessTokenRefreshParams<'static>;
+
 impl<'a> Oauth20AccessTokenRefreshParams<'a> {
+    /// Converts every borrowed value into an owned one, so the params
+    /// outlive the strings they were built from.
+    pub fn into_owned(self) -> Oauth20AccessTokenRefreshParams<'static> {
+        Oauth20AccessTokenRefreshParams {
+            client_id: self.client_id,
+            client_secret: self.client_secret,
+            refresh_token: self.refresh_token,
+            scopes: into_owned_scope(self.scopes),
+            scope_delimiter: self.scope_delimiter,
+            resource: self
+                .resource
+                .into_iter()
+                .map(|resource| Cow::Owned(resource.into_owned()))
+                .collect(),
+            audience: self
+                .audience
+                .map(|audience| Cow::Owned(audience.into_owned())),
+        }
+    }
+
     /// Builds params from a client id and refresh token, no secret nor scope.
     pub fn new(client_id: impl ToString, refresh_token: impl Into<SecretString>) -> Self {
         Self {
@@ -202,9 +315,21 @@ impl<'a> Oauth20AccessTokenRefreshParams<'a> {
             client_secret: None,
             refresh_token: refresh_token.into(),
             scopes: BTreeSet::new(),
+            scope_delimiter: Oauth20ScopeDelimiter::Space,
+            resource: Vec::new(),
+            audience: None,
         }
     }
 
+    /// Requests the given scope tokens, replacing any previous ones.
+    pub fn with_scopes<S: Into<Cow<'a, str>>>(
+        mut self,
+        tokens: impl IntoIterator<Item = S>,
+    ) -> Self {
+        self.scopes = collect_scope(tokens);
+        self
+    }
+
     /// Serializes the params into the form-urlencoded request body.
     pub fn to_serializer(&self) -> Serializer<'a, String> {
         let mut serializer = Serializer::new(String::new());
@@ -218,19 +343,20 @@ impl<'a> Oauth20AccessTokenRefreshParams<'a> {
 
         serializer.append_pair("refresh_token", self.refresh_token.expose_secret());
 
-        if !self.scopes.is_empty() {
-            let mut scope = String::new();
-            let mut glue = "";
-
-            for token in &self.scopes {
-                scope.push_str(glue);
-                scope.push_str(token);
-                glue = " ";
-            }
+        let scope = join_scope(&self.scopes, self.scope_delimiter);
 
+        if !scope.is_empty() {
             serializer.append_pair("scope", &scope);
         }
 
+        for resource in &self.resource {
+            serializer.append_pair("resource", resource);
+        }
+
+        if let Some(audience) = &self.audience {
+            serializer.append_pair("audience", audience);
+        }
+
         serializer
     }
 }
@@ -240,3 +366,114 @@ impl fmt::Display for Oauth20AccessTokenRefreshParams<'_> {
         f.write_str(&self.to_serializer().finish())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use alloc::{format, string::ToString, vec};
+    use core::time::Duration;
+
+    use crate::rfc6749::{refresh_access_token::*, scope::Oauth20ScopeDelimiter};
+
+    #[test]
+    fn params_debug_redacts_secrets() {
+        let mut params = Oauth20AccessTokenRefreshParams::new("client-id", "s3cr3t-refresh");
+        params.client_secret = Some("s3cr3t-client".into());
+
+        let debug = format!("{params:?}");
+        assert!(debug.contains("client-id"), "{debug}");
+        assert!(!debug.contains("s3cr3t"), "{debug}");
+    }
+
+    #[test]
+    fn refresh_body_is_golden() {
+        let mut params =
+            Oauth20AccessTokenRefreshParams::new("s6BhdRkqt3", "tGzv3JOkF0XG5Qx2TlKWIA");
+        assert_eq!(
+            params.to_string(),
+            "grant_type=refresh_token&client_id=s6BhdRkqt3&refresh_token=tGzv3JOkF0XG5Qx2TlKWIA",
+        );
+
+        params.client_secret = Some("a+b/c=".into());
+        params.scopes = ["write", "read", "email"]
+            .into_iter()
+            .map(Into::into)
+            .collect();
+        assert_eq!(
+            params.to_string(),
+            "grant_type=refresh_token\
+             &client_id=s6BhdRkqt3\
+             &client_secret=a%2Bb%2Fc%3D\
+             &refresh_token=tGzv3JOkF0XG5Qx2TlKWIA\
+             &scope=email+read+write",
+        );
+
+        params.scope_delimiter = Oauth20ScopeDelimiter::Comma;
+        assert!(params.to_string().ends_with("&scope=email%2Cread%2Cwrite"));
+    }
+
+    #[test]
+    fn refresh_outcome_follows_the_error_code() {
+        let error =
+            |json: &str| Err(Oauth20AccessTokenErrorParams::try_from(json.as_bytes()).unwrap());
+
+        let body = br#"{"access_token":"a","token_type":"Bearer"}"#;
+        let success = Oauth20AccessTokenSuccessParams::try_from(body.as_slice()).unwrap();
+        assert!(matches!(
+            Oauth20RefreshOutcome::from(Ok(success)),
+            Oauth20RefreshOutcome::Refreshed(_)
+        ));
+
+        assert!(matches!(
+            Oauth20RefreshOutcome::from(error(r#"{"error":"invalid_grant"}"#)),
+            Oauth20RefreshOutcome::ReauthorizationRequired(_)
+        ));
+
+        let mut overloaded = error(r#"{"error":"temporarily_unavailable"}"#);
+        if let Err(err) = &mut overloaded {
+            err.retry_after = Some(Duration::from_secs(30));
+        }
+        assert!(matches!(
+            Oauth20RefreshOutcome::from(overloaded),
+            Oauth20RefreshOutcome::Transient(_)
+        ));
+
+        assert!(matches!(
+            Oauth20RefreshOutcome::from(error(r#"{"error":"invalid_client"}"#)),
+            Oauth20RefreshOutcome::Failed(_)
+        ));
+    }
+
+    #[test]
+    fn refresh_body_targets_resources() {
+        let mut params = Oauth20AccessTokenRefreshParams::new("client-id", "refresh");
+        params.resource = vec![
+            "https://api.example.com/".into(),
+            "https://mail.example.com/".into(),
+        ];
+        params.audience = Some("https://api.example.com".into());
+
+        assert_eq!(
+            params.to_string(),
+            "grant_type=refresh_token\
+             &client_id=client-id\
+             &refresh_token=refresh\
+             &resource=https%3A%2F%2Fapi.example.com%2F\
+             &resource=https%3A%2F%2Fmail.example.com%2F\
+             &audience=https%3A%2F%2Fapi.example.com",
+        );
+    }
+
+    #[test]
+    fn refresh_body_percent_encodes_utf8() {
+        let mut params = Oauth20AccessTokenRefreshParams::new("clïent", "tøken");
+        params.scopes = ["café", "日本"].into_iter().map(Into::into).collect();
+
+        assert_eq!(
+            params.to_string(),
+            "grant_type=refresh_token\
+             &client_id=cl%C3%AFent\
+             &refresh_token=t%C3%B8ken\
+             &scope=caf%C3%A9+%E6%97%A5%E6%9C%AC",
+        );
+    }
+}
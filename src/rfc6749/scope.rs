@@ -0,0 +1,280 @@
+//! Access token scope (RFC 6749 section 3.3).
+//!
+//! The scope is a list of case-sensitive tokens joined by spaces. A
+//! few providers (older GitHub endpoints among them) expect and
+//! return commas instead: requests pick their delimiter, while
+//! responses are split on either.
+//!
+//! Scopes are held in a `BTreeSet` rather than a `HashSet`: every
+//! request serializes its tokens in lexicographic order, so signed
+//! requests and golden tests get a stable output by construction,
+//! with no feature to enable.
+//!
+//! Incremental authorization (Google's `include_granted_scopes`, set
+//! through the auth request `extras`) requests only the tokens not
+//! granted yet, see [`scope_delta`], then merges the granted scope
+//! back, see [`merge_scope`].
+//!
+//! A scope configured as one string (`"openid email profile"`) turns
+//! into request tokens with [`parse_scope`], which validates them;
+//! tokens from a slice or an iterator go through [`collect_scope`].
+
+use alloc::{
+    borrow::Cow,
+    collections::BTreeSet,
+    string::{String, ToString},
+};
+
+use thiserror::Error;
+
+/// Errors that can occur while parsing a configured scope.
+#[derive(Debug, Eq, Error, PartialEq)]
+pub enum Oauth20ScopeError {
+    /// A scope token does not match the scope token grammar.
+    #[error("Invalid OAuth 2.0 scope token `{0}`")]
+    InvalidToken(String),
+}
+
+/// The separator placed between scope tokens on requests.
+///
+/// Refs: <https://datatracker.ietf.org/doc/html/rfc6749#section-3.3>
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum Oauth20ScopeDelimiter {
+    /// Space-delimited, as the specification mandates.
+    #[default]
+    Space,
+    /// Comma-delimited, a vendor quirk.
+    Comma,
+}
+
+impl Oauth20ScopeDelimiter {
+    /// Returns the delimiter's wire value.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Space => " ",
+            Self::Comma => ",",
+        }
+    }
+}
+
+/// Joins scope tokens with the given delimiter.
+///
+/// Tokens are trimmed, then empty and duplicate ones are skipped, so
+/// tokens gathered from user input or concatenated config never
+/// produce stray delimiters. The result is empty when no token is
+/// left: omit the `scope` parameter then.
+pub fn join_scope<'a>(
+    tokens: impl IntoIterator<Item = &'a Cow<'a, str>>,
+    delimiter: Oauth20ScopeDelimiter,
+) -> String {
+    let tokens: BTreeSet<&str> = tokens
+        .into_iter()
+        .map(|token| token.trim())
+        .filter(|token| !token.is_empty())
+        .collect();
+
+    let mut scope = String::new();
+    let mut glue = "";
+
+    for token in tokens {
+        scope.push_str(glue);
+        scope.push_str(token);
+        glue = delimiter.as_str();
+    }
+
+    scope
+}
+
+/// Returns whether a token matches the scope token grammar: printable
+/// ASCII, except space, `"` and `\`.
+///
+/// Refs: <https://datatracker.ietf.org/doc/html/rfc6749#section-3.3>
+pub fn is_valid_scope_token(token: &str) -> bool {
+    // NOTE: scope-token = 1*( %x21 / %x23-5B / %x5D-7E )
+    !token.is_empty()
+        && token
+            .bytes()
+            .all(|b| matches!(b, 0x21 | 0x23..=0x5B | 0x5D..=0x7E))
+}
+
+/// Parses a space-delimited scope into request tokens.
+///
+/// Splits on ASCII whitespace, drops empty tokens, then checks each
+/// one with [`is_valid_scope_token`]. The tokens borrow from `scope`.
+///
+/// Refs: <https://datatracker.ietf.org/doc/html/rfc6749#section-3.3>
+pub fn parse_scope(scope: &str) -> Result<BTreeSet<Cow<'_, str>>, Oauth20ScopeError> {
+    scope
+        .split_ascii_whitespace()
+        .map(|token| match is_valid_scope_token(token) {
+            true => Ok(Cow::Borrowed(token)),
+            false => Err(Oauth20ScopeError::InvalidToken(token.to_string())),
+        })
+        .collect()
+}
+
+/// Collects scope tokens from any iterator of strings, borrowed or
+/// owned, sparing the `.map(Into::into)` before `collect`.
+pub fn collect_scope<'a, S: Into<Cow<'a, str>>>(
+    tokens: impl IntoIterator<Item = S>,
+) -> BTreeSet<Cow<'a, str>> {
+    tokens.into_iter().map(Into::into).collect()
+}
+
+/// Converts borrowed scope tokens into owned ones.
+pub(crate) fn into_owned_scope(scope: BTreeSet<Cow<'_, str>>) -> BTreeSet<Cow<'static, str>> {
+    scope
+        .into_iter()
+        .map(|token| Cow::Owned(token.into_owned()))
+        .collect()
+}
+
+/// Splits a scope into its tokens, whether space- or comma-delimited.
+///
+/// A scope holding a space is space-delimited, its tokens keeping any
+/// comma since the scope token grammar allows one; only a scope without
+/// any space splits on commas, as the comma-delimited providers send.
+///
+/// Refs: <https://datatracker.ietf.org/doc/html/rfc6749#section-3.3>
+pub fn split_scope(scope: &str) -> BTreeSet<String> {
+    let delimiter = if scope.contains(' ') { ' ' } else { ',' };
+
+    scope
+        .split(delimiter)
+        .filter(|token| !token.is_empty())
+        .map(String::from)
+        .collect()
+}
+
+/// Returns the requested tokens that are not granted yet.
+///
+/// The result fits the `scope` of an authorization request asking only for
+/// the missing tokens; it is empty when everything is granted already.
+pub fn scope_delta<'a>(
+    requested: impl IntoIterator<Item = &'a str>,
+    granted: &BTreeSet<String>,
+) -> BTreeSet<Cow<'a, str>> {
+    requested
+        .into_iter()
+        .filter(|token| !granted.contains(*token))
+        .map(Cow::Borrowed)
+        .collect()
+}
+
+/// Merges the scope of a token response into the previously granted one.
+///
+/// `response_scope` is the `scope` of the response: when absent, the server
+/// granted exactly what was `requested` (RFC 6749 section 5.1).
+///
+/// Refs: <https://datatracker.ietf.org/doc/html/rfc6749#section-5.1>
+pub fn merge_scope<'a>(
+    granted: &BTreeSet<String>,
+    requested: impl IntoIterator<Item = &'a str>,
+    response_scope: Option<&str>,
+) -> BTreeSet<String> {
+    let mut merged = granted.clone();
+
+    match response_scope {
+        Some(scope) => merged.extend(split_scope(scope)),
+        None => merged.extend(requested.into_iter().map(String::from)),
+    }
+
+    merged
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::{borrow::Cow, collections::BTreeSet, string::String, vec};
+
+    use crate::rfc6749::scope::*;
+
+    #[test]
+    fn scope_joins_with_delimiter_and_splits_on_either() {
+        let tokens: BTreeSet<Cow<str>> = ["repo", "user"].into_iter().map(Cow::from).collect();
+        assert_eq!(
+            join_scope(&tokens, Oauth20ScopeDelimiter::Space),
+            "repo user"
+        );
+        assert_eq!(
+            join_scope(&tokens, Oauth20ScopeDelimiter::Comma),
+            "repo,user"
+        );
+
+        let expected: BTreeSet<String> = ["repo", "user"].into_iter().map(String::from).collect();
+        assert_eq!(split_scope("repo user"), expected);
+        assert_eq!(split_scope("repo,user"), expected);
+
+        let expected: BTreeSet<String> = ["a,b", "c"].into_iter().map(String::from).collect();
+        assert_eq!(split_scope("a,b c"), expected);
+        let expected: BTreeSet<String> = ["repo,", "user"].into_iter().map(String::from).collect();
+        assert_eq!(split_scope("repo, user"), expected);
+    }
+
+    #[test]
+    fn scope_join_skips_empty_and_trims() {
+        let tokens: BTreeSet<Cow<str>> = ["read", "", "write ", " read", "write"]
+            .into_iter()
+            .map(Cow::from)
+            .collect();
+        assert_eq!(
+            join_scope(&tokens, Oauth20ScopeDelimiter::Space),
+            "read write"
+        );
+
+        let tokens: BTreeSet<Cow<str>> = [Cow::from(" ")].into();
+        assert_eq!(join_scope(&tokens, Oauth20ScopeDelimiter::Space), "");
+    }
+
+    #[test]
+    fn scope_token_grammar() {
+        assert!(is_valid_scope_token("https://mail.google.com/"));
+        assert!(is_valid_scope_token("read:user"));
+        assert!(!is_valid_scope_token(""));
+        assert!(!is_valid_scope_token("read write"));
+        assert!(!is_valid_scope_token("say\"hi\""));
+        assert!(!is_valid_scope_token("back\\slash"));
+        assert!(!is_valid_scope_token("café"));
+    }
+
+    #[test]
+    fn scope_parses_from_config_string() {
+        let scope = parse_scope("  openid\temail  profile openid\n").unwrap();
+        let expected: BTreeSet<Cow<str>> = ["email", "openid", "profile"]
+            .into_iter()
+            .map(Cow::from)
+            .collect();
+        assert_eq!(scope, expected);
+
+        assert!(parse_scope("").unwrap().is_empty());
+        assert_eq!(
+            parse_scope("openid say\"hi\""),
+            Err(Oauth20ScopeError::InvalidToken("say\"hi\"".into()))
+        );
+    }
+
+    #[test]
+    fn scope_collects_from_any_strings() {
+        let expected: BTreeSet<Cow<str>> = [Cow::from("email"), Cow::from("openid")].into();
+
+        assert_eq!(collect_scope(["openid", "email"]), expected);
+        assert_eq!(
+            collect_scope(vec![String::from("email"), String::from("openid")]),
+            expected
+        );
+    }
+
+    #[test]
+    fn scope_delta_then_merge() {
+        let granted = split_scope("openid email");
+
+        let delta = scope_delta(["openid", "calendar"], &granted);
+        assert_eq!(delta, BTreeSet::from([Cow::from("calendar")]));
+        assert!(scope_delta(["email"], &granted).is_empty());
+
+        let merged = merge_scope(&granted, ["calendar"], None);
+        assert_eq!(merged, split_scope("calendar email openid"));
+
+        let merged = merge_scope(&granted, ["calendar"], Some("openid email calendar"));
+        assert_eq!(merged, split_scope("calendar email openid"));
+    }
+}
@@ -4,7 +4,7 @@
 //! Sent on the authorization request and echoed back on the callback,
 //! binding the two legs of the authorization code grant together.
 
-use core::fmt;
+use core::{fmt, str::FromStr};
 
 #[cfg(feature = "client")]
 use alloc::{boxed::Box, vec::Vec};
@@ -17,6 +17,7 @@ use serde::{
     Deserialize, Deserializer, Serialize, Serializer,
     de::{Error, Visitor},
 };
+use thiserror::Error as ThisError;
 
 // NOTE: VSCHAR = %x20-7E
 const VSCHAR: [u8; 95] = [
@@ -28,6 +29,14 @@ const VSCHAR: [u8; 95] = [
     0x70, 0x71, 0x72, 0x73, 0x74, 0x75, 0x76, 0x77, 0x78, 0x79, 0x7A, 0x7B, 0x7C, 0x7D, 0x7E,
 ];
 
+/// Errors that can occur while rebuilding a received state.
+#[derive(Debug, ThisError)]
+pub enum Oauth20StateError {
+    /// The state contains a byte outside the visible ASCII characters.
+    #[error("Invalid byte 0x{0:x} found in state")]
+    InvalidByte(u8),
+}
+
 /// The value used to prevent CSRF attacks.
 ///
 /// Represents an opaque value used by the client to maintain state
@@ -50,6 +59,26 @@ impl Oauth20State {
         Self(SecretBox::from(random))
     }
 
+    /// Rebuilds the state echoed back on the callback, as received, to
+    /// compare it against the one sent.
+    ///
+    /// Only checks the bytes are visible ASCII characters (VSCHAR);
+    /// [`Oauth20AuthParams`] already rebuilds it while parsing the
+    /// redirection, use this when the state is received some other way.
+    ///
+    /// [`Oauth20AuthParams`]: crate::rfc6749::auth_response::Oauth20AuthParams
+    ///
+    /// Refs: <https://datatracker.ietf.org/doc/html/rfc6749#appendix-A.5>
+    pub fn from_received(state: &str) -> Result<Self, Oauth20StateError> {
+        let bytes = state.as_bytes();
+
+        if let Some(b) = bytes.iter().find(|b| !VSCHAR.contains(b)) {
+            return Err(Oauth20StateError::InvalidByte(*b));
+        }
+
+        Ok(Self(SecretBox::from(bytes.to_vec())))
+    }
+
     /// Exposes the random state bytes.
     // SAFETY: this function exposes the random state
     pub fn expose(&self) -> &[u8] {
@@ -77,6 +106,14 @@ impl Default for Oauth20State {
     }
 }
 
+impl FromStr for Oauth20State {
+    type Err = Oauth20StateError;
+
+    fn from_str(state: &str) -> Result<Self, Self::Err> {
+        Self::from_received(state)
+    }
+}
+
 impl Serialize for Oauth20State {
     // SAFETY: exposes the random state
     fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
@@ -115,3 +152,22 @@ impl<'de> Visitor<'de> for StateVisitor {
         Ok(Oauth20State(SecretBox::from(bytes.to_vec())))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::rfc6749::state::*;
+
+    #[test]
+    fn received_state_is_kept_as_is() {
+        let sent: Oauth20State = serde_json::from_str("\"xyz ~!\"").unwrap();
+        let received = Oauth20State::from_received("xyz ~!").unwrap();
+        assert_eq!(received.expose(), b"xyz ~!");
+        assert_eq!(received, sent);
+
+        assert_ne!("xyz".parse::<Oauth20State>().unwrap(), sent);
+        assert!(matches!(
+            Oauth20State::from_received("caf\u{e9}"),
+            Err(Oauth20StateError::InvalidByte(0xc3))
+        ));
+    }
+}
@@ -13,18 +13,11 @@
 //!     net::TcpStream,
 //! };
 //!
-//! use io_http::rfc9110::request::HttpRequest;
-//! use io_oauth::rfc7591::register::*;
+//! use io_oauth::{rfc6749::endpoint::post_request, rfc7591::register::*};
 //! use url::Url;
 //!
 //! let registration_url = Url::parse("https://example.com/register").unwrap();
-//! let request = HttpRequest {
-//!     method: "POST".into(),
-//!     url: registration_url.clone(),
-//!     headers: Vec::new(),
-//!     body: Vec::new(),
-//! }
-//! .header("Host", registration_url.host_str().unwrap());
+//! let request = post_request(&registration_url);
 //!
 //! let params = Oauth20ClientRegisterParams {
 //!     client_name: Some("My App".into()),
@@ -3,6 +3,21 @@
 //! The code verifier and derived code challenge hardening the
 //! authorization code grant for public clients: the challenge rides
 //! on the authorization request, the verifier on the token exchange.
+//!
+//! PKCE is not behind a feature: these types are always compiled.
+//! Only the random generation (`new`, `Default`) needs the `client`
+//! feature; without it, build the verifier from your own entropy with
+//! `FromStr` or [`Oauth20PkceCodeVerifier::from_bytes`].
+//!
+//! Leaving PKCE out is the failure mode to watch for: a server
+//! requiring it (OAuth 2.1, RFC 9700 for public clients) answers the
+//! authorization or the token request with a bare `invalid_request`.
+//! When [`Oauth20PkceCodeChallenge::negotiate`] finds a method in the
+//! server metadata, send the challenge.
+//!
+//! On the authorization server side, [`Oauth20PkceStoredChallenge`]
+//! keeps the received challenge and verifies the token request's
+//! verifier against it.
 
 use core::str::FromStr;
 
@@ -15,7 +30,9 @@ use log::debug;
 #[cfg(feature = "client")]
 use rand::seq::IndexedRandom;
 use secrecy::{ExposeSecret, SecretBox};
+use serde::{Deserialize, Serialize};
 use sha2::{Digest, Sha256};
+use thiserror::Error;
 
 /// unreserved = ALPHA / DIGIT / "-" / "." / "_" / "~"
 /// ALPHA = %x41-5A / %x61-7A
@@ -43,28 +60,36 @@ pub struct Oauth20PkceCodeChallenge {
 impl Oauth20PkceCodeChallenge {
     /// Returns the challenge encoded per its method (base64url for `S256`).
     pub fn encode(&self) -> Cow<'_, str> {
-        match self.method {
-            Oauth20PkceCodeChallengeMethod::Plain => {
-                let verifier = self.verifier.expose();
-                String::from_utf8_lossy(verifier)
-            }
-            Oauth20PkceCodeChallengeMethod::Sha256 => {
-                let digest = Sha256::digest(self.verifier.expose());
-                BASE64_URL_SAFE_NO_PAD.encode(digest).into()
-            }
-        }
+        self.method.transform(self.verifier.expose())
+    }
+
+    /// Picks the strongest method among the ones the server supports.
+    ///
+    /// Pass the `code_challenge_methods_supported` of the server metadata:
+    /// `S256` wins over `plain`, and `None` means the server advertises no
+    /// method at all (PKCE unsupported, or not advertised).
+    ///
+    /// Refs: <https://datatracker.ietf.org/doc/html/rfc8414#section-2>
+    pub fn negotiate(
+        methods: &[Oauth20PkceCodeChallengeMethod],
+    ) -> Option<Oauth20PkceCodeChallengeMethod> {
+        methods.iter().copied().max()
     }
 }
 
 /// The transformation from code verifier to code challenge.
 ///
+/// Variants are ordered from the weakest to the strongest.
+///
 /// Refs: <https://datatracker.ietf.org/doc/html/rfc7636#section-4.2>
-#[derive(Clone, Debug, Default)]
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, Ord, PartialEq, PartialOrd, Serialize)]
 pub enum Oauth20PkceCodeChallengeMethod {
     /// The challenge is the verifier verbatim.
+    #[serde(rename = "plain")]
     Plain,
     /// The challenge is the base64url-encoded SHA-256 of the verifier.
     #[default]
+    #[serde(rename = "S256")]
     Sha256,
 }
 
@@ -79,10 +104,121 @@ impl Oauth20PkceCodeChallengeMethod {
             Self::Sha256 => Self::SHA256,
         }
     }
+
+    /// Derives the code challenge from the given code verifier.
+    ///
+    /// Refs: <https://datatracker.ietf.org/doc/html/rfc7636#section-4.2>
+    pub fn transform<'a>(&self, verifier: &'a [u8]) -> Cow<'a, str> {
+        match self {
+            Self::Plain => String::from_utf8_lossy(verifier),
+            Self::Sha256 => {
+                let digest = Sha256::digest(verifier);
+                BASE64_URL_SAFE_NO_PAD.encode(digest).into()
+            }
+        }
+    }
+}
+
+impl FromStr for Oauth20PkceCodeChallengeMethod {
+    type Err = Oauth20PkceCodeChallengeMethodParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            Self::PLAIN => Ok(Self::Plain),
+            Self::SHA256 => Ok(Self::Sha256),
+            _ => Err(Oauth20PkceCodeChallengeMethodParseError(s.into())),
+        }
+    }
+}
+
+/// The PKCE code challenge received by an authorization server.
+///
+/// The server role of [`Oauth20PkceCodeChallenge`]: it holds the
+/// challenge as sent on the authorization request rather than the
+/// verifier, to be stored along the authorization code then checked
+/// against the verifier of the token request.
+///
+/// Refs: <https://datatracker.ietf.org/doc/html/rfc7636#section-4.4>
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct Oauth20PkceStoredChallenge {
+    /// The transformation the client applied to its verifier.
+    pub method: Oauth20PkceCodeChallengeMethod,
+    /// The code challenge, as received.
+    pub challenge: String,
+}
+
+impl Oauth20PkceStoredChallenge {
+    /// Builds the stored challenge from the `code_challenge` and
+    /// `code_challenge_method` params of the authorization request.
+    ///
+    /// An absent method defaults to `plain`.
+    ///
+    /// Refs: <https://datatracker.ietf.org/doc/html/rfc7636#section-4.3>
+    pub fn parse(
+        challenge: impl Into<String>,
+        method: Option<&str>,
+    ) -> Result<Self, Oauth20PkceCodeChallengeMethodParseError> {
+        let method = match method {
+            Some(method) => method.parse()?,
+            None => Oauth20PkceCodeChallengeMethod::Plain,
+        };
+
+        Ok(Self {
+            method,
+            challenge: challenge.into(),
+        })
+    }
+
+    /// Returns whether the `verifier` of the token request matches the
+    /// challenge.
+    ///
+    /// A verifier outside the verifier grammar (43 to 128 unreserved
+    /// characters) never matches. The comparison runs in constant time.
+    ///
+    /// Refs: <https://datatracker.ietf.org/doc/html/rfc7636#section-4.6>
+    pub fn verify(&self, verifier: &[u8]) -> bool {
+        if Oauth20PkceCodeVerifier::from_bytes(verifier).is_err() {
+            return false;
+        }
+
+        let expected = self.challenge.as_bytes();
+        let actual = self.method.transform(verifier);
+        let actual = actual.as_bytes();
+
+        expected.len() == actual.len()
+            && expected
+                .iter()
+                .zip(actual)
+                .fold(0, |diff, (a, b)| diff | (a ^ b))
+                == 0
+    }
+}
+
+/// The error returned when parsing an unknown code challenge method.
+#[derive(Debug, Error)]
+#[error("Unknown PKCE code challenge method `{0}`")]
+pub struct Oauth20PkceCodeChallengeMethodParseError(pub String);
+
+/// Errors that can occur while building a code verifier from bytes.
+#[derive(Debug, Error)]
+pub enum Oauth20PkceCodeVerifierError {
+    /// The verifier contains a byte outside the unreserved characters.
+    #[error("Invalid byte 0x{0:x} found in PKCE code verifier")]
+    InvalidByte(u8),
+    /// The verifier is not 43 to 128 bytes long.
+    #[error("Invalid PKCE code verifier length {0} (expected 43 to 128)")]
+    InvalidLength(usize),
 }
 
 /// The high-entropy secret the code challenge is derived from.
 ///
+/// Cloning copies the secret bytes into a new allocation, zeroized on
+/// drop like the original. The access token request borrows the
+/// verifier instead, see
+/// [`Oauth20AccessTokenRequestParams::pkce_code_verifier`].
+///
+/// [`Oauth20AccessTokenRequestParams::pkce_code_verifier`]: crate::rfc6749::access_token_request::Oauth20AccessTokenRequestParams::pkce_code_verifier
+///
 /// Refs: <https://datatracker.ietf.org/doc/html/rfc7636#section-4.1>
 #[derive(Clone, Debug)]
 pub struct Oauth20PkceCodeVerifier(SecretBox<[u8]>);
@@ -99,11 +235,47 @@ impl Oauth20PkceCodeVerifier {
         Self(SecretBox::from(random))
     }
 
+    /// Builds a verifier from bytes derived by the caller, checking they
+    /// are unreserved characters and 43 to 128 bytes long.
+    ///
+    /// Refs: <https://datatracker.ietf.org/doc/html/rfc7636#section-4.1>
+    pub fn from_bytes(bytes: impl AsRef<[u8]>) -> Result<Self, Oauth20PkceCodeVerifierError> {
+        let bytes = bytes.as_ref();
+
+        if let Some(b) = bytes.iter().find(|b| !UNRESERVED.contains(b)) {
+            return Err(Oauth20PkceCodeVerifierError::InvalidByte(*b));
+        }
+
+        if !(43..=128).contains(&bytes.len()) {
+            return Err(Oauth20PkceCodeVerifierError::InvalidLength(bytes.len()));
+        }
+
+        Ok(Self(SecretBox::from(bytes.to_vec())))
+    }
+
     /// Exposes the code verifier.
     // SAFETY: this function exposes the code verifier
     pub fn expose(&self) -> &[u8] {
         self.0.expose_secret()
     }
+
+    /// Returns the length of the verifier, in bytes.
+    pub fn len(&self) -> usize {
+        self.expose().len()
+    }
+
+    /// Returns whether the verifier is empty.
+    pub fn is_empty(&self) -> bool {
+        self.expose().is_empty()
+    }
+
+    /// Returns whether the length is within the 43 to 128 bytes required,
+    /// which `FromStr` does not check.
+    ///
+    /// Refs: <https://datatracker.ietf.org/doc/html/rfc7636#section-4.1>
+    pub fn is_valid_length(&self) -> bool {
+        (43..=128).contains(&self.len())
+    }
 }
 
 #[cfg(feature = "client")]
@@ -135,3 +307,84 @@ impl FromStr for Oauth20PkceCodeVerifier {
         Ok(Self(SecretBox::from(bytes.to_vec())))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use core::str::FromStr;
+
+    use crate::rfc7636::pkce::*;
+
+    #[test]
+    fn verifier_length_is_checked_without_exposing() {
+        let verifier = Oauth20PkceCodeVerifier::from_str(&"a".repeat(43)).unwrap();
+        assert_eq!(verifier.len(), 43);
+        assert!(verifier.is_valid_length());
+
+        let verifier = Oauth20PkceCodeVerifier::from_str(&"a".repeat(129)).unwrap();
+        assert!(!verifier.is_valid_length());
+
+        let verifier = Oauth20PkceCodeVerifier::from_str("").unwrap();
+        assert!(verifier.is_empty());
+        assert!(!verifier.is_valid_length());
+    }
+
+    #[test]
+    fn verifier_from_bytes_is_validated() {
+        let verifier = Oauth20PkceCodeVerifier::from_bytes([b'a'; 43]).unwrap();
+        assert_eq!(verifier.expose(), [b'a'; 43]);
+
+        assert!(matches!(
+            Oauth20PkceCodeVerifier::from_bytes([b'a'; 42]),
+            Err(Oauth20PkceCodeVerifierError::InvalidLength(42))
+        ));
+        assert!(matches!(
+            Oauth20PkceCodeVerifier::from_bytes([b'+'; 43]),
+            Err(Oauth20PkceCodeVerifierError::InvalidByte(b'+'))
+        ));
+    }
+
+    #[test]
+    fn negotiate_prefers_s256() {
+        use Oauth20PkceCodeChallengeMethod::*;
+
+        assert_eq!(
+            Oauth20PkceCodeChallenge::negotiate(&[Plain, Sha256]),
+            Some(Sha256)
+        );
+        assert_eq!(
+            Oauth20PkceCodeChallenge::negotiate(&[Sha256, Plain]),
+            Some(Sha256)
+        );
+        assert_eq!(Oauth20PkceCodeChallenge::negotiate(&[Plain]), Some(Plain));
+        assert_eq!(Oauth20PkceCodeChallenge::negotiate(&[]), None);
+
+        assert_eq!(
+            "S256".parse::<Oauth20PkceCodeChallengeMethod>().unwrap(),
+            Sha256
+        );
+        assert_eq!(
+            "plain".parse::<Oauth20PkceCodeChallengeMethod>().unwrap(),
+            Plain
+        );
+        assert!("s256".parse::<Oauth20PkceCodeChallengeMethod>().is_err());
+    }
+
+    #[test]
+    fn stored_challenge_verifies_the_verifier() {
+        let verifier = [b'a'; 43];
+        let challenge = Oauth20PkceCodeChallengeMethod::Sha256.transform(&verifier);
+
+        let stored = Oauth20PkceStoredChallenge::parse(challenge, Some("S256")).unwrap();
+        assert!(stored.verify(&verifier));
+        assert!(!stored.verify(&[b'b'; 43]));
+
+        let stored = Oauth20PkceStoredChallenge::parse("a".repeat(43), None).unwrap();
+        assert_eq!(stored.method, Oauth20PkceCodeChallengeMethod::Plain);
+        assert!(stored.verify(&verifier));
+
+        let stored = Oauth20PkceStoredChallenge::parse("short", None).unwrap();
+        assert!(!stored.verify(b"short"));
+
+        assert!(Oauth20PkceStoredChallenge::parse("c", Some("S512")).is_err());
+    }
+}
@@ -14,22 +14,16 @@
 //!     net::TcpStream,
 //! };
 //!
-//! use io_http::rfc9110::request::HttpRequest;
-//! use io_oauth::rfc8628::auth::*;
+//! use io_oauth::{rfc6749::endpoint::post_request, rfc8628::auth::*};
 //! use url::Url;
 //!
 //! let device_url = Url::parse("https://example.com/devicecode").unwrap();
-//! let request = HttpRequest {
-//!     method: "POST".into(),
-//!     url: device_url.clone(),
-//!     headers: Vec::new(),
-//!     body: Vec::new(),
-//! }
-//! .header("Host", device_url.host_str().unwrap());
+//! let request = post_request(&device_url);
 //!
 //! let params = Oauth20DeviceAuthRequestParams {
 //!     client_id: "client-id".into(),
 //!     scope: Default::default(),
+//!     scope_delimiter: Default::default(),
 //! };
 //!
 //! let mut stream = TcpStream::connect("example.com:443").unwrap();
@@ -55,12 +49,7 @@
 
 use core::fmt;
 
-use alloc::{
-    borrow::Cow,
-    collections::BTreeSet,
-    string::{String, ToString},
-    vec::Vec,
-};
+use alloc::{borrow::Cow, collections::BTreeSet, string::String, vec::Vec};
 
 use io_http::{
     coroutine::*,
@@ -76,7 +65,10 @@ use serde::Deserialize;
 use thiserror::Error;
 use url::{Url, form_urlencoded::Serializer};
 
-use crate::rfc6749::issue_access_token::Oauth20AccessTokenErrorParams;
+use crate::rfc6749::{
+    issue_access_token::Oauth20AccessTokenErrorParams,
+    scope::{Oauth20ScopeDelimiter, into_owned_scope, join_scope},
+};
 
 /// The device authorization response: success params, or error params.
 ///
@@ -129,12 +121,25 @@ pub struct Oauth20DeviceAuthRequest {
 impl Oauth20DeviceAuthRequest {
     /// Creates the coroutine to request a device and user code pair.
     pub fn new(request: HttpRequest, body: Oauth20DeviceAuthRequestParams<'_>) -> Self {
-        debug!("prepare device authorization request");
-        trace!("url: {}", request.url);
+        Self::from_request(Self::dry_run(request, body))
+    }
 
-        let request = request
+    /// Returns the HTTP request the coroutine would send, without sending it.
+    pub fn dry_run(request: HttpRequest, body: Oauth20DeviceAuthRequestParams<'_>) -> HttpRequest {
+        request
             .header("Content-Type", "application/x-www-form-urlencoded")
-            .body(body.to_string().into_bytes());
+            .body(body.to_serializer().finish().into_bytes())
+    }
+
+    /// Creates the coroutine sending `request` as-is.
+    ///
+    /// Meant for a request built by [`Self::dry_run`] then adjusted, for
+    /// instance by [`Oauth20ClientAuthMethod::authenticate`].
+    ///
+    /// [`Oauth20ClientAuthMethod::authenticate`]: crate::rfc6749::client_auth::Oauth20ClientAuthMethod::authenticate
+    pub fn from_request(request: HttpRequest) -> Self {
+        debug!("prepare device authorization request");
+        trace!("url: {}", request.url);
 
         Self {
             send: Http11Send::new(request),
@@ -227,25 +232,34 @@ pub struct Oauth20DeviceAuthRequestParams<'a> {
     ///
     /// Refs: <https://datatracker.ietf.org/doc/html/rfc6749#section-3.3>
     pub scope: BTreeSet<Cow<'a, str>>,
+    /// The separator between scope tokens, spaces unless the provider
+    /// expects commas.
+    pub scope_delimiter: Oauth20ScopeDelimiter,
 }
 
+/// The device authorization request params, owning every value.
+pub type Oauth20DeviceAuthRequestParamsOwned = Oauth20DeviceAuthRequestParams<'static>;
+
 impl<'a> Oauth20DeviceAuthRequestParams<'a> {
+    /// Converts every borrowed value into an owned one, so the params
+    /// outlive the strings they were built from.
+    pub fn into_owned(self) -> Oauth20DeviceAuthRequestParams<'static> {
+        Oauth20DeviceAuthRequestParams {
+            client_id: Cow::Owned(self.client_id.into_owned()),
+            scope: into_owned_scope(self.scope),
+            scope_delimiter: self.scope_delimiter,
+        }
+    }
+
     /// Serializes the params into the form-urlencoded request body.
     pub fn to_serializer(&self) -> Serializer<'a, String> {
         let mut serializer = Serializer::new(String::new());
 
         serializer.append_pair("client_id", &self.client_id);
 
-        if !self.scope.is_empty() {
-            let mut scope = String::new();
-            let mut glue = "";
-
-            for token in &self.scope {
-                scope.push_str(glue);
-                scope.push_str(token);
-                glue = " ";
-            }
+        let scope = join_scope(&self.scope, self.scope_delimiter);
 
+        if !scope.is_empty() {
             serializer.append_pair("scope", &scope);
         }
 
@@ -8,6 +8,8 @@
 //!
 //! One coroutine performs one poll; the caller waits the interval and retries
 //! with a fresh coroutine while the response carries `authorization_pending`.
+//! [`Oauth20DevicePolling`] keeps the interval and the code lifetime across
+//! polls.
 //!
 //! ```rust,no_run
 //! use std::{
@@ -15,18 +17,11 @@
 //!     net::TcpStream,
 //! };
 //!
-//! use io_http::rfc9110::request::HttpRequest;
-//! use io_oauth::rfc8628::token::*;
+//! use io_oauth::{rfc6749::endpoint::post_request, rfc8628::token::*};
 //! use url::Url;
 //!
 //! let token_url = Url::parse("https://example.com/token").unwrap();
-//! let request = HttpRequest {
-//!     method: "POST".into(),
-//!     url: token_url.clone(),
-//!     headers: Vec::new(),
-//!     body: Vec::new(),
-//! }
-//! .header("Host", token_url.host_str().unwrap());
+//! let request = post_request(&token_url);
 //!
 //! let params = Oauth20DeviceAccessTokenRequestParams {
 //!     client_id: "client-id".into(),
@@ -54,30 +49,24 @@
 //! # let _ = response;
 //! ```
 
-use core::fmt;
+use core::{fmt, time::Duration};
 
-use alloc::{
-    borrow::Cow,
-    string::{String, ToString},
-    vec::Vec,
-};
+use alloc::{borrow::Cow, string::String, vec::Vec};
 
-use io_http::{
-    coroutine::*,
-    rfc9110::{
-        request::HttpRequest,
-        send::{HttpSendOutput, HttpSendYield},
-    },
-    rfc9112::send::{Http11Send, Http11SendError},
-};
-use log::{debug, trace};
+use io_http::{rfc9110::request::HttpRequest, rfc9112::send::Http11SendError};
+use log::debug;
 use secrecy::{ExposeSecret, SecretString};
 use thiserror::Error;
 use url::{Url, form_urlencoded::Serializer};
 
-use crate::rfc6749::issue_access_token::{
-    Oauth20AccessTokenErrorParams, Oauth20AccessTokenResponse, Oauth20AccessTokenSuccessParams,
-    parse_http_date,
+use crate::{
+    rfc6749::issue_access_token::{
+        Oauth20AccessTokenErrorCode, Oauth20AccessTokenErrorParams, Oauth20AccessTokenResponse,
+        Oauth20AccessTokenResponseError, Oauth20AccessTokenSuccessParams, Oauth20CoroutineState,
+        Oauth20RequestError, Oauth20TokenCoroutine, Oauth20TokenCoroutineError,
+        Oauth20TokenCoroutineResult, accept_json,
+    },
+    rfc8628::auth::Oauth20DeviceAuthSuccessParams,
 };
 
 /// Errors that can occur during the coroutine progression.
@@ -88,7 +77,7 @@ pub enum Oauth20DeviceAccessTokenRequestError {
     SendHttpRequest(#[from] Http11SendError),
     /// The HTTP response could not be parsed.
     #[error(transparent)]
-    ParseHttpResponse(#[from] serde_json::Error),
+    ParseHttpResponse(#[from] Oauth20AccessTokenResponseError),
     /// The server answered with an unexpected redirection.
     #[error("Unexpected redirection {code} to {url}")]
     Redirect {
@@ -97,6 +86,9 @@ pub enum Oauth20DeviceAccessTokenRequestError {
         /// The redirection HTTP status code.
         code: u16,
     },
+    /// The coroutine was cancelled by the caller.
+    #[error("OAuth 2.0 device access token request cancelled")]
+    Cancelled,
 }
 
 /// Result returned by the coroutine's resume function.
@@ -109,8 +101,9 @@ pub enum Oauth20DeviceAccessTokenRequestResult {
     /// The coroutine wants the given bytes to be written to the
     /// socket.
     WantsWrite(Vec<u8>),
-    /// The coroutine encountered an error.
-    Err(Oauth20DeviceAccessTokenRequestError),
+    /// The coroutine encountered an error, along the identifier of
+    /// the request.
+    Err(Oauth20RequestError<Oauth20DeviceAccessTokenRequestError>),
 }
 
 /// The I/O-free coroutine for one device-code poll of the token endpoint.
@@ -121,68 +114,267 @@ pub enum Oauth20DeviceAccessTokenRequestResult {
 ///
 /// Refs: <https://datatracker.ietf.org/doc/html/rfc8628#section-3.4>
 pub struct Oauth20DeviceAccessTokenRequest {
-    send: Http11Send,
+    inner: Oauth20TokenCoroutine,
 }
 
 impl Oauth20DeviceAccessTokenRequest {
     /// Creates the coroutine for one device-code poll.
     pub fn new(request: HttpRequest, body: Oauth20DeviceAccessTokenRequestParams<'_>) -> Self {
-        debug!("prepare device access token request");
-        trace!("url: {}", request.url);
+        Self::from_request(Self::dry_run(request, body))
+    }
 
-        let request = request
-            .header("Content-Type", "application/x-www-form-urlencoded")
-            .body(body.to_string().into_bytes());
+    /// Creates the coroutine sending `request` as-is.
+    ///
+    /// Meant for a request built by [`Self::dry_run`] then adjusted, for
+    /// instance by [`Oauth20ClientAuthMethod::authenticate`].
+    ///
+    /// [`Oauth20ClientAuthMethod::authenticate`]: crate::rfc6749::client_auth::Oauth20ClientAuthMethod::authenticate
+    pub fn from_request(request: HttpRequest) -> Self {
+        Self {
+            inner: Oauth20TokenCoroutine::new("device access token", request),
+        }
+    }
 
+    /// Creates the coroutine sending `request` as-is, like
+    /// [`Self::from_request`], keeping a copy of it for
+    /// [`Self::into_request`].
+    ///
+    /// The copy holds the body a second time, secrets included, for the
+    /// whole life of the coroutine; hence opt-in.
+    // SAFETY: the retained body keeps the params' secrets in memory
+    pub fn from_request_retained(request: HttpRequest) -> Self {
         Self {
-            send: Http11Send::new(request),
+            inner: Oauth20TokenCoroutine::retained("device access token", request),
         }
     }
 
+    /// Returns the HTTP request the coroutine would send, without sending it.
+    ///
+    /// Meant for inspecting or replaying a request (with `curl`, say) when a
+    /// provider misbehaves. The `Debug` output of the request redacts its
+    /// body, but the body itself carries the form params in clear.
+    ///
+    /// Asks for a JSON response with `Accept: application/json`, unless
+    /// `request` already carries an `Accept` header.
+    // SAFETY: the returned body exposes the params' secrets
+    pub fn dry_run(
+        request: HttpRequest,
+        body: Oauth20DeviceAccessTokenRequestParams<'_>,
+    ) -> HttpRequest {
+        accept_json(request)
+            .header("Content-Type", "application/x-www-form-urlencoded")
+            .body(body.to_serializer().finish().into_bytes())
+    }
+
+    /// Returns the identifier of the request, when the caller set one.
+    ///
+    /// Read from the [`OAUTH20_REQUEST_ID_HEADER`] of the request, it tags
+    /// the errors returned by [`Self::resume`].
+    ///
+    /// [`OAUTH20_REQUEST_ID_HEADER`]: crate::rfc6749::issue_access_token::OAUTH20_REQUEST_ID_HEADER
+    pub fn request_id(&self) -> Option<&str> {
+        self.inner.request_id()
+    }
+
+    /// Returns whether the server kept the connection alive for another
+    /// token request, `false` until [`Self::resume`] returned the response.
+    pub fn keep_alive(&self) -> bool {
+        self.inner.keep_alive()
+    }
+
+    /// Returns the progress of the coroutine, for diagnostics.
+    pub fn state(&self) -> Oauth20CoroutineState {
+        self.inner.state()
+    }
+
+    /// Returns the request the coroutine was created with, to retry
+    /// through a fresh [`Self::from_request`] once it failed.
+    ///
+    /// `None` unless the coroutine was created by
+    /// [`Self::from_request_retained`], the only one keeping it.
+    // SAFETY: the returned body exposes the params' secrets
+    pub fn into_request(self) -> Option<HttpRequest> {
+        self.inner.into_request()
+    }
+
+    /// Cancels the coroutine.
+    ///
+    /// Every later [`Self::resume`] returns a `Cancelled` error without
+    /// yielding any more I/O. The request may have been partially written, or
+    /// its response partially read: drop the stream rather than reusing it
+    /// for another request.
+    pub fn cancel(&mut self) {
+        self.inner.cancel()
+    }
+
     /// Makes the coroutine progress.
     pub fn resume(&mut self, arg: Option<&[u8]>) -> Oauth20DeviceAccessTokenRequestResult {
-        match self.send.resume(arg) {
-            HttpCoroutineState::Complete(Ok(HttpSendOutput { response, .. }))
-                if response.status.is_success() =>
-            {
-                debug!("received device access token response");
-                trace!("status: {}", *response.status);
-
-                match Oauth20AccessTokenSuccessParams::try_from(response.body.as_slice()) {
-                    Ok(mut res) => {
-                        res.issued_at = response.header("date").and_then(parse_http_date);
-                        Oauth20DeviceAccessTokenRequestResult::Ok(Ok(res))
-                    }
-                    Err(err) => Oauth20DeviceAccessTokenRequestResult::Err(err.into()),
-                }
-            }
-            HttpCoroutineState::Complete(Ok(HttpSendOutput { response, .. })) => {
-                debug!("received device access token error response");
-                trace!("status: {}", *response.status);
-
-                match Oauth20AccessTokenErrorParams::try_from(response.body.as_slice()) {
-                    Ok(res) => Oauth20DeviceAccessTokenRequestResult::Ok(Err(res)),
-                    Err(err) => Oauth20DeviceAccessTokenRequestResult::Err(err.into()),
-                }
-            }
-            HttpCoroutineState::Yielded(HttpSendYield::WantsRead) => {
+        match self.inner.resume(arg) {
+            Oauth20TokenCoroutineResult::Ok(res) => Oauth20DeviceAccessTokenRequestResult::Ok(res),
+            Oauth20TokenCoroutineResult::WantsRead => {
                 Oauth20DeviceAccessTokenRequestResult::WantsRead
             }
-            HttpCoroutineState::Yielded(HttpSendYield::WantsWrite(bytes)) => {
+            Oauth20TokenCoroutineResult::WantsWrite(bytes) => {
                 Oauth20DeviceAccessTokenRequestResult::WantsWrite(bytes)
             }
-            HttpCoroutineState::Yielded(HttpSendYield::WantsRedirect { url, response, .. }) => {
-                Oauth20DeviceAccessTokenRequestResult::Err(
-                    Oauth20DeviceAccessTokenRequestError::Redirect {
-                        url,
-                        code: *response.status,
-                    },
-                )
+            Oauth20TokenCoroutineResult::Err(err) => {
+                Oauth20DeviceAccessTokenRequestResult::Err(err)
             }
-            HttpCoroutineState::Complete(Err(err)) => {
-                Oauth20DeviceAccessTokenRequestResult::Err(err.into())
+        }
+    }
+}
+
+impl Oauth20TokenCoroutineError for Oauth20DeviceAccessTokenRequestError {
+    fn redirect(url: Url, code: u16) -> Self {
+        Self::Redirect { url, code }
+    }
+
+    fn cancelled() -> Self {
+        Self::Cancelled
+    }
+}
+
+/// The outcome of one poll, classified for the polling loop.
+///
+/// `Pending` and `SlowDown` keep polling, every other variant stops it.
+///
+/// Refs: <https://datatracker.ietf.org/doc/html/rfc8628#section-3.5>
+#[derive(Debug)]
+pub enum Oauth20DeviceAccessTokenPoll {
+    /// The user has not completed the authorization yet: poll again
+    /// after the current interval.
+    Pending,
+    /// The server asks to poll less often: poll again after `interval`.
+    SlowDown {
+        /// The increased polling interval.
+        interval: Duration,
+    },
+    /// The user denied the authorization (`access_denied`, or Entra's
+    /// `authorization_declined`).
+    Denied(Oauth20AccessTokenErrorParams),
+    /// The device code expired: restart the grant from the device
+    /// authorization request.
+    Expired(Oauth20AccessTokenErrorParams),
+    /// The access token was issued.
+    Success(Oauth20AccessTokenSuccessParams),
+    /// Any other error response.
+    Error(Oauth20AccessTokenErrorParams),
+}
+
+impl Oauth20DeviceAccessTokenPoll {
+    /// The interval increase `slow_down` asks for.
+    ///
+    /// Refs: <https://datatracker.ietf.org/doc/html/rfc8628#section-3.5>
+    pub const SLOW_DOWN_INCREMENT: Duration = Duration::from_secs(5);
+
+    /// Classifies the response of a poll made after waiting `interval`.
+    ///
+    /// On `slow_down`, the next interval is `interval` plus
+    /// [`Self::SLOW_DOWN_INCREMENT`], or the `Retry-After` of the response
+    /// when it asks for more.
+    pub fn new(response: Oauth20AccessTokenResponse, interval: Duration) -> Self {
+        let err = match response {
+            Ok(success) => return Self::Success(success),
+            Err(err) => err,
+        };
+
+        match err.error {
+            Oauth20AccessTokenErrorCode::AuthorizationPending => Self::Pending,
+            Oauth20AccessTokenErrorCode::SlowDown => {
+                let mut interval = interval + Self::SLOW_DOWN_INCREMENT;
+
+                if let Some(after) = err.retry_after {
+                    interval = interval.max(after);
+                }
+
+                Self::SlowDown { interval }
             }
+            Oauth20AccessTokenErrorCode::AccessDenied
+            | Oauth20AccessTokenErrorCode::AuthorizationDeclined => Self::Denied(err),
+            Oauth20AccessTokenErrorCode::ExpiredToken => Self::Expired(err),
+            _ => Self::Error(err),
+        }
+    }
+
+    /// Returns whether polling must stop.
+    pub fn is_terminal(&self) -> bool {
+        !matches!(self, Self::Pending | Self::SlowDown { .. })
+    }
+}
+
+/// The polling schedule of one device authorization.
+///
+/// Tracks the interval between polls, increased on `slow_down`, and the
+/// deadline past which the device code is dead, so the polling loop only
+/// sleeps [`Self::interval`] then feeds each response to [`Self::poll`].
+/// The clock is the caller's, in seconds: Unix epoch seconds do, though
+/// the seconds elapsed since a monotonic start are immune to wall-clock
+/// jumps.
+///
+/// Refs: <https://datatracker.ietf.org/doc/html/rfc8628#section-3.5>
+#[derive(Clone, Debug)]
+pub struct Oauth20DevicePolling {
+    deadline: u64,
+    interval: Duration,
+}
+
+impl Oauth20DevicePolling {
+    /// Starts the schedule of the device authorization issued at `now`.
+    pub fn new(device: &Oauth20DeviceAuthSuccessParams, now: u64) -> Self {
+        Self {
+            deadline: now.saturating_add(device.expires_in as u64),
+            interval: Duration::from_secs(device.interval as u64),
+        }
+    }
+
+    /// Returns the time to wait before the next poll.
+    pub fn interval(&self) -> Duration {
+        self.interval
+    }
+
+    /// Returns when the device code expires, on the caller's clock, in
+    /// seconds.
+    pub fn deadline(&self) -> u64 {
+        self.deadline
+    }
+
+    /// Returns whether the device code is expired at `now`.
+    pub fn is_expired(&self, now: u64) -> bool {
+        now >= self.deadline
+    }
+
+    /// Classifies the response of a poll received at `now`, then updates
+    /// the schedule.
+    ///
+    /// `slow_down` increases the interval of the next polls. A pending
+    /// authorization past the deadline turns into
+    /// [`Oauth20DeviceAccessTokenPoll::Expired`], with a synthetic
+    /// `expired_token` error, so polling stops even when the server
+    /// keeps answering `authorization_pending`.
+    pub fn poll(
+        &mut self,
+        response: Oauth20AccessTokenResponse,
+        now: u64,
+    ) -> Oauth20DeviceAccessTokenPoll {
+        let poll = Oauth20DeviceAccessTokenPoll::new(response, self.interval);
+
+        if let Oauth20DeviceAccessTokenPoll::SlowDown { interval } = &poll {
+            self.interval = *interval;
+        }
+
+        if poll.is_terminal() || !self.is_expired(now) {
+            return poll;
         }
+
+        debug!("device code expired while authorization is pending");
+
+        Oauth20DeviceAccessTokenPoll::Expired(Oauth20AccessTokenErrorParams {
+            error: Oauth20AccessTokenErrorCode::ExpiredToken,
+            error_description: Some("device code lifetime elapsed".into()),
+            error_uri: None,
+            retry_after: None,
+            client_auth_challenge: None,
+        })
     }
 }
 
@@ -199,7 +391,20 @@ pub struct Oauth20DeviceAccessTokenRequestParams<'a> {
     pub device_code: SecretString,
 }
 
+/// The device access token request params, owning every value.
+pub type Oauth20DeviceAccessTokenRequestParamsOwned =
+    Oauth20DeviceAccessTokenRequestParams<'static>;
+
 impl<'a> Oauth20DeviceAccessTokenRequestParams<'a> {
+    /// Converts every borrowed value into an owned one, so the params
+    /// outlive the strings they were built from.
+    pub fn into_owned(self) -> Oauth20DeviceAccessTokenRequestParams<'static> {
+        Oauth20DeviceAccessTokenRequestParams {
+            client_id: Cow::Owned(self.client_id.into_owned()),
+            device_code: self.device_code,
+        }
+    }
+
     /// Serializes the params into the form-urlencoded request body.
     // SAFETY: exposes the device code
     pub fn to_serializer(&self) -> Serializer<'a, String> {
@@ -218,3 +423,88 @@ impl fmt::Display for Oauth20DeviceAccessTokenRequestParams<'_> {
         f.write_str(&self.to_serializer().finish())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use core::time::Duration;
+
+    use crate::rfc8628::token::*;
+
+    fn poll(json: &str, retry_after: Option<u64>) -> Oauth20DeviceAccessTokenPoll {
+        let mut err: Oauth20AccessTokenErrorParams = serde_json::from_str(json).unwrap();
+        err.retry_after = retry_after.map(Duration::from_secs);
+        Oauth20DeviceAccessTokenPoll::new(Err(err), Duration::from_secs(5))
+    }
+
+    #[test]
+    fn poll_outcomes_are_classified() {
+        let pending = poll(r#"{"error":"authorization_pending"}"#, None);
+        assert!(matches!(pending, Oauth20DeviceAccessTokenPoll::Pending));
+        assert!(!pending.is_terminal());
+
+        let slow_down = poll(r#"{"error":"slow_down"}"#, None);
+        assert!(matches!(
+            slow_down,
+            Oauth20DeviceAccessTokenPoll::SlowDown { interval } if interval == Duration::from_secs(10)
+        ));
+        assert!(!slow_down.is_terminal());
+
+        let slow_down = poll(r#"{"error":"slow_down"}"#, Some(30));
+        assert!(matches!(
+            slow_down,
+            Oauth20DeviceAccessTokenPoll::SlowDown { interval } if interval == Duration::from_secs(30)
+        ));
+
+        let denied = poll(r#"{"error":"access_denied"}"#, None);
+        assert!(matches!(denied, Oauth20DeviceAccessTokenPoll::Denied(_)));
+        assert!(denied.is_terminal());
+
+        let declined = poll(r#"{"error":"authorization_declined"}"#, None);
+        assert!(matches!(declined, Oauth20DeviceAccessTokenPoll::Denied(_)));
+
+        let expired = poll(r#"{"error":"expired_token"}"#, None);
+        assert!(matches!(expired, Oauth20DeviceAccessTokenPoll::Expired(_)));
+
+        let other = poll(r#"{"error":"invalid_client"}"#, None);
+        assert!(matches!(other, Oauth20DeviceAccessTokenPoll::Error(_)));
+        assert!(other.is_terminal());
+
+        let success = serde_json::from_str(r#"{"access_token":"a","token_type":"Bearer"}"#);
+        let success = Oauth20DeviceAccessTokenPoll::new(Ok(success.unwrap()), Duration::ZERO);
+        assert!(matches!(success, Oauth20DeviceAccessTokenPoll::Success(_)));
+        assert!(success.is_terminal());
+    }
+
+    #[test]
+    fn polling_tracks_interval_and_deadline() {
+        let device: Oauth20DeviceAuthSuccessParams = serde_json::from_str(
+            r#"{"device_code":"d","user_code":"u","verification_uri":"https://a.com","expires_in":60}"#,
+        )
+        .unwrap();
+        let mut polling = Oauth20DevicePolling::new(&device, 1000);
+        assert_eq!(polling.interval(), Duration::from_secs(5));
+        assert_eq!(polling.deadline(), 1060);
+
+        let pending = || Err(serde_json::from_str(r#"{"error":"authorization_pending"}"#).unwrap());
+        let slow_down = Err(serde_json::from_str(r#"{"error":"slow_down"}"#).unwrap());
+
+        let poll = polling.poll(slow_down, 1005);
+        assert!(matches!(
+            poll,
+            Oauth20DeviceAccessTokenPoll::SlowDown { .. }
+        ));
+        assert_eq!(polling.interval(), Duration::from_secs(10));
+
+        let poll = polling.poll(pending(), 1015);
+        assert!(matches!(poll, Oauth20DeviceAccessTokenPoll::Pending));
+        assert!(!polling.is_expired(1059));
+
+        let poll = polling.poll(pending(), 1060);
+        assert!(matches!(
+            poll,
+            Oauth20DeviceAccessTokenPoll::Expired(ref err)
+                if err.error == Oauth20AccessTokenErrorCode::ExpiredToken
+        ));
+        assert!(poll.is_terminal());
+    }
+}
@@ -0,0 +1,483 @@
+//! Token exchange request (RFC 8693 section 2).
+//!
+//! Exchanges a subject token, optionally along an actor token, for a
+//! new token meant for the given resources and audiences, possibly
+//! with a narrower scope. `resource` and `audience` may both repeat;
+//! together with `requested_token_type`, they tell the server which
+//! token to issue and for whom.
+//!
+//! # Example
+//!
+//! ```rust,no_run
+//! use std::{
+//!     io::{Read, Write},
+//!     net::TcpStream,
+//! };
+//!
+//! use io_oauth::{rfc6749::endpoint::post_request, rfc8693::exchange::*};
+//! use url::Url;
+//!
+//! let token_url = Url::parse("https://example.com/token").unwrap();
+//! let request = post_request(&token_url);
+//!
+//! let mut params =
+//!     Oauth20TokenExchangeRequestParams::new("the-subject-token", OAUTH20_TOKEN_TYPE_ACCESS_TOKEN);
+//! params.audience.push("https://api.example.com".into());
+//!
+//! let mut stream = TcpStream::connect("example.com:443").unwrap();
+//! let mut coroutine = Oauth20TokenExchangeRequest::new(request, params);
+//! let mut arg: Option<&[u8]> = None;
+//! let mut buf = [0u8; 4096];
+//!
+//! let response = loop {
+//!     match coroutine.resume(arg.take()) {
+//!         Oauth20TokenExchangeRequestResult::Ok(res) => break res,
+//!         Oauth20TokenExchangeRequestResult::WantsRead => {
+//!             let n = stream.read(&mut buf).unwrap();
+//!             arg = Some(&buf[..n]);
+//!         }
+//!         Oauth20TokenExchangeRequestResult::WantsWrite(bytes) => {
+//!             stream.write_all(&bytes).unwrap();
+//!         }
+//!         Oauth20TokenExchangeRequestResult::Err(err) => panic!("{err}"),
+//!     }
+//! };
+//! # let _ = response;
+//! ```
+
+use core::fmt;
+
+use alloc::{borrow::Cow, collections::BTreeSet, string::String, vec::Vec};
+
+use io_http::{rfc9110::request::HttpRequest, rfc9112::send::Http11SendError};
+use secrecy::{ExposeSecret, SecretString};
+use thiserror::Error;
+use url::{Url, form_urlencoded::Serializer};
+
+use crate::rfc6749::{
+    issue_access_token::{
+        Oauth20AccessTokenResponse, Oauth20AccessTokenResponseError, Oauth20CoroutineState,
+        Oauth20RequestError, Oauth20TokenCoroutine, Oauth20TokenCoroutineError,
+        Oauth20TokenCoroutineResult, accept_json,
+    },
+    scope::{Oauth20ScopeDelimiter, into_owned_scope, join_scope},
+};
+
+/// The token type identifier of an OAuth 2.0 access token.
+///
+/// Refs: <https://datatracker.ietf.org/doc/html/rfc8693#section-3>
+pub const OAUTH20_TOKEN_TYPE_ACCESS_TOKEN: &str = "urn:ietf:params:oauth:token-type:access_token";
+
+/// The token type identifier of an OAuth 2.0 refresh token.
+///
+/// Refs: <https://datatracker.ietf.org/doc/html/rfc8693#section-3>
+pub const OAUTH20_TOKEN_TYPE_REFRESH_TOKEN: &str = "urn:ietf:params:oauth:token-type:refresh_token";
+
+/// The token type identifier of an OpenID Connect ID token.
+///
+/// Refs: <https://datatracker.ietf.org/doc/html/rfc8693#section-3>
+pub const OAUTH20_TOKEN_TYPE_ID_TOKEN: &str = "urn:ietf:params:oauth:token-type:id_token";
+
+/// The token type identifier of a JWT.
+///
+/// Refs: <https://datatracker.ietf.org/doc/html/rfc8693#section-3>
+pub const OAUTH20_TOKEN_TYPE_JWT: &str = "urn:ietf:params:oauth:token-type:jwt";
+
+/// A security token along its type identifier.
+///
+/// Refs: <https://datatracker.ietf.org/doc/html/rfc8693#section-2.1>
+#[derive(Clone, Debug)]
+pub struct Oauth20SecurityToken<'a> {
+    /// The token itself.
+    pub token: SecretString,
+    /// The token type identifier, one of the `OAUTH20_TOKEN_TYPE_*`
+    /// constants or a provider-specific URI.
+    pub token_type: Cow<'a, str>,
+}
+
+impl<'a> Oauth20SecurityToken<'a> {
+    /// Builds a security token of the given type.
+    pub fn new(token: impl Into<SecretString>, token_type: impl Into<Cow<'a, str>>) -> Self {
+        Self {
+            token: token.into(),
+            token_type: token_type.into(),
+        }
+    }
+
+    /// Converts the borrowed type identifier into an owned one.
+    pub fn into_owned(self) -> Oauth20SecurityToken<'static> {
+        Oauth20SecurityToken {
+            token: self.token,
+            token_type: Cow::Owned(self.token_type.into_owned()),
+        }
+    }
+}
+
+/// The token exchange request parameters.
+///
+/// Refs: <https://datatracker.ietf.org/doc/html/rfc8693#section-2.1>
+#[derive(Debug)]
+pub struct Oauth20TokenExchangeRequestParams<'a> {
+    /// The URIs of the services the issued token is meant for, each
+    /// sent as a `resource` parameter.
+    pub resource: Vec<Cow<'a, str>>,
+    /// The logical names of the services the issued token is meant
+    /// for, each sent as an `audience` parameter.
+    pub audience: Vec<Cow<'a, str>>,
+    /// The scope of the issued token, usually narrower than the
+    /// subject token's (downscoping).
+    ///
+    /// Refs: <https://datatracker.ietf.org/doc/html/rfc6749#section-3.3>
+    pub scope: BTreeSet<Cow<'a, str>>,
+    /// The separator between scope tokens, spaces unless the provider
+    /// expects commas.
+    pub scope_delimiter: Oauth20ScopeDelimiter,
+    /// The type of token to issue, left to the server when `None`.
+    pub requested_token_type: Option<Cow<'a, str>>,
+    /// The token representing the party the issued token acts for.
+    pub subject: Oauth20SecurityToken<'a>,
+    /// The token representing the acting party, for delegation.
+    pub actor: Option<Oauth20SecurityToken<'a>>,
+}
+
+/// The token exchange request params, owning every value.
+pub type Oauth20TokenExchangeRequestParamsOwned = Oauth20TokenExchangeRequestParams<'static>;
+
+impl<'a> Oauth20TokenExchangeRequestParams<'a> {
+    /// Builds params exchanging the given subject token, with no target,
+    /// scope, requested type nor actor.
+    pub fn new(
+        subject_token: impl Into<SecretString>,
+        subject_token_type: impl Into<Cow<'a, str>>,
+    ) -> Self {
+        Self {
+            resource: Vec::new(),
+            audience: Vec::new(),
+            scope: BTreeSet::new(),
+            scope_delimiter: Oauth20ScopeDelimiter::Space,
+            requested_token_type: None,
+            subject: Oauth20SecurityToken::new(subject_token, subject_token_type),
+            actor: None,
+        }
+    }
+
+    /// Converts every borrowed value into an owned one, so the params
+    /// outlive the strings they were built from.
+    pub fn into_owned(self) -> Oauth20TokenExchangeRequestParams<'static> {
+        let into_owned = |values: Vec<Cow<'_, str>>| {
+            values
+                .into_iter()
+                .map(|value| Cow::Owned(value.into_owned()))
+                .collect()
+        };
+
+        Oauth20TokenExchangeRequestParams {
+            resource: into_owned(self.resource),
+            audience: into_owned(self.audience),
+            scope: into_owned_scope(self.scope),
+            scope_delimiter: self.scope_delimiter,
+            requested_token_type: self
+                .requested_token_type
+                .map(|token_type| Cow::Owned(token_type.into_owned())),
+            subject: self.subject.into_owned(),
+            actor: self.actor.map(Oauth20SecurityToken::into_owned),
+        }
+    }
+
+    /// Serializes the params into the form-urlencoded request body.
+    ///
+    /// Fields follow the order of RFC 8693 section 2.1, repeated
+    /// `resource` and `audience` params in insertion order.
+    pub fn to_serializer(&self) -> Serializer<'a, String> {
+        let mut serializer = Serializer::new(String::new());
+
+        serializer.append_pair(
+            "grant_type",
+            "urn:ietf:params:oauth:grant-type:token-exchange",
+        );
+
+        for resource in &self.resource {
+            serializer.append_pair("resource", resource);
+        }
+
+        for audience in &self.audience {
+            serializer.append_pair("audience", audience);
+        }
+
+        let scope = join_scope(&self.scope, self.scope_delimiter);
+
+        if !scope.is_empty() {
+            serializer.append_pair("scope", &scope);
+        }
+
+        if let Some(token_type) = &self.requested_token_type {
+            serializer.append_pair("requested_token_type", token_type);
+        }
+
+        serializer.append_pair("subject_token", self.subject.token.expose_secret());
+        serializer.append_pair("subject_token_type", &self.subject.token_type);
+
+        if let Some(actor) = &self.actor {
+            serializer.append_pair("actor_token", actor.token.expose_secret());
+            serializer.append_pair("actor_token_type", &actor.token_type);
+        }
+
+        serializer
+    }
+}
+
+impl fmt::Display for Oauth20TokenExchangeRequestParams<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.to_serializer().finish())
+    }
+}
+
+/// Errors that can occur during the coroutine progression.
+#[derive(Debug, Error)]
+pub enum Oauth20TokenExchangeRequestError {
+    /// The HTTP request could not be sent.
+    #[error(transparent)]
+    SendHttpRequest(#[from] Http11SendError),
+    /// The HTTP response could not be parsed.
+    #[error(transparent)]
+    ParseHttpResponse(#[from] Oauth20AccessTokenResponseError),
+    /// The server answered with an unexpected redirection.
+    #[error("Unexpected redirection {code} to {url}")]
+    Redirect {
+        /// The redirection target URL.
+        url: Url,
+        /// The redirection HTTP status code.
+        code: u16,
+    },
+    /// The coroutine was cancelled by the caller.
+    #[error("OAuth 2.0 token exchange request cancelled")]
+    Cancelled,
+}
+
+/// Result returned by the coroutine's resume function.
+#[derive(Debug)]
+pub enum Oauth20TokenExchangeRequestResult {
+    /// The coroutine has successfully terminated its execution.
+    Ok(Oauth20AccessTokenResponse),
+    /// The coroutine wants the socket to be read into.
+    WantsRead,
+    /// The coroutine wants the given bytes to be written to the socket.
+    WantsWrite(Vec<u8>),
+    /// The coroutine encountered an error, along the identifier of
+    /// the request.
+    Err(Oauth20RequestError<Oauth20TokenExchangeRequestError>),
+}
+
+/// The I/O-free coroutine to exchange a security token for another one.
+///
+/// The response is a regular access token response: the issued token
+/// lands in `access_token` whatever its type, with its type in
+/// `issued_token_type`.
+///
+/// Refs: <https://datatracker.ietf.org/doc/html/rfc8693#section-2>
+pub struct Oauth20TokenExchangeRequest {
+    inner: Oauth20TokenCoroutine,
+}
+
+impl Oauth20TokenExchangeRequest {
+    /// Creates the coroutine to exchange the subject token of `body`.
+    pub fn new(request: HttpRequest, body: Oauth20TokenExchangeRequestParams<'_>) -> Self {
+        Self::from_request(Self::dry_run(request, body))
+    }
+
+    /// Creates the coroutine sending `request` as-is.
+    ///
+    /// Meant for a request built by [`Self::dry_run`] then adjusted, for
+    /// instance by [`Oauth20ClientAuthMethod::authenticate`].
+    ///
+    /// [`Oauth20ClientAuthMethod::authenticate`]: crate::rfc6749::client_auth::Oauth20ClientAuthMethod::authenticate
+    pub fn from_request(request: HttpRequest) -> Self {
+        Self {
+            inner: Oauth20TokenCoroutine::new("token exchange", request),
+        }
+    }
+
+    /// Creates the coroutine sending `request` as-is, like
+    /// [`Self::from_request`], keeping a copy of it for
+    /// [`Self::into_request`].
+    ///
+    /// The copy holds the body a second time, secrets included, for the
+    /// whole life of the coroutine; hence opt-in.
+    // SAFETY: the retained body keeps the params' secrets in memory
+    pub fn from_request_retained(request: HttpRequest) -> Self {
+        Self {
+            inner: Oauth20TokenCoroutine::retained("token exchange", request),
+        }
+    }
+
+    /// Returns the HTTP request the coroutine would send, without sending it.
+    ///
+    /// Meant for inspecting or replaying a request (with `curl`, say) when a
+    /// provider misbehaves. The `Debug` output of the request redacts its
+    /// body, but the body itself carries the form params in clear.
+    ///
+    /// Asks for a JSON response with `Accept: application/json`, unless
+    /// `request` already carries an `Accept` header.
+    // SAFETY: the returned body exposes the params' secrets
+    pub fn dry_run(
+        request: HttpRequest,
+        body: Oauth20TokenExchangeRequestParams<'_>,
+    ) -> HttpRequest {
+        accept_json(request)
+            .header("Content-Type", "application/x-www-form-urlencoded")
+            .body(body.to_serializer().finish().into_bytes())
+    }
+
+    /// Returns the identifier of the request, when the caller set one.
+    ///
+    /// Read from the [`OAUTH20_REQUEST_ID_HEADER`] of the request, it tags
+    /// the errors returned by [`Self::resume`].
+    ///
+    /// [`OAUTH20_REQUEST_ID_HEADER`]: crate::rfc6749::issue_access_token::OAUTH20_REQUEST_ID_HEADER
+    pub fn request_id(&self) -> Option<&str> {
+        self.inner.request_id()
+    }
+
+    /// Returns whether the server kept the connection alive for another
+    /// token request, `false` until [`Self::resume`] returned the response.
+    pub fn keep_alive(&self) -> bool {
+        self.inner.keep_alive()
+    }
+
+    /// Returns the progress of the coroutine, for diagnostics.
+    pub fn state(&self) -> Oauth20CoroutineState {
+        self.inner.state()
+    }
+
+    /// Returns the request the coroutine was created with, to retry
+    /// through a fresh [`Self::from_request`] once it failed.
+    ///
+    /// `None` unless the coroutine was created by
+    /// [`Self::from_request_retained`], the only one keeping it.
+    // SAFETY: the returned body exposes the params' secrets
+    pub fn into_request(self) -> Option<HttpRequest> {
+        self.inner.into_request()
+    }
+
+    /// Cancels the coroutine.
+    ///
+    /// Every later [`Self::resume`] returns a `Cancelled` error without
+    /// yielding any more I/O. The request may have been partially written, or
+    /// its response partially read: drop the stream rather than reusing it
+    /// for another request.
+    pub fn cancel(&mut self) {
+        self.inner.cancel()
+    }
+
+    /// Makes the coroutine progress.
+    pub fn resume(&mut self, arg: Option<&[u8]>) -> Oauth20TokenExchangeRequestResult {
+        match self.inner.resume(arg) {
+            Oauth20TokenCoroutineResult::Ok(res) => Oauth20TokenExchangeRequestResult::Ok(res),
+            Oauth20TokenCoroutineResult::WantsRead => Oauth20TokenExchangeRequestResult::WantsRead,
+            Oauth20TokenCoroutineResult::WantsWrite(bytes) => {
+                Oauth20TokenExchangeRequestResult::WantsWrite(bytes)
+            }
+            Oauth20TokenCoroutineResult::Err(err) => Oauth20TokenExchangeRequestResult::Err(err),
+        }
+    }
+}
+
+impl Oauth20TokenCoroutineError for Oauth20TokenExchangeRequestError {
+    fn redirect(url: Url, code: u16) -> Self {
+        Self::Redirect { url, code }
+    }
+
+    fn cancelled() -> Self {
+        Self::Cancelled
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::{format, string::ToString};
+
+    use crate::{
+        rfc6749::issue_access_token::Oauth20AccessTokenSuccessParams, rfc8693::exchange::*,
+    };
+
+    #[test]
+    fn repeated_targets_serialize_as_repeated_pairs() {
+        let mut params =
+            Oauth20TokenExchangeRequestParams::new("s", OAUTH20_TOKEN_TYPE_ACCESS_TOKEN);
+        params.resource = ["https://a.com/api", "https://b.com/api"]
+            .map(Cow::from)
+            .into();
+        params.audience = ["billing", "invoices"].map(Cow::from).into();
+        params.scope = ["read"].map(Cow::from).into();
+
+        assert_eq!(
+            params.to_string(),
+            "grant_type=urn%3Aietf%3Aparams%3Aoauth%3Agrant-type%3Atoken-exchange\
+             &resource=https%3A%2F%2Fa.com%2Fapi&resource=https%3A%2F%2Fb.com%2Fapi\
+             &audience=billing&audience=invoices&scope=read\
+             &subject_token=s\
+             &subject_token_type=urn%3Aietf%3Aparams%3Aoauth%3Atoken-type%3Aaccess_token"
+        );
+    }
+
+    #[test]
+    fn requested_token_type_combines_with_audience_and_actor() {
+        let mut params = Oauth20TokenExchangeRequestParams::new("id", OAUTH20_TOKEN_TYPE_ID_TOKEN);
+        params.audience.push("backend".into());
+        params.requested_token_type = Some(OAUTH20_TOKEN_TYPE_JWT.into());
+        params.actor = Some(Oauth20SecurityToken::new(
+            "act",
+            OAUTH20_TOKEN_TYPE_ACCESS_TOKEN,
+        ));
+
+        assert_eq!(
+            params.to_string(),
+            "grant_type=urn%3Aietf%3Aparams%3Aoauth%3Agrant-type%3Atoken-exchange\
+             &audience=backend\
+             &requested_token_type=urn%3Aietf%3Aparams%3Aoauth%3Atoken-type%3Ajwt\
+             &subject_token=id\
+             &subject_token_type=urn%3Aietf%3Aparams%3Aoauth%3Atoken-type%3Aid_token\
+             &actor_token=act\
+             &actor_token_type=urn%3Aietf%3Aparams%3Aoauth%3Atoken-type%3Aaccess_token"
+        );
+
+        let mut params =
+            Oauth20TokenExchangeRequestParams::new("r", OAUTH20_TOKEN_TYPE_REFRESH_TOKEN);
+        params.resource.push("https://a.com/api".into());
+        params.requested_token_type = Some(OAUTH20_TOKEN_TYPE_ACCESS_TOKEN.into());
+
+        assert_eq!(
+            params.into_owned().to_string(),
+            "grant_type=urn%3Aietf%3Aparams%3Aoauth%3Agrant-type%3Atoken-exchange\
+             &resource=https%3A%2F%2Fa.com%2Fapi\
+             &requested_token_type=urn%3Aietf%3Aparams%3Aoauth%3Atoken-type%3Aaccess_token\
+             &subject_token=r\
+             &subject_token_type=urn%3Aietf%3Aparams%3Aoauth%3Atoken-type%3Arefresh_token"
+        );
+    }
+
+    #[test]
+    fn response_carries_the_issued_token_type() {
+        let params = Oauth20AccessTokenSuccessParams::try_from(
+            br#"{"access_token":"a","token_type":"N_A","issued_token_type":"urn:ietf:params:oauth:token-type:jwt"}"#
+                .as_slice(),
+        )
+        .unwrap();
+        assert_eq!(
+            params.issued_token_type.as_deref(),
+            Some(OAUTH20_TOKEN_TYPE_JWT)
+        );
+    }
+
+    #[test]
+    fn params_debug_redacts_tokens() {
+        let mut params = Oauth20TokenExchangeRequestParams::new("s3cr3t-s", OAUTH20_TOKEN_TYPE_JWT);
+        params.actor = Some(Oauth20SecurityToken::new(
+            "s3cr3t-a",
+            OAUTH20_TOKEN_TYPE_JWT,
+        ));
+
+        let debug = format!("{params:?}");
+        assert!(!debug.contains("s3cr3t"));
+    }
+}
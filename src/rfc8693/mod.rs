@@ -0,0 +1,7 @@
+//! OAuth 2.0 Token Exchange (RFC 8693).
+//!
+//! Trades a security token for another one at the token endpoint:
+//! delegation and impersonation between services, or a narrower token
+//! for a downstream API.
+
+pub mod exchange;
@@ -0,0 +1,669 @@
+//! In-memory mock authorization server (`test-util` feature).
+//!
+//! Stands in for a real identity provider when testing code built on
+//! the coroutines: [`Oauth20MockServer::authorize`] answers the
+//! authorization URL with the redirect the user-agent would land on,
+//! and [`Oauth20MockServer::token`] answers the raw bytes of a token
+//! request with the raw bytes of an HTTP/1.1 response. Feed the
+//! bytes of `WantsWrite` to the latter, and its output back on the
+//! next `WantsRead`: no socket involved.
+//!
+//! The mock validates what a real server would on the authorization
+//! code grant: the client identifier, the redirection URI, the
+//! single use of the code and the PKCE verifier against the recorded
+//! challenge (RFC 7636 section 4.6), while echoing the state back.
+//!
+//! To drive a coroutine against canned bytes instead, without any
+//! server logic, [`Oauth20MemoryStream`] records what is written and
+//! serves a response built by [`http_response`], in chunks if asked.
+//!
+//! For snapshot tests, [`snapshot_access_token_response`] renders a
+//! whole token response, success or error, as canonical JSON with the
+//! tokens redacted, and [`parse_access_token_response_snapshot`] reads
+//! it back.
+
+use core::time::Duration;
+
+use alloc::{
+    format,
+    string::{String, ToString},
+    vec::Vec,
+};
+
+use serde_json::{Value, json};
+use thiserror::Error;
+use url::{Url, form_urlencoded};
+
+use crate::{
+    rfc6749::issue_access_token::{
+        Oauth20AccessTokenErrorParams, Oauth20AccessTokenResponse, Oauth20AccessTokenSuccessParams,
+    },
+    rfc7636::pkce::Oauth20PkceStoredChallenge,
+};
+
+/// The value standing in for tokens in snapshots.
+pub const REDACTED: &str = "[REDACTED]";
+
+/// Errors that can occur while the mock answers an authorization request.
+///
+/// Only the cases where the specification forbids redirecting back to
+/// the client end up here; the others are redirected as error params.
+///
+/// Refs: <https://datatracker.ietf.org/doc/html/rfc6749#section-4.1.2.1>
+#[derive(Debug, Error)]
+pub enum Oauth20MockServerError {
+    /// The client identifier is missing or unknown.
+    #[error("Unknown OAuth 2.0 client `{0}`")]
+    UnknownClient(String),
+    /// The redirection URI does not match the registered one.
+    #[error("Unregistered OAuth 2.0 redirection URI `{0}`")]
+    UnregisteredRedirectUri(String),
+}
+
+/// A pending authorization, recorded on authorize and consumed by the
+/// token request.
+#[derive(Clone, Debug)]
+struct Oauth20MockGrant {
+    code: String,
+    redirect_uri: Option<String>,
+    code_challenge: Option<(String, String)>,
+    scope: Option<String>,
+}
+
+/// The in-memory mock authorization server.
+#[derive(Clone, Debug)]
+pub struct Oauth20MockServer {
+    /// The only client identifier the mock knows.
+    pub client_id: String,
+    /// The registered redirection URI.
+    pub redirect_uri: Url,
+    /// The authorization code issued on the next authorization.
+    pub code: String,
+    /// The access token issued on a successful token request.
+    pub access_token: String,
+    /// The refresh token issued alongside, if any.
+    pub refresh_token: Option<String>,
+    /// The access token lifetime in seconds, if any.
+    pub expires_in: Option<usize>,
+    /// The canned error code answered to every token request, if any.
+    pub token_error: Option<String>,
+    grant: Option<Oauth20MockGrant>,
+}
+
+impl Oauth20MockServer {
+    /// Creates a mock knowing a single client and redirection URI.
+    pub fn new(client_id: impl ToString, redirect_uri: Url) -> Self {
+        Self {
+            client_id: client_id.to_string(),
+            redirect_uri,
+            code: "mock-authorization-code".into(),
+            access_token: "mock-access-token".into(),
+            refresh_token: None,
+            expires_in: Some(3600),
+            token_error: None,
+            grant: None,
+        }
+    }
+
+    /// Makes every token request fail with the given error code.
+    pub fn with_token_error(mut self, code: impl ToString) -> Self {
+        self.token_error = Some(code.to_string());
+        self
+    }
+
+    /// Answers an authorization URL with the redirection URL.
+    ///
+    /// Records the code challenge for the token request and echoes the
+    /// state back, on success as on error.
+    ///
+    /// Refs: <https://datatracker.ietf.org/doc/html/rfc6749#section-4.1.2>
+    pub fn authorize(&mut self, url: &Url) -> Result<Url, Oauth20MockServerError> {
+        let param = |name: &str| {
+            url.query_pairs()
+                .find(|(k, _)| k == name)
+                .map(|(_, v)| v.into_owned())
+        };
+
+        let client_id = param("client_id").unwrap_or_default();
+        if client_id != self.client_id {
+            return Err(Oauth20MockServerError::UnknownClient(client_id));
+        }
+
+        let redirect_uri = param("redirect_uri");
+        if let Some(uri) = &redirect_uri
+            && uri != self.redirect_uri.as_str()
+        {
+            return Err(Oauth20MockServerError::UnregisteredRedirectUri(uri.clone()));
+        }
+
+        let mut redirect = self.redirect_uri.clone();
+        let mut query = redirect.query_pairs_mut();
+
+        match param("response_type").as_deref() {
+            Some("code") => {
+                let code_challenge = param("code_challenge").map(|challenge| {
+                    let method = param("code_challenge_method").unwrap_or("plain".into());
+                    (method, challenge)
+                });
+
+                self.grant = Some(Oauth20MockGrant {
+                    code: self.code.clone(),
+                    redirect_uri,
+                    code_challenge,
+                    scope: param("scope"),
+                });
+
+                query.append_pair("code", &self.code);
+            }
+            Some(_) => {
+                query.append_pair("error", "unsupported_response_type");
+            }
+            None => {
+                query.append_pair("error", "invalid_request");
+            }
+        }
+
+        if let Some(state) = param("state") {
+            query.append_pair("state", &state);
+        }
+
+        drop(query);
+        Ok(redirect)
+    }
+
+    /// Answers the raw bytes of a token request with the raw bytes of the
+    /// HTTP response.
+    ///
+    /// Refs: <https://datatracker.ietf.org/doc/html/rfc6749#section-4.1.3>
+    pub fn token(&mut self, request: &[u8]) -> Vec<u8> {
+        let body = match request.windows(4).position(|w| w == b"\r\n\r\n") {
+            Some(n) => &request[n + 4..],
+            None => &[],
+        };
+
+        let param = |name: &str| {
+            form_urlencoded::parse(body)
+                .find(|(k, _)| k == name)
+                .map(|(_, v)| v.into_owned())
+        };
+
+        if let Some(code) = &self.token_error {
+            return error_response(400, code);
+        }
+
+        if param("client_id").as_deref() != Some(self.client_id.as_str()) {
+            return error_response(401, "invalid_client");
+        }
+
+        match param("grant_type").as_deref() {
+            Some("authorization_code") => (),
+            Some(_) => return error_response(400, "unsupported_grant_type"),
+            None => return error_response(400, "invalid_request"),
+        }
+
+        // NOTE: an authorization code is single-use
+        let Some(grant) = self.grant.take() else {
+            return error_response(400, "invalid_grant");
+        };
+
+        if param("code").as_deref() != Some(grant.code.as_str()) {
+            return error_response(400, "invalid_grant");
+        }
+
+        if grant.redirect_uri.is_some() && param("redirect_uri") != grant.redirect_uri {
+            return error_response(400, "invalid_grant");
+        }
+
+        let verifier = param("code_verifier");
+
+        match (&grant.code_challenge, verifier) {
+            (None, None) => (),
+            (Some((method, challenge)), Some(verifier)) => {
+                let Ok(challenge) = Oauth20PkceStoredChallenge::parse(challenge, Some(method))
+                else {
+                    return error_response(400, "invalid_request");
+                };
+
+                if !challenge.verify(verifier.as_bytes()) {
+                    return error_response(400, "invalid_grant");
+                }
+            }
+            _ => return error_response(400, "invalid_grant"),
+        }
+
+        let mut body = json!({
+            "access_token": self.access_token,
+            "token_type": "Bearer",
+        });
+
+        if let Some(expires_in) = self.expires_in {
+            body["expires_in"] = json!(expires_in);
+        }
+
+        if let Some(token) = &self.refresh_token {
+            body["refresh_token"] = json!(token);
+        }
+
+        if let Some(scope) = grant.scope {
+            body["scope"] = json!(scope);
+        }
+
+        response(200, &body)
+    }
+}
+
+/// An in-memory stream standing in for the socket.
+///
+/// Records the bytes of every `WantsWrite`, and serves the canned
+/// response on every `WantsRead`, at most `chunk_size` bytes at a
+/// time, then empty reads (end of stream) once exhausted.
+#[derive(Clone, Debug)]
+pub struct Oauth20MemoryStream {
+    /// The bytes written so far: the raw request.
+    pub written: Vec<u8>,
+    response: Vec<u8>,
+    position: usize,
+    chunk_size: usize,
+}
+
+impl Oauth20MemoryStream {
+    /// Creates a stream serving the given response in one read.
+    pub fn new(response: impl Into<Vec<u8>>) -> Self {
+        Self {
+            written: Vec::new(),
+            response: response.into(),
+            position: 0,
+            chunk_size: usize::MAX,
+        }
+    }
+
+    /// Serves the response `size` bytes at a time (one at least), to
+    /// exercise partial reads.
+    pub fn with_chunk_size(mut self, size: usize) -> Self {
+        self.chunk_size = size.max(1);
+        self
+    }
+
+    /// Records bytes the coroutine wants written.
+    pub fn write(&mut self, bytes: &[u8]) {
+        self.written.extend_from_slice(bytes);
+    }
+
+    /// Returns the next chunk of the response, empty once exhausted.
+    pub fn read(&mut self) -> &[u8] {
+        let start = self.position;
+        let end = start
+            .saturating_add(self.chunk_size)
+            .min(self.response.len());
+        self.position = end;
+        &self.response[start..end]
+    }
+}
+
+/// Builds the raw bytes of an HTTP/1.1 JSON response, as a token
+/// endpoint answers.
+pub fn http_response(status: u16, body: &str) -> Vec<u8> {
+    let reason = match status {
+        200 => "OK",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        429 => "Too Many Requests",
+        500 => "Internal Server Error",
+        503 => "Service Unavailable",
+        _ => "Unknown",
+    };
+
+    format!(
+        "HTTP/1.1 {status} {reason}\r\n\
+         Content-Type: application/json\r\n\
+         Cache-Control: no-store\r\n\
+         Content-Length: {}\r\n\
+         \r\n\
+         {body}",
+        body.len(),
+    )
+    .into_bytes()
+}
+
+/// Renders a token response as canonical JSON, `{"ok":{..}}` or
+/// `{"err":{..}}` with keys sorted, the access and refresh tokens
+/// replaced by [`REDACTED`].
+pub fn snapshot_access_token_response(response: &Oauth20AccessTokenResponse) -> String {
+    let snapshot = match response {
+        Ok(success) => {
+            let mut success = json!(success);
+
+            success["access_token"] = json!(REDACTED);
+
+            if success["refresh_token"].is_string() {
+                success["refresh_token"] = json!(REDACTED);
+            }
+
+            json!({ "ok": success })
+        }
+        Err(err) => json!({
+            "err": {
+                "error": err.error.as_str(),
+                "error_description": err.error_description,
+                "error_uri": err.error_uri,
+                "retry_after": err.retry_after.map(|after| after.as_secs()),
+            }
+        }),
+    };
+
+    snapshot.to_string()
+}
+
+/// Parses a snapshot rendered by [`snapshot_access_token_response`] back
+/// into a token response, tokens being [`REDACTED`].
+pub fn parse_access_token_response_snapshot(
+    snapshot: &str,
+) -> Result<Oauth20AccessTokenResponse, serde_json::Error> {
+    let mut snapshot: Value = serde_json::from_str(snapshot)?;
+
+    if let Some(success) = snapshot.get_mut("ok") {
+        let success: Oauth20AccessTokenSuccessParams = serde_json::from_value(success.take())?;
+        return Ok(Ok(success));
+    }
+
+    let err = snapshot["err"].take();
+    let retry_after = err["retry_after"].as_u64().map(Duration::from_secs);
+    let mut err: Oauth20AccessTokenErrorParams = serde_json::from_value(err)?;
+    err.retry_after = retry_after;
+    Ok(Err(err))
+}
+
+fn error_response(status: u16, code: &str) -> Vec<u8> {
+    response(status, &json!({ "error": code }))
+}
+
+fn response(status: u16, body: &Value) -> Vec<u8> {
+    http_response(status, &body.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::{
+        borrow::Cow, collections::BTreeMap, collections::BTreeSet, string::String, vec::Vec,
+    };
+    use core::str::FromStr;
+
+    use io_http::rfc9110::request::HttpRequest;
+    use secrecy::ExposeSecret;
+    use url::Url;
+
+    use crate::{
+        rfc6749::{
+            access_token_request::*,
+            auth_request::Oauth20AuthRequestParams,
+            auth_response::Oauth20AuthParams,
+            endpoint::post_request,
+            issue_access_token::{
+                Oauth20AccessTokenErrorCode, Oauth20AccessTokenResponse, Oauth20RequestError,
+            },
+            refresh_access_token::*,
+            state::Oauth20State,
+        },
+        rfc7636::pkce::*,
+        test_util::*,
+    };
+
+    fn mock() -> Oauth20MockServer {
+        let redirect_uri = Url::parse("http://127.0.0.1:8080/callback").unwrap();
+        Oauth20MockServer::new("client-id", redirect_uri)
+    }
+
+    fn verifier() -> Oauth20PkceCodeVerifier {
+        Oauth20PkceCodeVerifier::from_str("dBjftJeZ4CVP-mB92K27uhbUJU1p1r_wW1gFWFOEjXk").unwrap()
+    }
+
+    fn authorize(server: &mut Oauth20MockServer, state: &Oauth20State) -> Cow<'static, str> {
+        let params = Oauth20AuthRequestParams {
+            client_id: "client-id".into(),
+            redirect_uri: "http://127.0.0.1:8080/callback".parse().ok(),
+            scope: BTreeSet::from(["openid".into()]),
+            scope_delimiter: Default::default(),
+            state: Some(Cow::Borrowed(state)),
+            pkce_code_challenge: Some(Cow::Owned(Oauth20PkceCodeChallenge {
+                method: Oauth20PkceCodeChallengeMethod::Sha256,
+                verifier: verifier(),
+            })),
+            extras: BTreeMap::new(),
+        };
+
+        let endpoint = Url::parse("http://127.0.0.1:8080/authorize").unwrap();
+        let redirect = server
+            .authorize(&params.try_build_url(&endpoint).unwrap())
+            .unwrap();
+        let code = Oauth20AuthParams::from(&redirect)
+            .validate(Some(state))
+            .unwrap();
+
+        Cow::Owned(code.into_owned())
+    }
+
+    fn exchange(
+        server: &mut Oauth20MockServer,
+        code: Cow<'_, str>,
+        verifier: Option<Oauth20PkceCodeVerifier>,
+    ) -> Oauth20AccessTokenResponse {
+        let url = Url::parse("http://127.0.0.1:8080/token").unwrap();
+        let request = HttpRequest {
+            method: "POST".into(),
+            url,
+            headers: Vec::new(),
+            body: Vec::new(),
+        }
+        .header("Host", "127.0.0.1:8080");
+
+        let params = Oauth20AccessTokenRequestParams {
+            code,
+            redirect_uri: "http://127.0.0.1:8080/callback".parse().ok(),
+            client_id: "client-id".into(),
+            client_secret: None,
+            pkce_code_verifier: verifier.map(Cow::Owned),
+        };
+
+        let mut coroutine = Oauth20AccessTokenRequest::new(request, params);
+        let mut response = Vec::new();
+        let mut arg: Option<Vec<u8>> = None;
+
+        loop {
+            match coroutine.resume(arg.take().as_deref()) {
+                Oauth20AccessTokenRequestResult::Ok(res) => break res,
+                Oauth20AccessTokenRequestResult::WantsRead => {
+                    arg = Some(core::mem::take(&mut response));
+                }
+                Oauth20AccessTokenRequestResult::WantsWrite(bytes) => {
+                    response = server.token(&bytes);
+                }
+                Oauth20AccessTokenRequestResult::Err(err) => panic!("{err}"),
+            }
+        }
+    }
+
+    #[test]
+    fn mock_server_completes_pkce_grant() {
+        let mut server = mock();
+        let state: Oauth20State = serde_json::from_str("\"xyz\"").unwrap();
+
+        let code = authorize(&mut server, &state);
+        let res = exchange(&mut server, code.clone(), Some(verifier())).unwrap();
+        assert_eq!(res.access_token.expose_secret(), "mock-access-token");
+        assert_eq!(res.scope.as_deref(), Some("openid"));
+
+        // the code is single-use
+        let err = exchange(&mut server, code, Some(verifier())).unwrap_err();
+        assert_eq!(err.error, Oauth20AccessTokenErrorCode::InvalidGrant);
+    }
+
+    #[test]
+    fn mock_server_rejects_wrong_verifier() {
+        let mut server = mock();
+        let state: Oauth20State = serde_json::from_str("\"xyz\"").unwrap();
+        let wrong = Oauth20PkceCodeVerifier::from_str(&"a".repeat(43)).unwrap();
+
+        let code = authorize(&mut server, &state);
+        let err = exchange(&mut server, code, Some(wrong)).unwrap_err();
+        assert_eq!(err.error, Oauth20AccessTokenErrorCode::InvalidGrant);
+
+        let code = authorize(&mut server, &state);
+        let err = exchange(&mut server, code, None).unwrap_err();
+        assert_eq!(err.error, Oauth20AccessTokenErrorCode::InvalidGrant);
+    }
+
+    #[test]
+    fn mock_server_answers_canned_error() {
+        let mut server = mock().with_token_error("invalid_client");
+        let state: Oauth20State = serde_json::from_str("\"xyz\"").unwrap();
+
+        let code = authorize(&mut server, &state);
+        let err = exchange(&mut server, code, Some(verifier())).unwrap_err();
+        assert_eq!(err.error, Oauth20AccessTokenErrorCode::InvalidClient);
+    }
+
+    #[cfg(feature = "client")]
+    #[test]
+    fn token_exchange_logs_no_secret() {
+        use alloc::{format, string::String};
+        use std::sync::Mutex;
+
+        use log::{LevelFilter, Log, Metadata, Record};
+
+        static LOGS: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+        struct Capture;
+
+        impl Log for Capture {
+            fn enabled(&self, _: &Metadata) -> bool {
+                true
+            }
+
+            fn log(&self, record: &Record) {
+                LOGS.lock().unwrap().push(format!("{}", record.args()));
+            }
+
+            fn flush(&self) {}
+        }
+
+        log::set_logger(&Capture).unwrap();
+        log::set_max_level(LevelFilter::Trace);
+
+        let mut server = mock();
+        server.refresh_token = Some(String::from("refresh-secret"));
+
+        let state: Oauth20State = serde_json::from_str("\"state-secret\"").unwrap();
+        let code = authorize(&mut server, &state);
+        let res = exchange(&mut server, code, Some(verifier())).unwrap();
+
+        let secrets = [
+            String::from("state-secret"),
+            server.code.clone(),
+            String::from_utf8(verifier().expose().to_vec()).unwrap(),
+            String::from(res.access_token.expose_secret()),
+            String::from(res.refresh_token.unwrap().expose_secret()),
+        ];
+
+        let logs = LOGS.lock().unwrap();
+        assert!(!logs.is_empty());
+
+        for line in logs.iter() {
+            for secret in &secrets {
+                assert!(!line.contains(secret.as_str()), "secret logged: {line}");
+            }
+        }
+    }
+
+    fn refresh(stream: &mut Oauth20MemoryStream) -> Oauth20AccessTokenRefreshResult {
+        let url = Url::parse("http://127.0.0.1:8080/token").unwrap();
+        let params = Oauth20AccessTokenRefreshParams::new("client-id", "refresh-token");
+        let mut coroutine = Oauth20AccessTokenRefresh::new(post_request(&url), params);
+        let mut read = false;
+
+        loop {
+            let arg = if read { Some(stream.read()) } else { None };
+
+            match coroutine.resume(arg) {
+                Oauth20AccessTokenRefreshResult::WantsRead => read = true,
+                Oauth20AccessTokenRefreshResult::WantsWrite(bytes) => {
+                    read = false;
+                    stream.write(&bytes);
+                }
+                result => break result,
+            }
+        }
+    }
+
+    #[test]
+    fn memory_stream_drives_a_success() {
+        let body = r#"{"access_token":"a","token_type":"Bearer","expires_in":60}"#;
+        let mut stream = Oauth20MemoryStream::new(http_response(200, body)).with_chunk_size(7);
+
+        let Oauth20AccessTokenRefreshResult::Ok(Ok(res)) = refresh(&mut stream) else {
+            panic!("expected a successful refresh");
+        };
+        assert_eq!(res.access_token.expose_secret(), "a");
+        assert_eq!(res.expires_in, Some(60));
+
+        let request = String::from_utf8(stream.written).unwrap();
+        assert!(request.starts_with("POST /token HTTP/1.1\r\n"), "{request}");
+        assert!(
+            request.ends_with("refresh_token=refresh-token"),
+            "{request}"
+        );
+    }
+
+    #[test]
+    fn memory_stream_drives_an_error() {
+        let body = r#"{"error":"invalid_grant"}"#;
+        let mut stream = Oauth20MemoryStream::new(http_response(400, body));
+
+        let Oauth20AccessTokenRefreshResult::Ok(Err(err)) = refresh(&mut stream) else {
+            panic!("expected an error response");
+        };
+        assert_eq!(err.error, Oauth20AccessTokenErrorCode::InvalidGrant);
+    }
+
+    #[test]
+    fn memory_stream_drives_a_malformed_body() {
+        let mut stream = Oauth20MemoryStream::new(http_response(200, "{not json"));
+
+        assert!(matches!(
+            refresh(&mut stream),
+            Oauth20AccessTokenRefreshResult::Err(Oauth20RequestError {
+                error: Oauth20AccessTokenRefreshError::ParseHttpResponse(_),
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn snapshots_redact_tokens_and_round_trip() {
+        let success: Oauth20AccessTokenSuccessParams = serde_json::from_str(
+            r#"{"access_token":"a","token_type":"Bearer","refresh_token":"r","expires_in":60}"#,
+        )
+        .unwrap();
+
+        let snapshot = snapshot_access_token_response(&Ok(success));
+        assert_eq!(
+            snapshot,
+            r#"{"ok":{"access_token":"[REDACTED]","expires_in":60,"issued_at":null,"refresh_token":"[REDACTED]","scope":null,"token_type":"Bearer"}}"#
+        );
+
+        let parsed = parse_access_token_response_snapshot(&snapshot).unwrap();
+        assert_eq!(snapshot_access_token_response(&parsed), snapshot);
+
+        let mut err: Oauth20AccessTokenErrorParams =
+            serde_json::from_str(r#"{"error":"slow_down"}"#).unwrap();
+        err.retry_after = Some(core::time::Duration::from_secs(10));
+
+        let snapshot = snapshot_access_token_response(&Err(err));
+        assert_eq!(
+            snapshot,
+            r#"{"err":{"error":"slow_down","error_description":null,"error_uri":null,"retry_after":10}}"#
+        );
+
+        let parsed = parse_access_token_response_snapshot(&snapshot).unwrap();
+        assert_eq!(snapshot_access_token_response(&parsed), snapshot);
+    }
+}